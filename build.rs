@@ -0,0 +1,46 @@
+//! Converts the bundled star catalog from galactic to equatorial coordinates ahead of time, so that
+//! it can be embedded in the binary in exactly the layout the GPU expects.
+//!
+//! Each record of `stars.bin` is four little endian `f32`s: galactic latitude and longitude in
+//! radians, visual magnitude, and B-V color index.
+
+use std::{env, fs, path::Path};
+
+#[allow(dead_code)]
+#[path = "src/astro.rs"]
+mod astro;
+
+fn main() {
+    println!("cargo:rerun-if-changed=stars.bin");
+    println!("cargo:rerun-if-changed=src/astro.rs");
+    if env::var_os("CARGO_FEATURE_BSC").is_none() {
+        return;
+    }
+
+    let big_endian = env::var("CARGO_CFG_TARGET_ENDIAN").as_deref() == Ok("big");
+    let input = fs::read("stars.bin").expect("failed to read stars.bin");
+
+    let mut output = Vec::with_capacity(input.len());
+    for chunk in input.chunks_exact(16) {
+        let [gal_lat, gal_long, magnitude, color_index] =
+            [0, 4, 8, 12].map(|i| f32::from_le_bytes(chunk[i..i + 4].try_into().unwrap()));
+        let (gal_lat, gal_long) = (gal_lat as f64, gal_long as f64);
+        let star = [
+            astro::dec_frm_gal(gal_long, gal_lat) as f32,
+            astro::asc_frm_gal(gal_long, gal_lat) as f32,
+            magnitude,
+            color_index,
+        ];
+        for value in star {
+            output.extend_from_slice(&if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            });
+        }
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("stars_equatorial.bin"), output)
+        .expect("failed to write converted star catalog");
+}
@@ -6,7 +6,7 @@ use bevy_starfield::StarfieldPlugin;
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
-        .add_plugin(StarfieldPlugin)
+        .add_plugin(StarfieldPlugin::default())
         .add_startup_system(setup)
         .run();
 }
@@ -0,0 +1,184 @@
+//! Star catalogs loaded as assets, which can be shared between starfields and hot reloaded.
+
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, HandleId, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashSet},
+};
+
+use crate::{catalog::stars_from_le_bytes, Star, StarfieldSource};
+
+/// A list of stars loaded from a file by [`StarCatalogLoader`].
+///
+/// Add a `Handle<StarCatalog>` to a starfield to show the catalog's stars. The starfield is
+/// rebuilt once the catalog loads and again whenever it is modified, for instance by hot reloading.
+#[derive(Clone, Debug, TypeUuid)]
+#[uuid = "4b648b82-ccbf-4f54-8d0a-047b7c5f0acc"]
+pub struct StarCatalog {
+    /// The stars in the catalog.
+    pub stars: Vec<Star>,
+}
+
+/// Loads [`StarCatalog`]s from `.stars` and `.stars.csv` files.
+///
+/// A `.stars` file holds one [`Star`] after another as little-endian `f32` declination, right
+/// ascension, magnitude and color index, for 16 bytes per star. A `.stars.csv` file has one star
+/// per line, written as right ascension and declination in degrees followed by the magnitude and
+/// an optional B-V color index. Empty lines and those starting with `#` are skipped.
+#[derive(Default)]
+pub struct StarCatalogLoader;
+impl AssetLoader for StarCatalogLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let is_csv = load_context.path().extension().and_then(|e| e.to_str()) == Some("csv");
+            let stars = if is_csv {
+                parse_star_csv(std::str::from_utf8(bytes)?)?
+            } else {
+                stars_from_le_bytes(bytes).ok_or(StarCatalogError::InvalidSize)?
+            };
+            load_context.set_default_asset(LoadedAsset::new(StarCatalog { stars }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stars", "stars.csv"]
+    }
+}
+
+/// Error returned when a star catalog file can't be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StarCatalogError {
+    /// A binary catalog's size wasn't a multiple of 16 bytes.
+    InvalidSize,
+    /// A line of a CSV catalog didn't hold three or four numbers. Line numbers start at one.
+    InvalidLine(usize),
+}
+impl fmt::Display for StarCatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize => write!(f, "star catalog size is not a multiple of 16 bytes"),
+            Self::InvalidLine(line) => write!(f, "invalid star on line {line} of star catalog"),
+        }
+    }
+}
+impl std::error::Error for StarCatalogError {}
+
+fn parse_star_csv(text: &str) -> Result<Vec<Star>, StarCatalogError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            let invalid = StarCatalogError::InvalidLine(i + 1);
+            let values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid.clone())?;
+            let (right_ascension, declination, magnitude, color_index) = match values[..] {
+                [ra, dec, magnitude] => (ra, dec, magnitude, 0.0),
+                [ra, dec, magnitude, color_index] => (ra, dec, magnitude, color_index),
+                _ => return Err(invalid),
+            };
+            Ok(Star::new(
+                declination.to_radians(),
+                right_ascension.to_radians(),
+                magnitude,
+            )
+            .with_color_index(color_index))
+        })
+        .collect()
+}
+
+pub(crate) fn apply_star_catalogs(
+    mut commands: Commands,
+    catalogs: Res<Assets<StarCatalog>>,
+    mut events: EventReader<AssetEvent<StarCatalog>>,
+    starfields: Query<(Entity, Ref<Handle<StarCatalog>>)>,
+) {
+    let updated: HashSet<HandleId> = events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => Some(handle.id()),
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+
+    for (entity, handle) in starfields.iter() {
+        if !handle.is_changed() && !updated.contains(&handle.id()) {
+            continue;
+        }
+        // Catalogs that are still loading are picked up by their `Created` event.
+        if let Some(catalog) = catalogs.get(&*handle) {
+            let source = StarfieldSource::Stars {
+                stars: catalog.stars.clone(),
+                ids: None,
+            };
+            commands.entity(entity).insert((source.build(), source));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_csv_rows() {
+        let text = [
+            "# right ascension, declination, magnitude, B-V",
+            "",
+            "90, 45, 1.5",
+            "   ",
+            "  # indented comment",
+            "180,-30,  -0.5, 0.65",
+        ]
+        .join("\n");
+        let stars = parse_star_csv(&text).unwrap();
+        assert_eq!(
+            stars,
+            vec![
+                Star::new(45f32.to_radians(), 90f32.to_radians(), 1.5),
+                Star::new((-30f32).to_radians(), 180f32.to_radians(), -0.5).with_color_index(0.65),
+            ]
+        );
+        assert_eq!(parse_star_csv(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn invalid_lines_are_numbered_from_one() {
+        // Skipped lines still count towards the line number.
+        let text = "# comment\n\n1, 2, 3\n1, 2\n";
+        assert_eq!(parse_star_csv(text), Err(StarCatalogError::InvalidLine(4)));
+        assert_eq!(
+            parse_star_csv("1, 2, 3, 4, 5"),
+            Err(StarCatalogError::InvalidLine(1))
+        );
+        assert_eq!(
+            parse_star_csv("1, 2, 3\n1, two, 3"),
+            Err(StarCatalogError::InvalidLine(2))
+        );
+    }
+
+    #[test]
+    fn binary_size_must_be_a_multiple_of_16() {
+        let star = Star::new(0.5, 1.0, 2.0).with_color_index(0.3);
+        let bytes: Vec<u8> = [0.5f32, 1.0, 2.0, 0.3]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        assert_eq!(stars_from_le_bytes(&bytes), Some(vec![star]));
+        assert_eq!(stars_from_le_bytes(&bytes[..15]), None);
+        assert_eq!(
+            stars_from_le_bytes(&[bytes.clone(), vec![0; 4]].concat()),
+            None
+        );
+    }
+}
@@ -119,6 +119,7 @@ pub(crate) fn dec_frm_ecl(ecl_long: f64, ecl_lat: f64, oblq_eclip: f64) -> f64 {
 ///
 /// * `gal_long`: Galactic longitude *| in radians*
 /// * `gal_lat`: Galactic latitude *| in radians*
+#[allow(unused)]
 pub(crate) fn asc_frm_gal(gal_long: f64, gal_lat: f64) -> f64 {
     12.25_f64.to_radians()
         + (gal_long - 123_f64.to_radians()).sin().atan2(
@@ -140,6 +141,7 @@ pub(crate) fn asc_frm_gal(gal_long: f64, gal_lat: f64) -> f64 {
 ///
 /// * `gal_long`: Galactic longitude *| in radians*
 /// * `gal_lat`: Galactic latitude *| in radians*
+#[allow(unused)]
 pub(crate) fn dec_frm_gal(gal_long: f64, gal_lat: f64) -> f64 {
     (gal_lat.sin() * 27.4_f64.to_radians().sin()
         + gal_lat.cos() * 27.4_f64.to_radians().cos() * (gal_long - 123_f64.to_radians()).cos())
@@ -0,0 +1,445 @@
+//! Rendering starfields into images on the CPU, for use as skyboxes and environment maps.
+
+use std::f32::consts::{PI, TAU};
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{
+        Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+    },
+};
+
+use crate::{star_color, StarSize, Starfield, StarfieldSettings};
+
+/// Options controlling how a starfield is baked into an image.
+#[derive(Clone, Debug)]
+pub struct BakeOptions {
+    /// Width in pixels of the output image.
+    ///
+    /// Defaults to 4096.
+    pub resolution: u32,
+    /// Number of samples taken along each axis of every output pixel.
+    ///
+    /// Averaging 2-4 samples along each axis smooths the edges of stars a few pixels across, at the
+    /// cost of bake time but not memory. Stars smaller than a pixel are spread over the nearest
+    /// pixels with their full brightness regardless. Defaults to 2.
+    pub supersampling: u32,
+    /// Rotation from equatorial directions to the frame of the baked image. Use
+    /// [`GameUnitsToCelestial::celestial_to_world`](crate::GameUnitsToCelestial::celestial_to_world)
+    /// to match the sky as currently drawn.
+    ///
+    /// Defaults to the identity.
+    pub celestial_to_world: Mat3,
+}
+impl Default for BakeOptions {
+    fn default() -> Self {
+        Self {
+            resolution: 4096,
+            supersampling: 2,
+            celestial_to_world: Mat3::IDENTITY,
+        }
+    }
+}
+
+/// Render a starfield to an equirectangular panorama twice as wide as it is tall.
+///
+/// The image uses the same layout as
+/// [`CloudCoverMapping::Equirectangular`](crate::CloudCoverMapping::Equirectangular), with -Z at
+/// the center and the zenith along the top edge. Star sizes given in pixels are interpreted as
+/// pixels of the output image.
+pub fn bake_equirectangular(
+    starfield: &Starfield,
+    settings: &StarfieldSettings,
+    options: &BakeOptions,
+) -> Image {
+    let width = options.resolution.max(2);
+    let height = width / 2;
+    let projection = Equirectangular { width, height };
+    let mut canvas = Canvas::new(width, height);
+    canvas.draw(&projection, starfield, settings, options);
+    encode_image(width, height, 1, canvas.values)
+}
+
+/// Render a starfield to a cubemap.
+///
+/// Each face is `resolution / 4` pixels wide, giving the same angular resolution as an
+/// equirectangular image baked with the same options. Faces are laid out so that the image can be
+/// used directly as an environment map.
+pub fn bake_cubemap(
+    starfield: &Starfield,
+    settings: &StarfieldSettings,
+    options: &BakeOptions,
+) -> Image {
+    bake_cubemap_layers(&[(starfield, settings)], options)
+}
+
+/// Render several starfields into the same cubemap.
+pub(crate) fn bake_cubemap_layers(
+    starfields: &[(&Starfield, &StarfieldSettings)],
+    options: &BakeOptions,
+) -> Image {
+    let (size, values) = bake_cube_faces(starfields, options);
+    cubemap_image(size, &values, 1)
+}
+
+/// Render several starfields into the six faces of a cubemap, returning the width of each face
+/// and the linear color of every pixel.
+pub(crate) fn bake_cube_faces(
+    starfields: &[(&Starfield, &StarfieldSettings)],
+    options: &BakeOptions,
+) -> (u32, Vec<Vec3>) {
+    let size = (options.resolution / 4).max(1);
+    let mut data = Vec::new();
+    for (forward, right, up) in CubeFace::ORIENTATIONS {
+        let projection = CubeFace {
+            size,
+            forward,
+            right,
+            up,
+        };
+        let mut canvas = Canvas::new(size, size);
+        for (starfield, settings) in starfields {
+            canvas.draw(&projection, starfield, settings, options);
+        }
+        data.extend(canvas.values);
+    }
+    (size, data)
+}
+
+/// Shrink each face of a cubemap to `new_size` pixels wide.
+pub(crate) fn shrink_cube_faces(size: u32, values: &[Vec3], new_size: u32) -> Vec<Vec3> {
+    values
+        .chunks_exact((size * size) as usize)
+        .flat_map(|face| shrink_face(size, face, new_size))
+        .collect()
+}
+
+/// Shrink a square image to `new_size` pixels wide, averaging the pixels each new one covers.
+fn shrink_face(size: u32, face: &[Vec3], new_size: u32) -> Vec<Vec3> {
+    let (size, new_size) = (size as usize, new_size.clamp(1, size) as usize);
+    // Pixels of the original covered by pixel `i` of the shrunk image along either axis.
+    let span =
+        |i: usize| i * size / new_size..((i + 1) * size / new_size).max(i * size / new_size + 1);
+    let mut output = Vec::with_capacity(new_size * new_size);
+    for y in 0..new_size {
+        let rows = span(y);
+        for x in 0..new_size {
+            let columns = span(x);
+            let mut sum = Vec3::ZERO;
+            for row in rows.clone() {
+                sum = face[row * size..][columns.clone()]
+                    .iter()
+                    .fold(sum, |sum, value| sum + *value);
+            }
+            output.push(sum / (rows.len() * columns.len()) as f32);
+        }
+    }
+    output
+}
+
+/// Cubemap image from the pixels of its faces, with `mip_levels` levels each half the size of the
+/// one before.
+pub(crate) fn cubemap_image(size: u32, values: &[Vec3], mip_levels: u32) -> Image {
+    let faces: Vec<_> = values.chunks_exact((size * size) as usize).collect();
+    let mut image = encode_image(size, size, 6, values.to_vec());
+    if mip_levels > 1 {
+        // Textures are uploaded one layer at a time, with all the mip levels of each layer.
+        let mut data = Vec::new();
+        for face in faces {
+            let mut level = face.to_vec();
+            let mut level_size = size;
+            for _ in 0..mip_levels {
+                data.extend(encode_values(&level));
+                let next_size = (level_size / 2).max(1);
+                level = shrink_face(level_size, &level, next_size);
+                level_size = next_size;
+            }
+        }
+        image.data = data;
+        image.texture_descriptor.mip_level_count = mip_levels;
+    }
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..default()
+    });
+    image
+}
+
+/// Mapping between directions and pixels of a baked image.
+trait Projection {
+    /// Pixel coordinates of a direction, or `None` if it isn't covered by the image.
+    fn project(&self, direction: Vec3) -> Option<Vec2>;
+    /// Direction through the given pixel coordinates.
+    fn unproject(&self, pixel: Vec2) -> Vec3;
+    /// Approximate number of pixels spanned by one radian at `direction`, horizontally and
+    /// vertically.
+    fn pixels_per_radian(&self, direction: Vec3) -> Vec2;
+    /// Index of the pixel at the given coordinates, wrapping them if the image wraps around.
+    fn index(&self, x: i32, y: i32) -> Option<usize>;
+}
+
+struct Equirectangular {
+    width: u32,
+    height: u32,
+}
+impl Projection for Equirectangular {
+    fn project(&self, direction: Vec3) -> Option<Vec2> {
+        let u = direction.x.atan2(-direction.z) / TAU + 0.5;
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        Some(Vec2::new(u * self.width as f32, v * self.height as f32))
+    }
+    fn unproject(&self, pixel: Vec2) -> Vec3 {
+        let longitude = (pixel.x / self.width as f32 - 0.5) * TAU;
+        let polar = pixel.y / self.height as f32 * PI;
+        Vec3::new(
+            polar.sin() * longitude.sin(),
+            polar.cos(),
+            -polar.sin() * longitude.cos(),
+        )
+    }
+    fn pixels_per_radian(&self, direction: Vec3) -> Vec2 {
+        let vertical = self.height as f32 / PI;
+        let cos_latitude = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+        Vec2::new(
+            (vertical / cos_latitude.max(1e-3)).min(self.width as f32),
+            vertical,
+        )
+    }
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if y < 0 || y >= self.height as i32 {
+            return None;
+        }
+        let x = x.rem_euclid(self.width as i32);
+        Some(y as usize * self.width as usize + x as usize)
+    }
+}
+
+/// One face of a cubemap.
+///
+/// Cubemaps are sampled with a left-handed coordinate system, so directions have their Z
+/// component flipped before being mapped to a face.
+struct CubeFace {
+    size: u32,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+}
+impl CubeFace {
+    /// Forward, right and up vectors of each face, in the order +X, -X, +Y, -Y, +Z, -Z.
+    const ORIENTATIONS: [(Vec3, Vec3, Vec3); 6] = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+}
+impl Projection for CubeFace {
+    fn project(&self, direction: Vec3) -> Option<Vec2> {
+        let direction = direction * Vec3::new(1.0, 1.0, -1.0);
+        let depth = direction.dot(self.forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        let plane = direction / depth;
+        Some(
+            Vec2::new(1.0 + plane.dot(self.right), 1.0 - plane.dot(self.up))
+                * (0.5 * self.size as f32),
+        )
+    }
+    fn unproject(&self, pixel: Vec2) -> Vec3 {
+        let plane = pixel / (0.5 * self.size as f32) - Vec2::ONE;
+        let direction = self.forward + self.right * plane.x - self.up * plane.y;
+        direction.normalize() * Vec3::new(1.0, 1.0, -1.0)
+    }
+    fn pixels_per_radian(&self, direction: Vec3) -> Vec2 {
+        let depth = (direction * Vec3::new(1.0, 1.0, -1.0)).dot(self.forward);
+        Vec2::splat(0.5 * self.size as f32 / depth.max(0.1).powi(2))
+    }
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.size as i32 || y >= self.size as i32 {
+            return None;
+        }
+        Some(y as usize * self.size as usize + x as usize)
+    }
+}
+
+/// Linear RGB accumulation buffer that stars are drawn into, at the resolution of the output image.
+struct Canvas {
+    values: Vec<Vec3>,
+}
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            values: vec![Vec3::ZERO; width as usize * height as usize],
+        }
+    }
+
+    /// Draw every visible star with the same brightness and falloff used by the shader, averaged
+    /// over `supersampling` by `supersampling` points in each pixel.
+    ///
+    /// Stars smaller than a pixel could fit between those points, so their light is split
+    /// bilinearly between the four nearest pixels instead. Either way each star adds the same
+    /// total light as its sprite would on screen, which shrinking the image later preserves.
+    fn draw(
+        &mut self,
+        projection: &impl Projection,
+        starfield: &Starfield,
+        settings: &StarfieldSettings,
+        options: &BakeOptions,
+    ) {
+        let rotation = Mat3::from_quat(settings.orientation) * options.celestial_to_world;
+        let limit = settings.visible_magnitude_limit;
+        let fade_width = settings.magnitude_fade_width.max(1e-4);
+        let samples = options.supersampling.max(1);
+        let weight = 1.0 / (samples * samples) as f32;
+
+        let scales = starfield.scales();
+        for (index, star) in starfield.stars().iter().enumerate() {
+            if star.magnitude >= limit {
+                continue;
+            }
+            let direction = rotation * star.direction();
+            let Some(center) = projection.project(direction) else {
+                continue;
+            };
+
+            let fade = 1.0 - smoothstep(limit - fade_width, limit, star.magnitude);
+            let brightness =
+                settings.intensity * fade * settings.magnitude_curve.brightness(star.magnitude);
+            let size_scale = if settings.star_size.scales_with_magnitude() {
+                settings.magnitude_curve.size_scale(star.magnitude)
+            } else {
+                1.0
+            };
+            let pixels_per_radian = projection.pixels_per_radian(direction);
+            let radius = 0.5
+                * size_scale
+                * scales.map_or(1.0, |scales| scales[index])
+                * match settings.star_size {
+                    StarSize::Pixels(pixels) | StarSize::ScreenSpace { pixels, .. } => {
+                        pixels / pixels_per_radian.y
+                    }
+                    StarSize::Arcminutes(arcminutes) => (arcminutes / 60.0).to_radians(),
+                };
+            if !(radius > 0.0 && brightness > 0.0) {
+                continue;
+            }
+            let color = star_color(star.color_index, settings.color_saturation) * brightness;
+
+            let footprint = pixels_per_radian * radius;
+            if footprint.max_element() < 1.0 {
+                // The falloff integrates to half the area of the disc.
+                let light = color * (0.5 * PI * footprint.x * footprint.y);
+                self.splat(projection, center, light);
+                continue;
+            }
+
+            let extent = footprint.ceil().as_ivec2() + IVec2::ONE;
+            let center_pixel = center.floor().as_ivec2();
+            for y in center_pixel.y - extent.y..=center_pixel.y + extent.y {
+                for x in center_pixel.x - extent.x..=center_pixel.x + extent.x {
+                    let Some(index) = projection.index(x, y) else {
+                        continue;
+                    };
+                    let mut falloff = 0.0;
+                    for sample_y in 0..samples {
+                        for sample_x in 0..samples {
+                            let offset = (Vec2::new(sample_x as f32, sample_y as f32) + 0.5)
+                                / samples as f32;
+                            let pixel_direction =
+                                projection.unproject(Vec2::new(x as f32, y as f32) + offset);
+                            let angle = pixel_direction.dot(direction).clamp(-1.0, 1.0).acos();
+                            falloff += smoothstep(1.0, 0.0, (angle / radius).powi(2));
+                        }
+                    }
+                    self.values[index] += color * falloff * weight;
+                }
+            }
+        }
+    }
+
+    /// Add `light` to the four pixels around `position`, weighted by how close their centers are.
+    fn splat(&mut self, projection: &impl Projection, position: Vec2, light: Vec3) {
+        let position = position - 0.5;
+        let corner = position.floor();
+        let fraction = position - corner;
+        let corner = corner.as_ivec2();
+        for (offset, weight) in [
+            (IVec2::new(0, 0), (1.0 - fraction.x) * (1.0 - fraction.y)),
+            (IVec2::new(1, 0), fraction.x * (1.0 - fraction.y)),
+            (IVec2::new(0, 1), (1.0 - fraction.x) * fraction.y),
+            (IVec2::new(1, 1), fraction.x * fraction.y),
+        ] {
+            let pixel = corner + offset;
+            if let Some(index) = projection.index(pixel.x, pixel.y) {
+                self.values[index] += light * weight;
+            }
+        }
+    }
+}
+
+/// Linear colors as sRGB bytes, clamping anything brighter than white.
+fn encode_values(values: &[Vec3]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| {
+            let value = value.min(Vec3::ONE);
+            Color::rgb_linear(value.x, value.y, value.z)
+                .as_rgba_f32()
+                .map(|c| (c * 255.0).round() as u8)
+        })
+        .collect()
+}
+
+fn encode_image(width: u32, height: u32, layers: u32, values: Vec<Vec3>) -> Image {
+    let data = encode_values(&values);
+    Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Same as the WGSL builtin of the same name.
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{direction_to_radec, Star};
+
+    #[test]
+    fn small_stars_keep_their_light_between_pixels() {
+        let projection = Equirectangular {
+            width: 64,
+            height: 32,
+        };
+        let settings = StarfieldSettings {
+            star_size: StarSize::Pixels(0.5),
+            ..default()
+        };
+        let total = |pixel: Vec2| {
+            let (right_ascension, declination) = direction_to_radec(projection.unproject(pixel));
+            let starfield = Starfield::new(vec![Star::new(declination, right_ascension, 0.0)]);
+            let mut canvas = Canvas::new(projection.width, projection.height);
+            canvas.draw(&projection, &starfield, &settings, &BakeOptions::default());
+            canvas.values.iter().sum::<Vec3>()
+        };
+
+        // One star lands on a pixel center, the other on the edge between two pixels.
+        let centered = total(Vec2::new(20.5, 10.5));
+        let between = total(Vec2::new(21.0, 10.5));
+        assert!(centered.x > 0.0);
+        assert!((centered - between).abs().max_element() < 1e-3 * centered.x);
+    }
+}
@@ -0,0 +1,76 @@
+//! Rotation models for the bodies the sky can be observed from.
+
+use bevy::{math::DMat3, prelude::*};
+
+/// The body whose surface the observer stands on.
+///
+/// Stars are far enough away that their directions are the same from anywhere in the solar system,
+/// but each body's axis of rotation and length of day determines where they appear in its sky.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect, FromReflect)]
+pub enum CelestialBody {
+    #[default]
+    Earth,
+    Moon,
+    Mars,
+    /// A body with the given [IAU rotation
+    /// elements](https://en.wikipedia.org/wiki/Axial_tilt#Solar_System_bodies).
+    Custom {
+        /// Right ascension of the north pole in degrees.
+        pole_right_ascension: f64,
+        /// Declination of the north pole in degrees.
+        pole_declination: f64,
+        /// Angle of the prime meridian at the J2000 epoch in degrees.
+        prime_meridian: f64,
+        /// Rotation rate of the prime meridian in degrees per day.
+        rotation_rate: f64,
+    },
+}
+impl CelestialBody {
+    /// Returns the pole right ascension, pole declination and prime meridian angle (all in
+    /// radians) of the body at the given Julian date.
+    pub(crate) fn rotation_elements(&self, julian_date: f64) -> (f64, f64, f64) {
+        let d = julian_date - 2451545.0;
+        let t = crate::astro::julian_cent(julian_date);
+        let (ra, dec, w) = match *self {
+            CelestialBody::Earth => {
+                return (0.0, 90f64.to_radians(), crate::astro::mn_sidr(julian_date));
+            }
+            CelestialBody::Moon => (
+                269.9949 + 0.0031 * t,
+                66.5392 + 0.0130 * t,
+                38.3213 + 13.17635815 * d,
+            ),
+            CelestialBody::Mars => (
+                317.68143 - 0.1061 * t,
+                52.88650 - 0.0609 * t,
+                176.630 + 350.89198226 * d,
+            ),
+            CelestialBody::Custom {
+                pole_right_ascension,
+                pole_declination,
+                prime_meridian,
+                rotation_rate,
+            } => (
+                pole_right_ascension,
+                pole_declination,
+                prime_meridian + rotation_rate * d,
+            ),
+        };
+        (
+            ra.to_radians(),
+            dec.to_radians(),
+            w.rem_euclid(360.0).to_radians(),
+        )
+    }
+
+    /// Rotation from equatorial (ICRF) directions to the body fixed frame at the given Julian date.
+    ///
+    /// The body fixed frame has +Z along the body's north pole and -X pointing towards its prime
+    /// meridian.
+    pub(crate) fn celestial_to_body(&self, julian_date: f64) -> DMat3 {
+        let (pole_ra, pole_dec, w) = self.rotation_elements(julian_date);
+        DMat3::from_rotation_z(std::f64::consts::PI - w)
+            * DMat3::from_rotation_x(pole_dec - std::f64::consts::FRAC_PI_2)
+            * DMat3::from_rotation_z(-std::f64::consts::FRAC_PI_2 - pole_ra)
+    }
+}
@@ -0,0 +1,136 @@
+//! Star data and the bundled star catalog.
+
+use bevy::{
+    math::Vec3,
+    reflect::{FromReflect, Reflect},
+};
+use bytemuck::{Pod, Zeroable};
+
+/// A single star as seen from Earth.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Star {
+    /// Declination in radians.
+    pub declination: f32,
+    /// Right ascension in radians.
+    pub right_ascension: f32,
+    /// Apparent visual magnitude. Smaller values are brighter.
+    pub magnitude: f32,
+    /// B-V color index, which determines the star's color as described in [`star_color`].
+    ///
+    /// Zero, the color index of Vega, is drawn as white.
+    ///
+    /// [`star_color`]: crate::star_color
+    pub color_index: f32,
+}
+impl Star {
+    /// Create a white star from its equatorial coordinates (in radians) and apparent magnitude.
+    pub fn new(declination: f32, right_ascension: f32, magnitude: f32) -> Self {
+        Self {
+            declination,
+            right_ascension,
+            magnitude,
+            color_index: 0.0,
+        }
+    }
+
+    /// Unit vector pointing at the star in the celestial frame, as described in
+    /// [`radec_to_direction`](crate::radec_to_direction).
+    pub fn direction(&self) -> Vec3 {
+        crate::radec_to_direction(self.right_ascension, self.declination)
+    }
+
+    /// Set the B-V color index of the star.
+    pub fn with_color_index(self, color_index: f32) -> Self {
+        Self {
+            color_index,
+            ..self
+        }
+    }
+}
+
+/// Descriptive information about a star that isn't needed to draw it, for showing to players.
+///
+/// Metadata is kept on the CPU in [`Starfield::metadata`](crate::Starfield::metadata), alongside
+/// the stars themselves. The apparent magnitude is part of [`Star`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarMetadata {
+    /// Common name of the star, such as "Sirius".
+    pub name: Option<String>,
+    /// Designation of the star in a catalog, such as "HIP 32349" or "HR 2491".
+    pub designation: Option<String>,
+    /// Spectral type of the star, such as "A1V".
+    pub spectral_type: Option<String>,
+}
+
+/// Identifier for a star that stays the same when its starfield is rebuilt, so that save games and
+/// scripts can keep referring to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarId(pub u64);
+impl StarId {
+    /// ID of the star at `index` in a catalog.
+    pub fn catalog(index: u32) -> Self {
+        Self(index as u64)
+    }
+
+    /// ID of the `index`th star procedurally generated from `seed`.
+    ///
+    /// These never collide with catalog IDs.
+    pub fn generated(seed: u64, index: u32) -> Self {
+        // SplitMix64 finalizer.
+        let mut z = seed ^ (index as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        Self((z ^ (z >> 31)) | (1 << 63))
+    }
+}
+
+/// Decode stars stored as consecutive little-endian `f32`s, or `None` if `bytes` doesn't hold a
+/// whole number of stars.
+pub(crate) fn stars_from_le_bytes(bytes: &[u8]) -> Option<Vec<Star>> {
+    if !bytes.len().is_multiple_of(16) {
+        return None;
+    }
+    let stars = bytes
+        .chunks_exact(16)
+        .map(|chunk| {
+            let [declination, right_ascension, magnitude, color_index] =
+                [0, 4, 8, 12].map(|i| f32::from_le_bytes(chunk[i..i + 4].try_into().unwrap()));
+            Star::new(declination, right_ascension, magnitude).with_color_index(color_index)
+        })
+        .collect();
+    Some(stars)
+}
+
+/// Catalog converted to equatorial coordinates by the build script, aligned so that it can be
+/// cast directly to stars.
+#[cfg(feature = "bsc")]
+#[repr(C, align(16))]
+struct Aligned<T: ?Sized>(T);
+#[cfg(feature = "bsc")]
+static BRIGHT_STAR_CATALOG: &Aligned<[u8]> = &Aligned(*include_bytes!(concat!(
+    env!("OUT_DIR"),
+    "/stars_equatorial.bin"
+)));
+
+/// The ~9,000 stars of the [Yale Bright Star Catalog](http://tdc-www.harvard.edu/catalogs/bsc5.html),
+/// which includes every star visible to the naked eye.
+///
+/// Colors come from the B-V index stored with each star in the bundled `stars.bin`. The copy that
+/// currently ships has a B-V index of zero for every star, so they are drawn white until it is
+/// regenerated from the catalog. Only available with the `bsc` feature, which is enabled by
+/// default.
+#[cfg(feature = "bsc")]
+pub fn bright_star_catalog() -> Vec<Star> {
+    bright_star_catalog_slice().to_vec()
+}
+
+/// The same stars as [`bright_star_catalog`], borrowed straight from the binary without any
+/// decoding or allocation.
+#[cfg(feature = "bsc")]
+pub fn bright_star_catalog_slice() -> &'static [Star] {
+    bytemuck::cast_slice(&BRIGHT_STAR_CATALOG.0)
+}
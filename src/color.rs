@@ -0,0 +1,42 @@
+//! Star colors derived from their B-V color index.
+
+use bevy::math::Vec3;
+
+/// Wavelengths in meters that the red, green and blue channels are sampled at.
+const WAVELENGTHS: [f32; 3] = [610e-9, 550e-9, 465e-9];
+
+/// Second radiation constant hc/k, in meter kelvins.
+const SECOND_RADIATION_CONSTANT: f32 = 1.4388e-2;
+
+/// Effective temperature in kelvin of a star with the given B-V color index, according to
+/// [Ballesteros (2012)](https://arxiv.org/abs/1201.1809).
+pub fn color_index_to_temperature(color_index: f32) -> f32 {
+    let color_index = color_index.clamp(-0.4, 2.0);
+    4600.0 * (1.0 / (0.92 * color_index + 1.7) + 1.0 / (0.92 * color_index + 0.62))
+}
+
+/// Linear RGB color of a blackbody at `temperature` kelvin, scaled so that its brightest channel
+/// is one.
+///
+/// Colors are relative to a star with a color index of zero, like Vega, which comes out white.
+/// Cooler stars are yellow to red and hotter ones slightly blue.
+pub fn blackbody_color(temperature: f32) -> Vec3 {
+    let white = color_index_to_temperature(0.0);
+    let [r, g, b] = WAVELENGTHS.map(|wavelength| {
+        // Ratio of spectral radiance to that of the white point, which leaves out the
+        // wavelength dependent factors the two have in common.
+        let exponent = |t: f32| SECOND_RADIATION_CONSTANT / (wavelength * t);
+        (exponent(white).exp() - 1.0) / (exponent(temperature.max(500.0)).exp() - 1.0)
+    });
+    let color = Vec3::new(r, g, b);
+    color / color.max_element()
+}
+
+/// Linear RGB color of a star with the given B-V color index.
+///
+/// A `saturation` of 0.0 makes every star white, 1.0 gives physically based tints and larger
+/// values exaggerate them for stylized skies.
+pub fn star_color(color_index: f32, saturation: f32) -> Vec3 {
+    let color = blackbody_color(color_index_to_temperature(color_index));
+    Vec3::ONE.lerp(color, saturation).max(Vec3::ZERO)
+}
@@ -0,0 +1,124 @@
+//! Bright galaxies, nebulae and globular clusters, drawn as [`Nebula`] billboards.
+
+use bevy::prelude::*;
+
+use crate::Nebula;
+
+/// Kind of a [`DeepSkyObject`], which decides how it is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeepSkyKind {
+    /// A galaxy beyond the Milky Way.
+    Galaxy,
+    /// A cloud of gas glowing red with hydrogen emission.
+    EmissionNebula,
+    /// A shell of gas thrown off by a dying star.
+    PlanetaryNebula,
+    /// The expanding debris of a supernova.
+    SupernovaRemnant,
+    /// A dense ball of old stars, too far away for the eye to resolve.
+    GlobularCluster,
+}
+
+/// A deep-sky object from the table embedded with the `deep-sky` feature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeepSkyObject {
+    /// Common name of the object, such as "Andromeda Galaxy".
+    pub name: &'static str,
+    /// What kind of object this is.
+    pub kind: DeepSkyKind,
+    /// Right ascension in radians.
+    pub right_ascension: f32,
+    /// Declination in radians.
+    pub declination: f32,
+    /// Apparent length in radians of the object's longest axis.
+    pub major_axis: f32,
+    /// Apparent length in radians of the object's shortest axis.
+    pub minor_axis: f32,
+    /// Angle in radians from celestial north towards east of the major axis.
+    pub position_angle: f32,
+    /// Integrated apparent magnitude of the whole object.
+    pub magnitude: f32,
+}
+impl DeepSkyObject {
+    /// A nebula that draws this object, spread out according to its size and colored by its
+    /// kind, with a brightness that follows its surface brightness.
+    pub fn nebula(&self) -> Nebula {
+        let (color, wispiness) = match self.kind {
+            DeepSkyKind::Galaxy => (Color::rgb(1.0, 0.9, 0.75), 0.15),
+            DeepSkyKind::EmissionNebula => (Color::rgb(0.9, 0.25, 0.35), 1.0),
+            DeepSkyKind::PlanetaryNebula => (Color::rgb(0.4, 0.9, 0.8), 0.3),
+            DeepSkyKind::SupernovaRemnant => (Color::rgb(0.9, 0.5, 0.4), 1.0),
+            DeepSkyKind::GlobularCluster => (Color::rgb(1.0, 0.95, 0.8), 0.0),
+        };
+        // Spreading the same light over a larger area makes the object fainter.
+        let area = self.major_axis.to_degrees() * self.minor_axis.to_degrees();
+        let brightness = 0.3 * 10f32.powf(-0.4 * (self.magnitude - 4.0)) / area.max(1e-6);
+        Nebula {
+            right_ascension: self.right_ascension,
+            declination: self.declination,
+            // The noise fades out well inside the billboard, so it needs to be larger than the
+            // object.
+            radius: 0.75 * self.major_axis,
+            aspect_ratio: self.minor_axis / self.major_axis,
+            position_angle: self.position_angle,
+            color,
+            brightness: brightness.min(0.5),
+            seed: self
+                .name
+                .bytes()
+                .fold(0u32, |seed, b| seed.wrapping_mul(31).wrapping_add(b.into())),
+            wispiness,
+            texture: None,
+        }
+    }
+}
+
+/// The bright galaxies, nebulae and globular clusters in the embedded table.
+///
+/// The table covers the largest and brightest objects visible from either hemisphere, including
+/// the Andromeda Galaxy, the Magellanic Clouds and the most prominent Messier objects. Open
+/// clusters are left out since their stars are already in star catalogs.
+pub fn deep_sky_objects() -> Vec<DeepSkyObject> {
+    include_str!("deep_sky.csv")
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let number = |i: usize| fields[i].parse::<f32>().unwrap();
+            DeepSkyObject {
+                name: fields[0],
+                kind: match fields[1] {
+                    "galaxy" => DeepSkyKind::Galaxy,
+                    "emission" => DeepSkyKind::EmissionNebula,
+                    "planetary" => DeepSkyKind::PlanetaryNebula,
+                    "supernova remnant" => DeepSkyKind::SupernovaRemnant,
+                    "globular" => DeepSkyKind::GlobularCluster,
+                    kind => unreachable!("unknown deep-sky object kind {kind}"),
+                },
+                right_ascension: (number(2) * 15.0).to_radians(),
+                declination: number(3).to_radians(),
+                major_axis: (number(4) / 60.0).to_radians(),
+                minor_axis: (number(5) / 60.0).to_radians(),
+                position_angle: number(6).to_radians(),
+                magnitude: number(7),
+            }
+        })
+        .collect()
+}
+
+/// Spawn a [`Nebula`] for every object returned by [`deep_sky_objects`], named after the object.
+///
+/// The nebulae are ordinary entities, so games can restyle them or give them textures after they
+/// are spawned.
+pub struct DeepSkyPlugin;
+impl Plugin for DeepSkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_deep_sky_objects);
+    }
+}
+
+fn spawn_deep_sky_objects(mut commands: Commands) {
+    for object in deep_sky_objects() {
+        commands.spawn((object.nebula(), Name::new(object.name)));
+    }
+}
@@ -0,0 +1,48 @@
+//! Conversions between equatorial coordinates and directions.
+//!
+//! Directions are in the celestial frame that stars are stored in: +Z points towards the north
+//! celestial pole and +X towards the vernal equinox. Multiply by
+//! [`GameUnitsToCelestial::celestial_to_world`](crate::GameUnitsToCelestial::celestial_to_world)
+//! (after any starfield orientation) to find where something appears in the world.
+
+use std::f32::consts::TAU;
+
+use bevy::math::{Mat3, Vec3};
+
+/// Julian date of the J2000.0 epoch, which star catalogs are usually given for.
+pub const J2000: f64 = 2451545.0;
+
+/// Unit vector pointing at the given right ascension and declination, in radians.
+pub fn radec_to_direction(right_ascension: f32, declination: f32) -> Vec3 {
+    Vec3::new(
+        declination.cos() * right_ascension.cos(),
+        declination.cos() * right_ascension.sin(),
+        declination.sin(),
+    )
+}
+
+/// Right ascension and declination in radians of `direction`, which doesn't need to be
+/// normalized. Right ascension is between zero and 2π.
+pub fn direction_to_radec(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize_or_zero();
+    (
+        direction.y.atan2(direction.x).rem_euclid(TAU),
+        direction.z.clamp(-1.0, 1.0).asin(),
+    )
+}
+
+/// Rotation that moves J2000.0 directions to the equator and equinox of `julian_date`, using the
+/// IAU 1976 precession model.
+///
+/// The celestial pole drifts by about a degree per 70 years, which only matters for skies set far
+/// from the present day.
+pub fn precession_from_j2000(julian_date: f64) -> Mat3 {
+    let t = (julian_date - J2000) / 36525.0;
+    let arcseconds = |a: f64, b: f64, c: f64| ((a + (b + c * t) * t) * t / 3600.0).to_radians();
+    let zeta = arcseconds(2306.2181, 0.30188, 0.017998);
+    let z = arcseconds(2306.2181, 1.09468, 0.018203);
+    let theta = arcseconds(2004.3109, -0.42665, -0.041833);
+    Mat3::from_rotation_z(z as f32)
+        * Mat3::from_rotation_y(-theta as f32)
+        * Mat3::from_rotation_z(zeta as f32)
+}
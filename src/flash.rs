@@ -0,0 +1,96 @@
+//! Briefly highlighting individual stars.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::{StarId, Starfield};
+
+/// Maximum number of stars in a starfield that can flash at the same time.
+pub const MAX_STAR_FLASHES: usize = 16;
+
+/// Add to a starfield to make individual stars flash, so that tutorials and quests can point at a
+/// specific spot in the sky.
+///
+/// A flashing star brightens by up to 2.5 magnitudes and takes on the flash's color, easing in and
+/// back out over the flash's duration. While [`ReducedMotion`](crate::ReducedMotion) is enabled,
+/// stars are highlighted steadily for the duration instead of pulsing.
+#[derive(Clone, Component, Default)]
+pub struct StarFlashes {
+    flashes: Vec<StarFlash>,
+}
+
+#[derive(Clone)]
+struct StarFlash {
+    id: StarId,
+    color: Color,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl StarFlashes {
+    /// Flash the star with the given ID in `color` for `duration` seconds.
+    ///
+    /// Flashing a star that is already flashing restarts its flash. If [`MAX_STAR_FLASHES`] stars
+    /// are already flashing, the one closest to finishing is replaced.
+    pub fn flash_star(&mut self, id: StarId, color: Color, duration: f32) {
+        self.flashes.retain(|flash| flash.id != id);
+        if self.flashes.len() == MAX_STAR_FLASHES {
+            let (closest, _) = self
+                .flashes
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    (a.duration - a.elapsed).total_cmp(&(b.duration - b.elapsed))
+                })
+                .unwrap();
+            self.flashes.swap_remove(closest);
+        }
+        self.flashes.push(StarFlash {
+            id,
+            color,
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+        });
+    }
+
+    /// Whether any star is currently flashing.
+    pub fn is_empty(&self) -> bool {
+        self.flashes.is_empty()
+    }
+
+    /// Index, color and strength of each flashing star that is part of `starfield`.
+    pub(crate) fn active<'a>(
+        &'a self,
+        starfield: &'a Starfield,
+        reduced_motion: bool,
+    ) -> impl Iterator<Item = (u32, Vec3, f32)> + 'a {
+        self.flashes.iter().filter_map(move |flash| {
+            let index = starfield.index_of(flash.id)?;
+            let strength = if reduced_motion {
+                1.0
+            } else {
+                (PI * flash.elapsed / flash.duration.max(1e-6))
+                    .sin()
+                    .powi(2)
+            };
+            let [r, g, b, _] = flash.color.as_linear_rgba_f32();
+            Some((index as u32, Vec3::new(r, g, b), strength))
+        })
+    }
+}
+
+pub(crate) fn update_star_flashes(time: Res<Time>, mut flashes: Query<&mut StarFlashes>) {
+    for mut flashes in flashes.iter_mut() {
+        // Avoid marking the component changed while nothing is flashing.
+        if flashes.is_empty() {
+            continue;
+        }
+        for flash in &mut flashes.flashes {
+            flash.elapsed += time.delta_seconds();
+        }
+        flashes
+            .flashes
+            .retain(|flash| flash.elapsed < flash.duration);
+    }
+}
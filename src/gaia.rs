@@ -0,0 +1,471 @@
+//! Dense real skies from a pre-filtered subset of
+//! [Gaia DR3](https://www.cosmos.esa.int/web/gaia/dr3), stored in a compact binary format and
+//! streamed into a starfield over several frames.
+
+use std::{f64::consts::TAU, fmt, path::Path};
+
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use futures_lite::future;
+
+use crate::{CatalogLoadEvent, CatalogLoadState, Star, Starfield, StarfieldSource};
+
+/// Bytes at the start of every Gaia catalog file.
+const MAGIC: &[u8; 4] = b"GDR3";
+/// Size of the magic bytes and star count.
+const HEADER_SIZE: usize = 8;
+/// Size of each encoded star.
+const STAR_SIZE: usize = 12;
+/// Stored in place of the BP-RP color of stars that don't have one.
+const UNKNOWN_COLOR: i16 = i16::MIN;
+
+/// A star from the Gaia archive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaiaStar {
+    /// Right ascension in radians.
+    pub right_ascension: f64,
+    /// Declination in radians.
+    pub declination: f64,
+    /// Mean magnitude in the Gaia G band.
+    pub g_magnitude: f32,
+    /// Difference between the BP and RP band magnitudes, if both were measured.
+    pub bp_rp: Option<f32>,
+}
+impl GaiaStar {
+    /// The star as drawn, with its G magnitude and BP-RP color converted to the V magnitude and
+    /// B-V color index used everywhere else.
+    ///
+    /// Conversions follow the polynomial fits published with Gaia EDR3, and stars without a color
+    /// are treated as white.
+    pub fn star(&self) -> Star {
+        let (magnitude, color_index) = match self.bp_rp {
+            Some(x) => {
+                let x = x.clamp(-0.5, 5.0);
+                let g_minus_v = -0.02704 + 0.01424 * x - 0.2156 * x * x + 0.01426 * x * x * x;
+                let b_minus_v = (0.85 * x - 0.07 * x * x).clamp(-0.4, 2.0);
+                (self.g_magnitude - g_minus_v, b_minus_v)
+            }
+            None => (self.g_magnitude, 0.0),
+        };
+        Star::new(
+            self.declination as f32,
+            self.right_ascension as f32,
+            magnitude,
+        )
+        .with_color_index(color_index)
+    }
+}
+
+/// Error returned when a Gaia catalog can't be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GaiaError {
+    /// The CSV header didn't include a required column.
+    MissingColumn(&'static str),
+    /// A CSV row was missing a required value or it couldn't be parsed. Line numbers start at one.
+    InvalidRow(usize),
+    /// A binary catalog didn't start with the expected header, or its size didn't match the
+    /// number of stars in the header.
+    InvalidFormat,
+}
+impl fmt::Display for GaiaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingColumn(name) => write!(f, "Gaia export has no \"{name}\" column"),
+            Self::InvalidRow(line) => write!(f, "invalid star on line {line} of Gaia export"),
+            Self::InvalidFormat => write!(f, "not a valid Gaia star catalog"),
+        }
+    }
+}
+impl std::error::Error for GaiaError {}
+
+/// Read every star brighter than `max_magnitude` in the G band from a CSV export of the Gaia
+/// archive.
+///
+/// The export needs the `ra`, `dec`, `phot_g_mean_mag` and `bp_rp` columns of the `gaia_source`
+/// table, in any order. Stars without a G magnitude are skipped.
+pub fn parse_gaia_csv(csv: &str, max_magnitude: f32) -> Result<Vec<GaiaStar>, GaiaError> {
+    let mut lines = csv.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(|h| h.trim().trim_matches('"'))
+        .collect();
+    let column = |name| {
+        header
+            .iter()
+            .position(|&h| h == name)
+            .ok_or(GaiaError::MissingColumn(name))
+    };
+    let ra_column = column("ra")?;
+    let dec_column = column("dec")?;
+    let g_column = column("phot_g_mean_mag")?;
+    let bp_rp_column = column("bp_rp")?;
+
+    let mut stars = Vec::new();
+    for (i, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let number = |column: usize| fields.get(column)?.parse::<f64>().ok();
+        let invalid = || GaiaError::InvalidRow(i + 2);
+
+        let Some(g_magnitude) = number(g_column) else {
+            continue;
+        };
+        if g_magnitude >= max_magnitude as f64 {
+            continue;
+        }
+        stars.push(GaiaStar {
+            right_ascension: number(ra_column).ok_or_else(invalid)?.to_radians(),
+            declination: number(dec_column).ok_or_else(invalid)?.to_radians(),
+            g_magnitude: g_magnitude as f32,
+            bp_rp: number(bp_rp_column).map(|bp_rp| bp_rp as f32),
+        });
+    }
+    Ok(stars)
+}
+
+/// Encode stars into the compact binary format read by [`decode_gaia_catalog`] and
+/// [`StreamGaiaCatalog`], brightest first.
+///
+/// Each star takes 12 bytes: its position as 32-bit fixed point right ascension and declination,
+/// which is accurate to a few milliarcseconds, followed by its G magnitude and BP-RP color in
+/// thousandths of a magnitude. Files start with the bytes `GDR3` and the number of stars as a
+/// little-endian `u32`.
+pub fn encode_gaia_catalog(stars: &[GaiaStar]) -> Vec<u8> {
+    // Sorted by the magnitude that is drawn after a round trip through the format, which isn't
+    // quite the order of G magnitudes, so that decoded starfields are sorted by brightness.
+    let mut sorted: Vec<(f32, GaiaStar)> = stars
+        .iter()
+        .map(|star| {
+            let stored = GaiaStar {
+                g_magnitude: millimagnitudes(star.g_magnitude) as f32 / 1000.0,
+                bp_rp: star
+                    .bp_rp
+                    .map(|bp_rp| millimagnitudes(bp_rp) as f32 / 1000.0),
+                ..*star
+            };
+            (stored.star().magnitude, *star)
+        })
+        .collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + STAR_SIZE * sorted.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&(sorted.len() as u32).to_le_bytes());
+    for (_, star) in sorted {
+        let right_ascension = (star.right_ascension.rem_euclid(TAU) / TAU * 2f64.powi(32)) as u64;
+        let declination = star.declination / std::f64::consts::FRAC_PI_2 * i32::MAX as f64;
+        bytes.extend_from_slice(&(right_ascension as u32).to_le_bytes());
+        bytes.extend_from_slice(&(declination.round() as i32).to_le_bytes());
+        bytes.extend_from_slice(&millimagnitudes(star.g_magnitude).to_le_bytes());
+        let bp_rp = star.bp_rp.map_or(UNKNOWN_COLOR, millimagnitudes);
+        bytes.extend_from_slice(&bp_rp.to_le_bytes());
+    }
+    bytes
+}
+
+/// Magnitude in thousandths, as stored in the binary format.
+fn millimagnitudes(magnitude: f32) -> i16 {
+    (magnitude * 1000.0).round().clamp(-32767.0, 32767.0) as i16
+}
+
+/// Decode every star of a catalog written by [`encode_gaia_catalog`].
+pub fn decode_gaia_catalog(bytes: &[u8]) -> Result<Vec<Star>, GaiaError> {
+    let count = star_count(bytes)?;
+    Ok(decode_stars(&bytes[HEADER_SIZE..], 0..count))
+}
+
+/// Number of stars in a catalog, after checking its header and size.
+fn star_count(bytes: &[u8]) -> Result<usize, GaiaError> {
+    if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+        return Err(GaiaError::InvalidFormat);
+    }
+    let count = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    // The count is untrusted, and can overflow the expected size on 32-bit targets.
+    let size = STAR_SIZE
+        .checked_mul(count)
+        .and_then(|size| size.checked_add(HEADER_SIZE));
+    if size != Some(bytes.len()) {
+        return Err(GaiaError::InvalidFormat);
+    }
+    Ok(count)
+}
+
+/// Decode the stars at `indices` from the data following the header.
+fn decode_stars(data: &[u8], indices: std::ops::Range<usize>) -> Vec<Star> {
+    data[indices.start * STAR_SIZE..indices.end * STAR_SIZE]
+        .chunks_exact(STAR_SIZE)
+        .map(|chunk| {
+            let right_ascension = u32::from_le_bytes(chunk[0..4].try_into().unwrap());
+            let declination = i32::from_le_bytes(chunk[4..8].try_into().unwrap());
+            let g_magnitude = i16::from_le_bytes(chunk[8..10].try_into().unwrap());
+            let bp_rp = i16::from_le_bytes(chunk[10..12].try_into().unwrap());
+            GaiaStar {
+                right_ascension: right_ascension as f64 / 2f64.powi(32) * TAU,
+                declination: declination as f64 / i32::MAX as f64 * std::f64::consts::FRAC_PI_2,
+                g_magnitude: g_magnitude as f32 / 1000.0,
+                bp_rp: (bp_rp != UNKNOWN_COLOR).then_some(bp_rp as f32 / 1000.0),
+            }
+            .star()
+        })
+        .collect()
+}
+
+/// Add to a starfield to replace its stars with a Gaia catalog written by
+/// [`encode_gaia_catalog`], fetched through the [`AssetServer`]'s IO.
+///
+/// Once the file arrives, the starfield is resized to hold every star and filled in over several
+/// frames, brightest stars first, so that neither decoding nor uploading hundreds of thousands of
+/// stars causes a hitch. Progress is reported through [`CatalogLoadState`] and
+/// [`CatalogLoadEvent`]s, with the `Parsing` state lasting until the last star is in place.
+#[derive(Clone, Component)]
+pub struct StreamGaiaCatalog {
+    /// Path of the catalog, relative to the asset folder.
+    pub path: String,
+    /// Number of stars decoded and uploaded each frame.
+    ///
+    /// Defaults to 50,000.
+    pub stars_per_frame: usize,
+}
+impl StreamGaiaCatalog {
+    /// Stream the catalog at `path` with the default settings.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            stars_per_frame: 50_000,
+        }
+    }
+}
+
+/// Progress of a [`StreamGaiaCatalog`].
+#[derive(Component)]
+pub(crate) enum GaiaStream {
+    Fetching(Task<Result<Vec<u8>, String>>),
+    Streaming {
+        bytes: Vec<u8>,
+        count: usize,
+        next: usize,
+    },
+}
+
+pub(crate) fn start_gaia_streams(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    streams: Query<(Entity, &StreamGaiaCatalog), Changed<StreamGaiaCatalog>>,
+    mut load_state: ResMut<CatalogLoadState>,
+    mut events: EventWriter<CatalogLoadEvent>,
+) {
+    for (entity, stream) in streams.iter() {
+        let asset_server = asset_server.clone();
+        let path = stream.path.clone();
+        let task = IoTaskPool::get().spawn(async move {
+            asset_server
+                .asset_io()
+                .load_path(Path::new(&path))
+                .await
+                .map_err(|e| e.to_string())
+        });
+        commands.entity(entity).insert(GaiaStream::Fetching(task));
+
+        let state = CatalogLoadState::Fetching {
+            path: stream.path.clone(),
+        };
+        *load_state = state.clone();
+        events.send(CatalogLoadEvent {
+            starfield: entity,
+            state,
+        });
+    }
+}
+
+pub(crate) fn poll_gaia_streams(
+    mut commands: Commands,
+    mut streams: Query<(
+        Entity,
+        &StreamGaiaCatalog,
+        &mut GaiaStream,
+        Option<&mut Starfield>,
+    )>,
+    mut load_state: ResMut<CatalogLoadState>,
+    mut events: EventWriter<CatalogLoadEvent>,
+) {
+    for (entity, settings, mut stream, starfield) in streams.iter_mut() {
+        let state = match &mut *stream {
+            GaiaStream::Fetching(fetch) => match future::block_on(future::poll_once(fetch)) {
+                None => continue,
+                Some(Ok(bytes)) => match star_count(&bytes) {
+                    Ok(count) => {
+                        // Placeholders are too faint to ever be drawn, and keep the starfield
+                        // sorted by brightness as the real stars replace them.
+                        let hidden = Star::new(0.0, 0.0, f32::INFINITY);
+                        let source = StarfieldSource::Stars {
+                            stars: vec![hidden; count],
+                            ids: None,
+                        };
+                        let len = bytes.len();
+                        commands.entity(entity).insert((source.build(), source));
+                        *stream = GaiaStream::Streaming {
+                            bytes,
+                            count,
+                            next: 0,
+                        };
+                        CatalogLoadState::Parsing { bytes: len }
+                    }
+                    Err(error) => {
+                        commands.entity(entity).remove::<GaiaStream>();
+                        CatalogLoadState::Failed {
+                            error: error.to_string(),
+                        }
+                    }
+                },
+                Some(Err(error)) => {
+                    commands.entity(entity).remove::<GaiaStream>();
+                    CatalogLoadState::Failed { error }
+                }
+            },
+            GaiaStream::Streaming { bytes, count, next } => {
+                // The placeholder starfield is inserted by a command, so wait a frame for it.
+                let Some(mut starfield) = starfield.filter(|s| s.stars().len() == *count) else {
+                    continue;
+                };
+                let count = *count;
+                let end = (*next + settings.stars_per_frame.max(1)).min(count);
+                let stars = decode_stars(&bytes[HEADER_SIZE..], *next..end);
+                starfield.replace_stars(*next, &stars);
+                *next = end;
+                if end < count {
+                    continue;
+                }
+                commands.entity(entity).remove::<GaiaStream>();
+                CatalogLoadState::Ready { stars: count }
+            }
+        };
+
+        *load_state = state.clone();
+        events.send(CatalogLoadEvent {
+            starfield: entity,
+            state,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Vec<GaiaStar> {
+        vec![
+            GaiaStar {
+                right_ascension: 0.0,
+                declination: std::f64::consts::FRAC_PI_2,
+                g_magnitude: -1.234,
+                bp_rp: Some(0.25),
+            },
+            GaiaStar {
+                right_ascension: 1.0,
+                declination: -0.5,
+                g_magnitude: 3.5,
+                bp_rp: None,
+            },
+            GaiaStar {
+                right_ascension: 6.0,
+                declination: -std::f64::consts::FRAC_PI_2,
+                g_magnitude: 12.3456,
+                bp_rp: Some(2.0),
+            },
+        ]
+    }
+
+    #[test]
+    fn catalog_round_trips() {
+        let stars = catalog();
+        let bytes = encode_gaia_catalog(&stars);
+        assert_eq!(bytes.len(), HEADER_SIZE + STAR_SIZE * stars.len());
+
+        let decoded = decode_gaia_catalog(&bytes).unwrap();
+        assert_eq!(decoded.len(), stars.len());
+        for (star, decoded) in stars.iter().map(GaiaStar::star).zip(decoded) {
+            assert!((star.right_ascension - decoded.right_ascension).abs() < 1e-6);
+            assert!((star.declination - decoded.declination).abs() < 1e-6);
+            assert!((star.magnitude - decoded.magnitude).abs() < 1e-3);
+            assert!((star.color_index - decoded.color_index).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn catalog_is_sorted_by_brightness() {
+        let mut stars = catalog();
+        stars.reverse();
+        let decoded = decode_gaia_catalog(&encode_gaia_catalog(&stars)).unwrap();
+        assert!(decoded
+            .windows(2)
+            .all(|pair| pair[0].magnitude <= pair[1].magnitude));
+    }
+
+    #[test]
+    fn unknown_colors_are_white() {
+        let star = GaiaStar {
+            right_ascension: 1.0,
+            declination: 0.0,
+            g_magnitude: 5.0,
+            bp_rp: None,
+        };
+        let decoded = decode_gaia_catalog(&encode_gaia_catalog(&[star])).unwrap();
+        assert_eq!(decoded[0].magnitude, 5.0);
+        assert_eq!(decoded[0].color_index, 0.0);
+    }
+
+    #[test]
+    fn empty_catalog_round_trips() {
+        assert_eq!(
+            decode_gaia_catalog(&encode_gaia_catalog(&[])),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let bytes = encode_gaia_catalog(&catalog());
+        for truncated in 0..HEADER_SIZE {
+            assert_eq!(
+                decode_gaia_catalog(&bytes[..truncated]),
+                Err(GaiaError::InvalidFormat)
+            );
+        }
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert_eq!(
+            decode_gaia_catalog(&wrong_magic),
+            Err(GaiaError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn mismatched_sizes_are_rejected() {
+        let bytes = encode_gaia_catalog(&catalog());
+        assert_eq!(
+            decode_gaia_catalog(&bytes[..bytes.len() - 1]),
+            Err(GaiaError::InvalidFormat)
+        );
+
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(decode_gaia_catalog(&extra), Err(GaiaError::InvalidFormat));
+
+        // Counts whose size overflows 32-bit targets, as well as ones that are merely too large.
+        for count in [4, u32::MAX / STAR_SIZE as u32 + 1, u32::MAX] {
+            let mut oversized = bytes.clone();
+            oversized[4..8].copy_from_slice(&count.to_le_bytes());
+            assert_eq!(
+                decode_gaia_catalog(&oversized),
+                Err(GaiaError::InvalidFormat)
+            );
+        }
+    }
+}
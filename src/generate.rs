@@ -0,0 +1,373 @@
+//! Procedurally generated starfields.
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::math::Vec3;
+use bevy::reflect::{FromReflect, Reflect};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{direction_to_radec, radec_to_direction, Star, StarId, Starfield};
+
+/// Options for filling the sky with random stars instead of a catalog.
+///
+/// Generation draws everything from the RNG it is handed, so passing in a stream from a game's own
+/// world generation keeps the sky consistent with the rest of its procedural universe.
+#[derive(Clone, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarGenerator {
+    /// Number of stars to generate outside of [`clusters`](Self::clusters).
+    ///
+    /// Defaults to 9,000, about as many as are visible to the naked eye.
+    pub count: usize,
+    /// Apparent magnitude of the brightest possible star.
+    ///
+    /// Defaults to -1.5.
+    pub brightest_magnitude: f32,
+    /// Apparent magnitude of the faintest possible star.
+    ///
+    /// Defaults to 6.5.
+    pub faintest_magnitude: f32,
+    /// Band along which stars bunch up, like the Milky Way in a dark sky.
+    ///
+    /// Defaults to `None`, which spreads stars uniformly over the whole sky.
+    pub milky_way: Option<MilkyWay>,
+    /// Star clusters added on top of the other stars, each with its own members.
+    ///
+    /// Defaults to none.
+    pub clusters: Vec<StarCluster>,
+    /// Spread of the random sizes given to stars by [`StarGenerator::starfield`], as the standard
+    /// deviation of the natural logarithm of their scale.
+    ///
+    /// Sizes already depend on magnitude, so this adds variety between stars of equal brightness.
+    /// Defaults to 0.0, which makes every star of a given magnitude the same size.
+    pub size_variation: f32,
+}
+impl Default for StarGenerator {
+    fn default() -> Self {
+        Self {
+            count: 9000,
+            brightest_magnitude: -1.5,
+            faintest_magnitude: 6.5,
+            milky_way: None,
+            clusters: Vec::new(),
+            size_variation: 0.0,
+        }
+    }
+}
+
+/// A galactic plane that concentrates generated stars into a band across the sky.
+///
+/// Stars thin out with a gaussian falloff away from the plane, and a narrower lane of dust along
+/// its middle dims and reddens the stars behind it.
+#[derive(Clone, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MilkyWay {
+    /// Declination in radians of the pole of the galactic plane.
+    ///
+    /// Defaults to that of the real north galactic pole, 27.13°.
+    pub pole_declination: f32,
+    /// Right ascension in radians of the pole of the galactic plane.
+    ///
+    /// Defaults to that of the real north galactic pole, 192.86°.
+    pub pole_right_ascension: f32,
+    /// Fraction of stars that belong to the band. The rest are spread over the whole sky.
+    ///
+    /// Defaults to 0.5.
+    pub fraction: f32,
+    /// Standard deviation in radians of the angle between band stars and the plane.
+    ///
+    /// Defaults to 10°.
+    pub thickness: f32,
+    /// Extinction in magnitudes of stars right on the plane.
+    ///
+    /// Defaults to 1.5. Stars are also reddened by a third of their extinction, as interstellar
+    /// dust does.
+    pub dust_extinction: f32,
+    /// Standard deviation in radians of the angle between the plane and dimmed stars.
+    ///
+    /// Defaults to 2°.
+    pub dust_thickness: f32,
+}
+impl Default for MilkyWay {
+    fn default() -> Self {
+        Self {
+            pole_declination: 27.13f32.to_radians(),
+            pole_right_ascension: 192.86f32.to_radians(),
+            fraction: 0.5,
+            thickness: 10f32.to_radians(),
+            dust_extinction: 1.5,
+            dust_thickness: 2f32.to_radians(),
+        }
+    }
+}
+impl MilkyWay {
+    fn pole(&self) -> Vec3 {
+        radec_to_direction(self.pole_right_ascension, self.pole_declination)
+    }
+
+    /// Random direction with a gaussian distributed angle from the plane.
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> (f32, f32) {
+        let latitude = (gaussian(rng) * self.thickness).clamp(-FRAC_PI_2, FRAC_PI_2);
+        let longitude = rng.gen_range(0.0..TAU);
+
+        let pole = self.pole();
+        let (u, v) = pole.any_orthonormal_pair();
+        let direction =
+            latitude.cos() * (longitude.cos() * u + longitude.sin() * v) + latitude.sin() * pole;
+        let (right_ascension, declination) = direction_to_radec(direction);
+        (declination, right_ascension)
+    }
+
+    /// Magnitudes of extinction for a star at the given equatorial coordinates.
+    fn extinction(&self, declination: f32, right_ascension: f32) -> f32 {
+        let direction = radec_to_direction(right_ascension, declination);
+        let latitude = direction.dot(self.pole()).clamp(-1.0, 1.0).asin();
+        self.dust_extinction * (-0.5 * (latitude / self.dust_thickness).powi(2)).exp()
+    }
+}
+
+/// How densely the members of a [`StarCluster`] crowd towards its center.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClusterProfile {
+    /// [Plummer](https://en.wikipedia.org/wiki/Plummer_model) profile, whose density falls off
+    /// smoothly without ever reaching zero. Suits loose open clusters.
+    Plummer,
+    /// [King](https://en.wikipedia.org/wiki/King_model) profile, which is cut off at a tidal
+    /// radius. Suits dense globular clusters.
+    King {
+        /// Radius beyond which there are no members, as a multiple of the cluster's radius.
+        tidal_radius: f32,
+    },
+}
+
+/// A group of stars gathered around a point in the sky, such as an open or globular cluster.
+#[derive(Clone, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarCluster {
+    /// Right ascension in radians of the center of the cluster.
+    ///
+    /// Defaults to 0.0.
+    pub right_ascension: f32,
+    /// Declination in radians of the center of the cluster.
+    ///
+    /// Defaults to 0.0.
+    pub declination: f32,
+    /// Angular radius in radians of the cluster's core, the scale radius of its profile. About
+    /// half of a Plummer cluster's members lie within it.
+    ///
+    /// Defaults to 0.5°.
+    pub radius: f32,
+    /// How members are spread around the center.
+    ///
+    /// Defaults to [`ClusterProfile::Plummer`].
+    pub profile: ClusterProfile,
+    /// Number of members.
+    ///
+    /// Defaults to 200.
+    pub count: usize,
+    /// Apparent magnitude of the brightest possible member.
+    ///
+    /// Defaults to 4.0.
+    pub brightest_magnitude: f32,
+    /// Apparent magnitude of the faintest possible member.
+    ///
+    /// Defaults to 9.0.
+    pub faintest_magnitude: f32,
+    /// Average color index of the members. Young open clusters are bluer than the 0.6 of a
+    /// Sun-like star, and old globular clusters redder.
+    ///
+    /// Defaults to 0.6.
+    pub color_index: f32,
+}
+impl Default for StarCluster {
+    fn default() -> Self {
+        Self {
+            right_ascension: 0.0,
+            declination: 0.0,
+            radius: 0.5f32.to_radians(),
+            profile: ClusterProfile::Plummer,
+            count: 200,
+            brightest_magnitude: 4.0,
+            faintest_magnitude: 9.0,
+            color_index: 0.6,
+        }
+    }
+}
+impl StarCluster {
+    /// Angle between the center and a random member, following the cluster's profile.
+    fn sample_radius<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+        match self.profile {
+            ClusterProfile::Plummer => {
+                // Invert the projected mass fraction R² / (R² + a²), cut off at ten radii.
+                let fraction = rng.gen::<f32>() * 100.0 / 101.0;
+                self.radius * (fraction / (1.0 - fraction)).sqrt()
+            }
+            ClusterProfile::King { tidal_radius } => {
+                let tidal_radius = tidal_radius.max(1.0);
+                let density = |r: f32| {
+                    (1.0 / (1.0 + r * r).sqrt() - 1.0 / (1.0 + tidal_radius * tidal_radius).sqrt())
+                        .powi(2)
+                };
+                // Rejection sample positions spread uniformly over the disc out to the tidal
+                // radius, which always terminates since the density peaks at the center.
+                loop {
+                    let r = tidal_radius * rng.gen::<f32>().sqrt();
+                    if rng.gen::<f32>() * density(0.0) <= density(r) {
+                        break self.radius * r;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Random member of the cluster, as a declination and right ascension.
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> (f32, f32) {
+        let angle = self.sample_radius(rng).min(PI);
+        let azimuth = rng.gen_range(0.0..TAU);
+
+        let center = radec_to_direction(self.right_ascension, self.declination);
+        let (u, v) = center.any_orthonormal_pair();
+        let direction =
+            angle.sin() * (azimuth.cos() * u + azimuth.sin() * v) + angle.cos() * center;
+        let (right_ascension, declination) = direction_to_radec(direction);
+        (declination, right_ascension)
+    }
+}
+
+/// Random magnitude between the two limits, with about three times as many stars at each magnitude
+/// step as the one before it.
+fn random_magnitude<R: Rng + ?Sized>(rng: &mut R, brightest: f32, faintest: f32) -> f32 {
+    // Invert the cumulative distribution N(<m) ∝ 10^(0.5 m) between the two magnitude limits.
+    let low = 10f32.powf(0.5 * brightest);
+    let high = 10f32.powf(0.5 * faintest);
+    2.0 * (low + rng.gen::<f32>() * (high - low)).log10()
+}
+
+/// Standard normal random number, from the Box-Muller transform.
+fn gaussian<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    let radius = (-2.0 * (1.0 - rng.gen::<f32>()).ln()).sqrt();
+    radius * rng.gen_range(0.0..TAU).cos()
+}
+
+impl StarGenerator {
+    /// Generate stars spread uniformly over the sky using `rng`.
+    ///
+    /// As in the real sky, each magnitude step has about three times as many stars as the one
+    /// before it. Color indices cluster around those of Sun-like stars. With a [`MilkyWay`], some
+    /// stars are gathered into its band and those behind its dust are dimmed, which can push them
+    /// below `faintest_magnitude`. Members of each [`StarCluster`] follow the other stars.
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<Star> {
+        let mut stars: Vec<Star> = (0..self.count)
+            .map(|_| {
+                let (declination, right_ascension) = match &self.milky_way {
+                    Some(milky_way) if rng.gen::<f32>() < milky_way.fraction => {
+                        milky_way.sample(rng)
+                    }
+                    _ => (rng.gen_range(-1.0f32..=1.0).asin(), rng.gen_range(0.0..TAU)),
+                };
+                let magnitude =
+                    random_magnitude(rng, self.brightest_magnitude, self.faintest_magnitude);
+                let color_index = -0.3 + 0.8 * (rng.gen::<f32>() + rng.gen::<f32>());
+                self.dimmed_star(declination, right_ascension, magnitude, color_index)
+            })
+            .collect();
+        for cluster in &self.clusters {
+            stars.extend((0..cluster.count).map(|_| {
+                let (declination, right_ascension) = cluster.sample(rng);
+                let magnitude =
+                    random_magnitude(rng, cluster.brightest_magnitude, cluster.faintest_magnitude);
+                let color_index = cluster.color_index + 0.2 * gaussian(rng);
+                self.dimmed_star(declination, right_ascension, magnitude, color_index)
+            }));
+        }
+        stars
+    }
+
+    /// A star behind the dust of the Milky Way, if there is one.
+    fn dimmed_star(
+        &self,
+        declination: f32,
+        right_ascension: f32,
+        mut magnitude: f32,
+        mut color_index: f32,
+    ) -> Star {
+        if let Some(milky_way) = &self.milky_way {
+            let extinction = milky_way.extinction(declination, right_ascension);
+            magnitude += extinction;
+            color_index += extinction / 3.1;
+        }
+        Star::new(declination, right_ascension, magnitude).with_color_index(color_index)
+    }
+
+    /// Generate stars with a fresh `R` seeded from `seed`, so that the same seed always produces
+    /// the same sky.
+    pub fn generate_seeded<R: Rng + SeedableRng>(&self, seed: u64) -> Vec<Star> {
+        self.generate(&mut R::seed_from_u64(seed))
+    }
+
+    /// Build a starfield from `seed`, with stars identified by [`StarId::generated`] and
+    /// [sorted by brightness](Starfield::sorted_by_brightness).
+    ///
+    /// This always uses the same portable RNG, so the sky can be reproduced from a save file that
+    /// stores only the generator and seed. Every platform draws the same random numbers, but the
+    /// functions turning them into stars (`powf`, `ln`, `asin` and so on) aren't guaranteed to
+    /// round identically everywhere. Positions and magnitudes may then differ in their last bits,
+    /// which can swap stars of almost equal magnitude in the brightness order, so only their
+    /// [`StarId`]s are reliable across platforms.
+    pub fn starfield(&self, seed: u64) -> Starfield {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let stars = self.generate(&mut rng);
+        let ids = (0..stars.len() as u32)
+            .map(|index| StarId::generated(seed, index))
+            .collect();
+        let starfield = Starfield::with_ids(stars, ids);
+        let starfield = if self.size_variation > 0.0 {
+            let scales = (0..starfield.stars().len())
+                .map(|_| (gaussian(&mut rng) * self.size_variation).exp())
+                .collect();
+            starfield.with_scales(scales)
+        } else {
+            starfield
+        };
+        starfield.sorted_by_brightness()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn same_seed_same_stars() {
+        let generator = StarGenerator {
+            count: 2000,
+            milky_way: Some(MilkyWay::default()),
+            size_variation: 0.3,
+            ..Default::default()
+        };
+        let a = generator.starfield(42);
+        let b = generator.starfield(42);
+        assert_eq!(a.stars(), b.stars());
+        assert_eq!(a.ids(), b.ids());
+        assert_eq!(a.scales(), b.scales());
+
+        let c = generator.starfield(43);
+        assert_ne!(a.stars(), c.stars());
+        assert!(a.ids().iter().all(|id| !c.ids().contains(id)));
+    }
+
+    #[test]
+    fn generated_ids_never_collide_with_catalog_ids() {
+        let starfield = StarGenerator::default().starfield(7);
+        let ids: HashSet<StarId> = starfield.ids().iter().copied().collect();
+        assert_eq!(ids.len(), starfield.ids().len());
+
+        for (seed, index) in [(0, 0), (0, u32::MAX), (u64::MAX, 0), (7, 1234)] {
+            assert!(StarId::generated(seed, index) > StarId::catalog(u32::MAX));
+        }
+        assert!(ids.iter().all(|id| *id > StarId::catalog(u32::MAX)));
+    }
+}
@@ -0,0 +1,59 @@
+//! Material that adds glints of reflected starlight to shiny surfaces.
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{AsBindGroup, ShaderRef, ShaderType},
+};
+
+pub(crate) const GLINT_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 11345964228115515980);
+
+/// Additive material that makes upward facing surfaces such as frozen lakes or calm seas sparkle
+/// with the reflections of bright stars.
+///
+/// Apply it to a copy of the reflective surface's mesh, or to a plane just above it. `sky` should
+/// be a cubemap produced by [`bake_cubemap`](crate::bake_cubemap).
+#[derive(Clone, AsBindGroup, TypeUuid)]
+#[uuid = "3929b300-9309-4531-a5e0-86ab9d44004d"]
+pub struct StarGlintMaterial {
+    /// Cubemap of the sky being reflected.
+    #[texture(0, dimension = "cube")]
+    #[sampler(1)]
+    pub sky: Handle<Image>,
+    /// Appearance of the glints.
+    #[uniform(2)]
+    pub settings: StarGlintSettings,
+}
+
+/// Appearance of a [`StarGlintMaterial`].
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct StarGlintSettings {
+    /// Brightness of the glints.
+    ///
+    /// Defaults to 1.0.
+    pub intensity: f32,
+    /// Reflected sky brightness below which nothing is drawn, so that only individual stars
+    /// produce glints.
+    ///
+    /// Defaults to 0.1.
+    pub threshold: f32,
+}
+impl Default for StarGlintSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            threshold: 0.1,
+        }
+    }
+}
+
+impl Material for StarGlintMaterial {
+    fn fragment_shader() -> ShaderRef {
+        GLINT_SHADER_HANDLE.typed().into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Add
+    }
+}
@@ -0,0 +1,38 @@
+#import bevy_pbr::mesh_view_bindings
+
+struct GlintSettings {
+    intensity: f32,
+    threshold: f32,
+}
+
+@group(1) @binding(0)
+var sky_texture: texture_cube<f32>;
+
+@group(1) @binding(1)
+var sky_sampler: sampler;
+
+@group(1) @binding(2)
+var<uniform> settings: GlintSettings;
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    #import bevy_pbr::mesh_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let normal = normalize(in.world_normal);
+    let to_eye = normalize(view.world_position - in.world_position.xyz);
+    let reflected = reflect(-to_eye, normal);
+
+    // Cubemaps are sampled with a left-handed coordinate system.
+    let sky = textureSample(sky_texture, sky_sampler, vec3(reflected.xy, -reflected.z)).r;
+    let glint = max(sky - settings.threshold, 0.0) / max(1.0 - settings.threshold, 0.0001);
+
+    let upward = smoothstep(0.7, 1.0, normal.y);
+    let fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(normal, to_eye), 0.0), 5.0);
+
+    // Additive blending: the output is premultiplied with an alpha of zero.
+    return vec4(vec3(glint * upward * fresnel * settings.intensity), 0.0);
+}
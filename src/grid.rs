@@ -0,0 +1,257 @@
+//! Lines of right ascension and declination, the celestial equator and the ecliptic.
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::Indices,
+        render_resource::{AsBindGroup, PrimitiveTopology, ShaderRef},
+        view::NoFrustumCulling,
+    },
+};
+
+use crate::{
+    observer::sky_rotation, radec_to_direction, GameUnitsToCelestial, LineStyle, Observer,
+    OverlayElement, OverlayStyle, Starfield, StarfieldSettings,
+};
+
+pub(crate) const GRID_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 146143632207423462);
+
+/// Angle in degrees between the points that lines are built from.
+const STEP: f32 = 2.0;
+
+/// Add to a starfield entity to draw a celestial coordinate grid over its sky.
+///
+/// The lines turn with the starfield, including its
+/// [`orientation`](StarfieldSettings::orientation), and are colored and patterned according to
+/// the [`OverlayStyle`] resource. Like nebulae, they are drawn infinitely far away and only show
+/// where nothing else has been drawn.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct CelestialGrid {
+    /// Whether to draw lines of constant right ascension and declination.
+    ///
+    /// Defaults to `true`.
+    pub grid: bool,
+    /// Spacing in degrees between lines of constant right ascension.
+    ///
+    /// Defaults to 15.0, one hour of right ascension.
+    pub right_ascension_spacing: f32,
+    /// Spacing in degrees between lines of constant declination.
+    ///
+    /// Defaults to 10.0.
+    pub declination_spacing: f32,
+    /// Whether to draw the celestial equator.
+    ///
+    /// Defaults to `true`.
+    pub equator: bool,
+    /// Whether to draw the ecliptic, the Sun's path through the sky over a year.
+    ///
+    /// Defaults to `true`.
+    pub ecliptic: bool,
+}
+impl Default for CelestialGrid {
+    fn default() -> Self {
+        Self {
+            grid: true,
+            right_ascension_spacing: 15.0,
+            declination_spacing: 10.0,
+            equator: true,
+            ecliptic: true,
+        }
+    }
+}
+
+#[derive(Clone, AsBindGroup, TypeUuid)]
+#[uuid = "72b2f4ef-00f9-4796-bff9-c1fff97237d7"]
+pub(crate) struct CelestialGridMaterial {}
+
+impl Material for CelestialGridMaterial {
+    fn vertex_shader() -> ShaderRef {
+        GRID_SHADER_HANDLE.typed().into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        GRID_SHADER_HANDLE.typed().into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+}
+
+/// Entity holding the lines drawn for a [`CelestialGrid`], stored on the starfield.
+#[derive(Component)]
+pub(crate) struct CelestialGridLines(Entity);
+
+/// Marks the entity drawing a starfield's grid lines.
+#[derive(Component)]
+pub(crate) struct CelestialGridOf(Entity);
+
+/// Builds a line list mesh out of polylines of celestial directions.
+#[derive(Default)]
+struct LineMeshBuilder {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+}
+impl LineMeshBuilder {
+    fn add_line(&mut self, points: impl Iterator<Item = Vec3>, (color, style): (Color, LineStyle)) {
+        let pattern = match style {
+            LineStyle::Solid => 0.0,
+            LineStyle::Dashed => 1.0,
+            LineStyle::Dotted => 2.0,
+        };
+        let color = color.as_linear_rgba_f32();
+        let mut previous: Option<(Vec3, f32)> = None;
+        for point in points {
+            if let Some((start, distance)) = previous {
+                let end_distance = distance + start.angle_between(point).to_degrees();
+                self.positions.extend([start.to_array(), point.to_array()]);
+                self.uvs
+                    .extend([[distance, pattern], [end_distance, pattern]]);
+                self.colors.extend([color, color]);
+                previous = Some((point, end_distance));
+            } else {
+                previous = Some((point, 0.0));
+            }
+        }
+    }
+
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        let indices = (0..self.positions.len() as u32).collect();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+/// Directions along a circle of constant declination, from right ascension 0 all the way around.
+fn parallel(declination: f32) -> impl Iterator<Item = Vec3> {
+    let steps = (360.0 / STEP) as u32;
+    (0..=steps)
+        .map(move |i| radec_to_direction((i as f32 * STEP).to_radians(), declination.to_radians()))
+}
+
+fn grid_mesh(grid: &CelestialGrid, style: &OverlayStyle) -> Mesh {
+    let mut builder = LineMeshBuilder::default();
+
+    if grid.grid {
+        let grid_style = style.style(OverlayElement::Grid);
+        let spacing = grid.declination_spacing.max(1.0);
+        let mut declination = spacing;
+        while declination < 90.0 {
+            builder.add_line(parallel(declination), grid_style);
+            builder.add_line(parallel(-declination), grid_style);
+            declination += spacing;
+        }
+        if !grid.equator {
+            builder.add_line(parallel(0.0), grid_style);
+        }
+
+        let spacing = grid.right_ascension_spacing.max(1.0);
+        let steps = (180.0 / STEP) as u32;
+        let mut right_ascension: f32 = 0.0;
+        while right_ascension < 360.0 {
+            let meridian = (0..=steps).map(|i| {
+                let declination = -90.0 + i as f32 * STEP;
+                radec_to_direction(right_ascension.to_radians(), declination.to_radians())
+            });
+            builder.add_line(meridian, grid_style);
+            right_ascension += spacing;
+        }
+    }
+
+    if grid.equator {
+        builder.add_line(parallel(0.0), style.style(OverlayElement::Equator));
+    }
+
+    if grid.ecliptic {
+        let (sin, cos) = 23.4393f32.to_radians().sin_cos();
+        let steps = (360.0 / STEP) as u32;
+        let ecliptic = (0..=steps).map(|i| {
+            let (sin_longitude, cos_longitude) = (i as f32 * STEP).to_radians().sin_cos();
+            Vec3::new(cos_longitude, sin_longitude * cos, sin_longitude * sin)
+        });
+        builder.add_line(ecliptic, style.style(OverlayElement::Ecliptic));
+    }
+
+    builder.build()
+}
+
+pub(crate) fn build_celestial_grids(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<CelestialGridMaterial>>,
+    mut material: Local<Option<Handle<CelestialGridMaterial>>>,
+    style: Res<OverlayStyle>,
+    grids: Query<(Entity, Ref<CelestialGrid>, Option<&CelestialGridLines>), With<Starfield>>,
+    lines: Query<(Entity, &CelestialGridOf)>,
+) {
+    // Clean up after grids that were removed, or whose starfield was despawned.
+    for (lines_entity, CelestialGridOf(starfield)) in lines.iter() {
+        if !grids.contains(*starfield) {
+            commands.entity(lines_entity).despawn_recursive();
+            if let Some(mut starfield) = commands.get_entity(*starfield) {
+                starfield.remove::<CelestialGridLines>();
+            }
+        }
+    }
+
+    let material = material
+        .get_or_insert_with(|| materials.add(CelestialGridMaterial {}))
+        .clone();
+    for (entity, grid, grid_lines) in grids.iter() {
+        if !grid.is_changed() && !style.is_changed() {
+            continue;
+        }
+        let mesh = meshes.add(grid_mesh(&grid, &style));
+        match grid_lines {
+            Some(CelestialGridLines(lines_entity)) => {
+                commands.entity(*lines_entity).insert(mesh);
+            }
+            None => {
+                let lines_entity = commands
+                    .spawn((
+                        MaterialMeshBundle {
+                            mesh,
+                            material: material.clone(),
+                            ..default()
+                        },
+                        // The mesh sits on a unit sphere around the origin rather than where it is
+                        // drawn, so its bounding box is meaningless.
+                        NoFrustumCulling,
+                        CelestialGridOf(entity),
+                        Name::new("Celestial Grid"),
+                    ))
+                    .id();
+                commands
+                    .entity(entity)
+                    .insert(CelestialGridLines(lines_entity));
+            }
+        }
+    }
+}
+
+pub(crate) fn orient_celestial_grids(
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+    starfields: Query<(&StarfieldSettings, Option<(&Observer, &Transform)>)>,
+    mut lines: Query<(&CelestialGridOf, &mut Transform), Without<StarfieldSettings>>,
+) {
+    let celestial_to_world = game_units_to_celestial.celestial_to_world(time.elapsed_seconds_f64());
+    for (CelestialGridOf(starfield), mut transform) in lines.iter_mut() {
+        let (orientation, rotation) = match starfields.get(*starfield) {
+            Ok((settings, observer)) => (
+                settings.orientation,
+                sky_rotation(observer, celestial_to_world),
+            ),
+            Err(_) => (Quat::IDENTITY, celestial_to_world),
+        };
+        transform.rotation = orientation * Quat::from_mat3(&rotation);
+    }
+}
@@ -0,0 +1,41 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    // As for nebulae, positions are celestial directions drawn infinitely far away.
+    let direction = (mesh.model * vec4(vertex.position, 0.0)).xyz;
+    out.clip_position = view.view_proj * vec4(direction, 0.0);
+    out.clip_position.z = 0.0;
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The first texture coordinate is the distance in degrees along the line, and the second
+    // selects its pattern: 0 for solid, 1 for dashed and 2 for dotted.
+    let distance = in.uv.x;
+    let pattern = u32(in.uv.y + 0.5);
+    if (pattern == 1u && fract(distance / 4.0) > 0.6) {
+        discard;
+    }
+    if (pattern == 2u && fract(distance / 1.5) > 0.35) {
+        discard;
+    }
+    return in.color;
+}
@@ -0,0 +1,134 @@
+//! Reading the [HYG database](https://github.com/astronexus/HYG-Database), which combines the
+//! Hipparcos, Yale Bright Star and Gliese catalogs into about 120,000 stars with distances.
+
+use std::fmt;
+
+use crate::{CatalogStar, Star, StarId, StarMetadata};
+
+/// HYG stores this distance for stars whose parallax is unknown.
+const UNKNOWN_DISTANCE: f64 = 100_000.0;
+
+/// A star read from the HYG database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HygStar {
+    /// ID of the star, taken from its HYG ID.
+    pub id: StarId,
+    /// Position and brightness of the star as seen from the Sun, along with its distance.
+    ///
+    /// Stars without a known distance are given an infinite one, and those without a known color
+    /// index are white.
+    pub star: CatalogStar,
+    /// Common name of the star, such as "Sirius", if it has one.
+    pub name: Option<String>,
+    /// Number of the star in the Hipparcos catalog, if it has one.
+    pub hipparcos: Option<u32>,
+    /// Spectral type of the star, such as "A1V", if it is known.
+    pub spectral_type: Option<String>,
+}
+impl HygStar {
+    /// The star's name, Hipparcos designation and spectral type, for
+    /// [`Starfield::with_metadata`](crate::Starfield::with_metadata).
+    pub fn metadata(&self) -> StarMetadata {
+        StarMetadata {
+            name: self.name.clone(),
+            designation: self.hipparcos.map(|hip| format!("HIP {hip}")),
+            spectral_type: self.spectral_type.clone(),
+        }
+    }
+}
+
+/// Error returned when the HYG database can't be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HygError {
+    /// The header didn't include a required column.
+    MissingColumn(&'static str),
+    /// A row was missing a required value or it couldn't be parsed. Line numbers start at one.
+    InvalidRow(usize),
+}
+impl fmt::Display for HygError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingColumn(name) => write!(f, "HYG database has no \"{name}\" column"),
+            Self::InvalidRow(line) => write!(f, "invalid star on line {line} of HYG database"),
+        }
+    }
+}
+impl std::error::Error for HygError {}
+
+/// Read every star brighter than `max_magnitude` from the CSV text of the HYG database.
+///
+/// Both version 3 and version 4 of the database are supported. The Sun is skipped.
+pub fn parse_hyg_catalog(csv: &str, max_magnitude: f32) -> Result<Vec<HygStar>, HygError> {
+    let mut lines = csv.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(unquote)
+        .collect();
+    let column = |name| {
+        header
+            .iter()
+            .position(|&h| h == name)
+            .ok_or(HygError::MissingColumn(name))
+    };
+    let id_column = column("id")?;
+    let ra_column = column("ra")?;
+    let dec_column = column("dec")?;
+    let dist_column = column("dist")?;
+    let mag_column = column("mag")?;
+    let ci_column = column("ci")?;
+    let proper_column = column("proper")?;
+    // Older exports of the database sometimes leave these out.
+    let hip_column = column("hip").ok();
+    let spect_column = column("spect").ok();
+
+    let mut stars = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line_number = i + 2;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(unquote).collect();
+        let field = |column: usize| fields.get(column).copied().unwrap_or_default();
+        let text = |column: Option<usize>| {
+            Some(field(column?))
+                .filter(|text| !text.is_empty())
+                .map(str::to_owned)
+        };
+        let number = |column: usize| field(column).parse::<f64>().ok();
+        let invalid = || HygError::InvalidRow(line_number);
+
+        let magnitude = number(mag_column).ok_or_else(invalid)?;
+        let distance = number(dist_column).ok_or_else(invalid)?;
+        if magnitude >= max_magnitude as f64 || distance <= 0.0 {
+            continue;
+        }
+        let id = field(id_column).parse::<u32>().map_err(|_| invalid())?;
+        let right_ascension = (number(ra_column).ok_or_else(invalid)? * 15.0).to_radians();
+        let declination = number(dec_column).ok_or_else(invalid)?.to_radians();
+
+        stars.push(HygStar {
+            id: StarId::catalog(id),
+            star: CatalogStar {
+                star: Star::new(declination as f32, right_ascension as f32, magnitude as f32)
+                    .with_color_index(number(ci_column).unwrap_or(0.0) as f32),
+                distance: if distance >= UNKNOWN_DISTANCE {
+                    f64::INFINITY
+                } else {
+                    distance
+                },
+            },
+            name: text(Some(proper_column)),
+            hipparcos: hip_column
+                .and_then(|column| field(column).parse::<u32>().ok())
+                .filter(|&hip| hip > 0),
+            spectral_type: text(spect_column),
+        });
+    }
+    Ok(stars)
+}
+
+fn unquote(field: &str) -> &str {
+    field.trim().trim_matches('"')
+}
@@ -0,0 +1,74 @@
+//! Live editing of starfields through an egui window.
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::{
+    bevy_egui::{egui, EguiContext, EguiPlugin},
+    reflect_inspector::ui_for_value,
+    DefaultInspectorConfigPlugin,
+};
+
+use crate::{Starfield, StarfieldSettings, StarfieldSource};
+
+/// Show a window for tweaking the settings and source of every starfield while the game runs.
+///
+/// Edits take effect immediately: changing the source, such as the star count or magnitude range
+/// of a [`StarGenerator`](crate::StarGenerator), regenerates the stars on the spot. Adds
+/// [`EguiPlugin`] if it hasn't been added already.
+pub struct StarfieldInspectorPlugin;
+impl Plugin for StarfieldInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugin(EguiPlugin);
+        }
+        if !app.is_plugin_added::<DefaultInspectorConfigPlugin>() {
+            app.add_plugin(DefaultInspectorConfigPlugin);
+        }
+        app.add_system(starfield_inspector_ui);
+    }
+}
+
+fn starfield_inspector_ui(world: &mut World) {
+    let Ok(egui_context) = world
+        .query_filtered::<&mut EguiContext, With<PrimaryWindow>>()
+        .get_single(world)
+    else {
+        return;
+    };
+    let mut egui_context = egui_context.clone();
+
+    let starfields: Vec<(Entity, String)> = world
+        .query_filtered::<(Entity, Option<&Name>), With<Starfield>>()
+        .iter(world)
+        .map(|(entity, name)| {
+            let name = name.map_or_else(|| format!("{entity:?}"), |name| name.to_string());
+            (entity, name)
+        })
+        .collect();
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+
+    egui::Window::new("Starfields").show(egui_context.get_mut(), |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (entity, name) in starfields {
+                egui::CollapsingHeader::new(name)
+                    .id_source(entity)
+                    .show(ui, |ui| {
+                        // Only flag the components as changed when they were actually edited, so
+                        // the stars aren't rebuilt every frame.
+                        if let Some(mut settings) = world.get_mut::<StarfieldSettings>(entity) {
+                            if ui_for_value(settings.bypass_change_detection(), ui, &type_registry)
+                            {
+                                settings.set_changed();
+                            }
+                        }
+                        ui.separator();
+                        if let Some(mut source) = world.get_mut::<StarfieldSource>(entity) {
+                            if ui_for_value(source.bypass_change_detection(), ui, &type_registry) {
+                                source.set_changed();
+                            }
+                        }
+                    });
+            }
+        });
+    });
+}
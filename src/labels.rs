@@ -0,0 +1,211 @@
+//! Names drawn next to stars as UI text.
+
+use bevy::prelude::*;
+
+use crate::{
+    observer::sky_rotation, GameUnitsToCelestial, Observer, StarId, Starfield, StarfieldSettings,
+};
+
+/// Which stars [`StarLabels`] puts names next to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StarLabelSelection {
+    /// The given number of brightest stars across every starfield, labeled with the name from
+    /// their [`StarMetadata`](crate::StarMetadata), or their designation if they have no name.
+    /// Stars without either are skipped.
+    Brightest(usize),
+    /// The given stars, labeled with the given text.
+    Stars(Vec<(StarId, String)>),
+}
+
+/// Insert this resource to label stars with their names, for planetarium views and star charts.
+///
+/// Each label is a UI text node that follows its star across the screen as the camera and sky
+/// turn. Labels are hidden while their star is off screen or fainter than its starfield's
+/// magnitude limit.
+#[derive(Clone, Debug, Resource)]
+pub struct StarLabels {
+    /// Camera the labels are positioned for. If `None`, the first active 3D camera is used.
+    ///
+    /// Defaults to `None`.
+    pub camera: Option<Entity>,
+    /// Which stars to label.
+    ///
+    /// Defaults to the 20 brightest named stars.
+    pub selection: StarLabelSelection,
+    /// Font, size and color of the labels.
+    ///
+    /// Defaults to 14 pixel text in a pale blue.
+    pub style: TextStyle,
+    /// Offset in logical pixels, right and up, from a star to the bottom left corner of its label.
+    ///
+    /// Defaults to 6 pixels in each direction.
+    pub offset: Vec2,
+}
+impl StarLabels {
+    /// Labels drawn in `font` with the default settings.
+    pub fn new(font: Handle<Font>) -> Self {
+        Self {
+            camera: None,
+            selection: StarLabelSelection::Brightest(20),
+            style: TextStyle {
+                font,
+                font_size: 14.0,
+                color: Color::rgba(0.75, 0.85, 1.0, 0.8),
+            },
+            offset: Vec2::splat(6.0),
+        }
+    }
+}
+
+/// UI node labeling a star.
+#[derive(Component)]
+pub(crate) struct StarLabel {
+    starfield: Entity,
+    index: usize,
+}
+
+/// The stars to label along with their text, in the order they are spawned.
+fn select_labels(
+    selection: &StarLabelSelection,
+    starfields: &[(Entity, &Starfield)],
+) -> Vec<(Entity, usize, String)> {
+    match selection {
+        StarLabelSelection::Brightest(count) => {
+            let mut named: Vec<_> = starfields
+                .iter()
+                .flat_map(|&(entity, starfield)| {
+                    let metadata = starfield.metadata().unwrap_or_default();
+                    metadata
+                        .iter()
+                        .enumerate()
+                        .filter_map(move |(index, metadata)| {
+                            let text = metadata.name.as_ref().or(metadata.designation.as_ref())?;
+                            let magnitude = starfield.stars()[index].magnitude;
+                            Some((magnitude, (entity, index, text.clone())))
+                        })
+                })
+                .collect();
+            named.sort_by(|a, b| a.0.total_cmp(&b.0));
+            named.truncate(*count);
+            named.into_iter().map(|(_, label)| label).collect()
+        }
+        StarLabelSelection::Stars(stars) => stars
+            .iter()
+            .filter_map(|(id, text)| {
+                starfields.iter().find_map(|&(entity, starfield)| {
+                    Some((entity, starfield.index_of(*id)?, text.clone()))
+                })
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn update_star_labels(
+    mut commands: Commands,
+    labels: Option<Res<StarLabels>>,
+    mut current: Local<Vec<(Entity, usize, String)>>,
+    starfields: Query<(Entity, &Starfield)>,
+    changed_starfields: Query<(), Changed<Starfield>>,
+    mut removed_starfields: RemovedComponents<Starfield>,
+    label_nodes: Query<Entity, With<StarLabel>>,
+) {
+    let removed = removed_starfields.iter().count() > 0;
+    let labels_changed = labels.as_ref().is_some_and(|labels| labels.is_changed());
+    // Searching large starfields for the brightest stars is slow, so only do it when something
+    // could have changed the result.
+    if !labels_changed && !removed && changed_starfields.is_empty() && labels.is_some() {
+        return;
+    }
+
+    let wanted = match &labels {
+        Some(labels) => {
+            let starfields: Vec<_> = starfields.iter().collect();
+            select_labels(&labels.selection, &starfields)
+        }
+        None => Vec::new(),
+    };
+    if wanted == *current && !labels_changed {
+        return;
+    }
+
+    for entity in label_nodes.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if let Some(labels) = labels {
+        for (starfield, index, text) in &wanted {
+            commands.spawn((
+                TextBundle {
+                    text: Text::from_section(text.clone(), labels.style.clone()),
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    // Hidden until it has been positioned.
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                StarLabel {
+                    starfield: *starfield,
+                    index: *index,
+                },
+            ));
+        }
+    }
+    *current = wanted;
+}
+
+pub(crate) fn position_star_labels(
+    labels: Option<Res<StarLabels>>,
+    cameras: Query<(Entity, &Camera, &GlobalTransform), With<Camera3d>>,
+    starfields: Query<(
+        &Starfield,
+        &StarfieldSettings,
+        Option<(&Observer, &Transform)>,
+    )>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+    mut label_nodes: Query<(&StarLabel, &mut Style, &mut Visibility)>,
+) {
+    let Some(labels) = labels else {
+        return;
+    };
+    let camera = match labels.camera {
+        Some(entity) => cameras.get(entity).ok(),
+        None => cameras.iter().find(|(_, camera, _)| camera.is_active),
+    };
+    let celestial_to_world = game_units_to_celestial.celestial_to_world(time.elapsed_seconds_f64());
+
+    for (label, mut style, mut visibility) in label_nodes.iter_mut() {
+        let position = camera.zip(starfields.get(label.starfield).ok()).and_then(
+            |((_, camera, transform), (starfield, settings, observer))| {
+                let star = starfield.stars().get(label.index)?;
+                if star.magnitude >= settings.visible_magnitude_limit {
+                    return None;
+                }
+                let direction = Mat3::from_quat(settings.orientation)
+                    * sky_rotation(observer, celestial_to_world)
+                    * star.direction();
+                // Any point along the direction projects to the same place, as long as it is
+                // inside the camera's depth range.
+                camera.world_to_viewport(transform, transform.translation() + direction * 100.0)
+            },
+        );
+        match position {
+            Some(position) => {
+                style.position = UiRect {
+                    left: Val::Px(position.x + labels.offset.x),
+                    bottom: Val::Px(position.y + labels.offset.y),
+                    ..default()
+                };
+                if *visibility != Visibility::Inherited {
+                    *visibility = Visibility::Inherited;
+                }
+            }
+            None => {
+                if *visibility != Visibility::Hidden {
+                    *visibility = Visibility::Hidden;
+                }
+            }
+        }
+    }
+}
@@ -1,44 +1,125 @@
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::{
-    core_pipeline::core_3d::Opaque3d,
-    ecs::{
-        query::WorldQuery,
-        system::{lifetimeless::Read, SystemParam, SystemState},
-    },
-    pbr::SetMeshViewBindGroup,
+    ecs::system::EntityCommands,
     prelude::*,
-    reflect::TypeUuid,
-    render::{
-        extract_resource::ExtractResource,
-        render_phase::{
-            AddRenderCommand, DrawFunctions, PhaseItem, RenderCommand, RenderCommandResult,
-            RenderPhase, SetItemPipeline, TrackedRenderPass,
-        },
-        render_resource::{
-            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
-            BufferBinding, BufferBindingType, BufferInitDescriptor, BufferUsages, ColorTargetState,
-            ColorWrites, CompareFunction, DepthStencilState, FragmentState, FrontFace,
-            MultisampleState, PipelineCache, PolygonMode, PrimitiveState, PrimitiveTopology,
-            RenderPipelineDescriptor, ShaderStages, ShaderType, SpecializedRenderPipeline,
-            SpecializedRenderPipelines, TextureFormat, UniformBuffer, VertexState, BlendState,
-        },
-        renderer::{RenderDevice, RenderQueue},
-        texture::DefaultImageSampler,
-        view::{ViewTarget, ViewUniformOffset, ViewUniforms},
-        Extract, RenderApp, RenderSet,
+    render::{extract_component::ExtractComponent, extract_resource::ExtractResource},
+};
+use std::{
+    fmt,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
     },
 };
-use std::num::NonZeroU64;
 
+mod asset;
 mod astro;
+mod bake;
+mod body;
+mod catalog;
+mod color;
+#[cfg(feature = "deep-sky")]
+mod deep_sky;
+mod equatorial;
+mod flash;
+#[cfg(feature = "gaia")]
+mod gaia;
+mod generate;
+mod glint;
+mod grid;
+#[cfg(feature = "hyg")]
+mod hyg;
+#[cfg(feature = "inspector")]
+mod inspector;
+mod labels;
+mod loading;
+mod meteor;
+#[cfg(feature = "picking")]
+mod mod_picking;
+mod nebula;
+mod observer;
+mod overlay;
+mod picking;
+mod plane;
+mod quality;
+mod reflections;
+mod render;
+mod sky;
+mod skybox;
+mod solar_system;
+mod spikes;
+mod starlight;
+mod target;
+mod ui;
+mod vantage;
+
+pub use asset::{StarCatalog, StarCatalogError, StarCatalogLoader};
+pub use bake::{bake_cubemap, bake_equirectangular, BakeOptions};
+pub use body::CelestialBody;
+#[cfg(feature = "bsc")]
+pub use catalog::{bright_star_catalog, bright_star_catalog_slice};
+pub use catalog::{Star, StarId, StarMetadata};
+pub use color::{blackbody_color, color_index_to_temperature, star_color};
+#[cfg(feature = "deep-sky")]
+pub use deep_sky::{deep_sky_objects, DeepSkyKind, DeepSkyObject, DeepSkyPlugin};
+pub use equatorial::{direction_to_radec, precession_from_j2000, radec_to_direction, J2000};
+pub use flash::{StarFlashes, MAX_STAR_FLASHES};
+#[cfg(feature = "gaia")]
+pub use gaia::{
+    decode_gaia_catalog, encode_gaia_catalog, parse_gaia_csv, GaiaError, GaiaStar,
+    StreamGaiaCatalog,
+};
+pub use generate::{ClusterProfile, MilkyWay, StarCluster, StarGenerator};
+pub use glint::{StarGlintMaterial, StarGlintSettings};
+pub use grid::CelestialGrid;
+#[cfg(feature = "hyg")]
+pub use hyg::{parse_hyg_catalog, HygError, HygStar};
+#[cfg(feature = "inspector")]
+pub use inspector::StarfieldInspectorPlugin;
+pub use labels::{StarLabelSelection, StarLabels};
+pub use loading::{
+    AsyncStarfieldBuild, CatalogLoadEvent, CatalogLoadState, FetchStarCatalog, StarfieldReady,
+};
+pub use meteor::{MeteorPlugin, MeteorSettings, Meteors};
+#[cfg(feature = "picking")]
+pub use mod_picking::StarfieldPickingPlugin;
+pub use nebula::Nebula;
+pub use observer::{Observer, SkyTime};
+pub use overlay::{LineStyle, OverlayElement, OverlayPalette, OverlayStyle};
+pub use picking::{StarHover, StarHoverEvent, StarPick, StarPicker};
+pub use plane::{StarPlane, StarPlaneLayer, MAX_STAR_PLANE_LAYERS};
+pub use quality::{StarfieldAutoQuality, StarfieldQuality, StarfieldQualityPreset};
+pub use reflections::StarfieldReflections;
+pub use sky::{
+    AtmosphericExtinction, CloudCover, CloudCoverMapping, Daylight, HorizonHaze, HorizonProfile,
+    MoonGlow, SkyBrightness, StarfieldSun, MAX_HORIZON_SAMPLES,
+};
+pub use skybox::{BakedStarfieldSkybox, StarfieldSkybox};
+pub use solar_system::{SolarSystem, SolarSystemBody, SolarSystemPlugin};
+pub use spikes::{DiffractionSpikeShape, DiffractionSpikes};
+pub use starlight::AmbientStarlight;
+pub use target::StarfieldRenderTarget;
+pub use ui::StarfieldUiBackground;
+pub use vantage::{sky_from_vantage_point, CatalogStar};
 
 /// Conversion between game units and astronomical ones.
-#[derive(Clone, Resource)]
+///
+/// This places the world on the surface of a [`CelestialBody`] and tracks the date there, so that
+/// every starfield rotates with local sidereal time as the game runs. Starfields with an
+/// [`Observer`] are instead seen from their own place on the body, at the date kept by [`SkyTime`].
+#[derive(Clone, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
 pub struct GameUnitsToCelestial {
-    /// The geodetic latitude in degress of the point on the Earth's surface corresponding to the
+    /// The body the sky is observed from.
+    ///
+    /// Defaults to the Earth.
+    pub body: CelestialBody,
+    /// The geodetic latitude in degress of the point on the body's surface corresponding to the
     /// world space origin.
     pub origin_latitude: f32,
-    /// The longitude in degress of the point on the Earth's surface corresponding to the world
+    /// The longitude in degress of the point on the body's surface corresponding to the world
     /// space origin.
     pub origin_longitude: f32,
     /// The heading of the world space coordinate frame in degrees. A value of 0.0 means that the
@@ -57,6 +138,7 @@ pub struct GameUnitsToCelestial {
 impl Default for GameUnitsToCelestial {
     fn default() -> Self {
         Self {
+            body: CelestialBody::Earth,
             origin_latitude: 0.0,
             origin_longitude: 0.0,
             heading: 0.0,
@@ -65,282 +147,1617 @@ impl Default for GameUnitsToCelestial {
         }
     }
 }
+impl GameUnitsToCelestial {
+    /// Rotation from equatorial (ICRF) directions to world space after `elapsed_seconds` of game
+    /// time.
+    pub fn celestial_to_world(&self, elapsed_seconds: f64) -> Mat3 {
+        self.body_to_world() * self.celestial_to_body(elapsed_seconds)
+    }
+
+    /// The Julian date after `elapsed_seconds` of game time.
+    pub fn julian_date(&self, elapsed_seconds: f64) -> f64 {
+        self.initial_julian_date + self.time_scale * elapsed_seconds / 86400.0
+    }
+
+    /// Change `initial_julian_date` so that the Julian date after `elapsed_seconds` of game time
+    /// is `julian_date`, for jumping the sky to a different date or time of night.
+    pub fn set_julian_date(&mut self, julian_date: f64, elapsed_seconds: f64) {
+        self.initial_julian_date = julian_date - self.time_scale * elapsed_seconds / 86400.0;
+    }
+
+    /// Local sidereal time in radians at the world space origin after `elapsed_seconds` of game
+    /// time, which is the right ascension currently crossing the meridian.
+    ///
+    /// For bodies other than the Earth this is the rotation angle of the body's prime meridian
+    /// plus the origin's longitude.
+    pub fn local_sidereal_time(&self, elapsed_seconds: f64) -> f64 {
+        let (_, _, rotation) = self
+            .body
+            .rotation_elements(self.julian_date(elapsed_seconds));
+        (rotation + (self.origin_longitude as f64).to_radians()).rem_euclid(std::f64::consts::TAU)
+    }
+
+    fn body_to_world(&self) -> Mat3 {
+        Mat3::from_euler(
+            EulerRot::ZXY,
+            self.origin_longitude.to_radians(),
+            self.origin_latitude.to_radians(),
+            (180.0 - self.heading).to_radians(),
+        )
+        .transpose()
+    }
+
+    fn celestial_to_body(&self, elapsed_seconds: f64) -> Mat3 {
+        let celestial_to_body = self
+            .body
+            .celestial_to_body(self.julian_date(elapsed_seconds));
+        Mat3::from_cols(
+            celestial_to_body.x_axis.as_vec3(),
+            celestial_to_body.y_axis.as_vec3(),
+            celestial_to_body.z_axis.as_vec3(),
+        )
+    }
+}
+
+/// How large stars appear on screen.
+///
+/// Unless otherwise noted, sizes refer to the brightest stars and fainter stars are drawn
+/// proportionally smaller, as configured by [`StarfieldSettings::magnitude_curve`].
+#[derive(Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StarSize {
+    /// Diameter in physical pixels of the camera's viewport.
+    Pixels(f32),
+    /// Angular diameter in arcminutes, which keeps the sky looking the same regardless of window
+    /// or viewport resolution.
+    Arcminutes(f32),
+    /// Every star is drawn with the same diameter in screen pixels regardless of its magnitude,
+    /// which suits stylized skies and star maps.
+    ///
+    /// If `fov_compensation` is set, the size is specified for a 45° vertical field of view and
+    /// scaled proportionally as the camera zooms in or out.
+    ScreenSpace { pixels: f32, fov_compensation: bool },
+}
+impl Default for StarSize {
+    fn default() -> Self {
+        StarSize::Pixels(4.0)
+    }
+}
+impl StarSize {
+    /// Returns the diameter in pixels, the angular diameter in radians, and whether the pixel
+    /// diameter should be scaled by the camera's zoom.
+    fn shader_params(&self) -> (f32, f32, bool) {
+        match *self {
+            StarSize::Pixels(pixels) => (pixels, 0.0, false),
+            StarSize::Arcminutes(arcminutes) => (0.0, (arcminutes / 60.0).to_radians(), false),
+            StarSize::ScreenSpace {
+                pixels,
+                fov_compensation,
+            } => (pixels, 0.0, fov_compensation),
+        }
+    }
+
+    /// The numeric size, regardless of units.
+    fn value(&self) -> f32 {
+        match *self {
+            StarSize::Pixels(size) | StarSize::Arcminutes(size) => size,
+            StarSize::ScreenSpace { pixels, .. } => pixels,
+        }
+    }
+
+    /// Whether fainter stars should be drawn smaller than brighter ones.
+    fn scales_with_magnitude(&self) -> bool {
+        !matches!(self, StarSize::ScreenSpace { .. })
+    }
+}
+
+/// Geometry each star is drawn with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StarRenderMode {
+    /// A camera facing quad made of two triangles.
+    #[default]
+    Quad,
+    /// A single camera facing triangle around the star. This halves the number of vertices, which
+    /// helps with very large starfields, at the cost of shading about 30% more pixels per star.
+    Triangle,
+    /// A single point primitive per star, which is by far the cheapest mode for hundreds of
+    /// thousands of stars.
+    ///
+    /// WebGPU has no way to set the size of points, so every star covers exactly one pixel and is
+    /// only dimmed by its magnitude. Glow is not drawn.
+    Point,
+}
+
+/// How the size and brightness of stars depend on their apparent magnitude.
+///
+/// Both fall off exponentially for stars fainter than a reference magnitude.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MagnitudeCurve {
+    /// Magnitude of stars drawn at exactly the configured [`StarSize`].
+    ///
+    /// Defaults to about 2.86.
+    pub size_reference_magnitude: f32,
+    /// Natural logarithm of how much smaller stars get for each magnitude they are fainter.
+    ///
+    /// Defaults to 0.35.
+    pub size_falloff: f32,
+    /// Smallest size of any star, as a multiple of the configured [`StarSize`].
+    ///
+    /// Defaults to 0.25.
+    pub min_size: f32,
+    /// Largest size of any star, as a multiple of the configured [`StarSize`]. Raising this lets
+    /// the brightest stars, like Sirius, stand out from the rest.
+    ///
+    /// Defaults to 1.0.
+    pub max_size: f32,
+    /// Magnitude of the faintest stars drawn at full brightness.
+    ///
+    /// Defaults to about 1.43.
+    pub brightness_reference_magnitude: f32,
+    /// Natural logarithm of how much dimmer stars get for each magnitude they are fainter.
+    ///
+    /// Defaults to 0.7.
+    pub brightness_falloff: f32,
+}
+impl Default for MagnitudeCurve {
+    fn default() -> Self {
+        Self {
+            size_reference_magnitude: 1.0 / 0.35,
+            size_falloff: 0.35,
+            min_size: 0.25,
+            max_size: 1.0,
+            brightness_reference_magnitude: 1.0 / 0.7,
+            brightness_falloff: 0.7,
+        }
+    }
+}
+impl MagnitudeCurve {
+    /// Size of a star of the given magnitude, as a multiple of the configured [`StarSize`].
+    pub fn size_scale(&self, magnitude: f32) -> f32 {
+        (self.size_falloff * (self.size_reference_magnitude - magnitude))
+            .exp()
+            .clamp(self.min_size, self.max_size)
+    }
+
+    /// Brightness of a star of the given magnitude, between zero and one.
+    pub fn brightness(&self, magnitude: f32) -> f32 {
+        (self.brightness_falloff * (self.brightness_reference_magnitude - magnitude))
+            .exp()
+            .min(1.0)
+    }
+
+    fn is_valid(&self) -> bool {
+        [
+            self.size_reference_magnitude,
+            self.size_falloff,
+            self.brightness_reference_magnitude,
+            self.brightness_falloff,
+        ]
+        .iter()
+        .all(|v| v.is_finite())
+            && self.min_size > 0.0
+            && self.min_size <= self.max_size
+            && self.max_size.is_finite()
+    }
+}
+
+/// How stars are blended with what is behind them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StarBlendMode {
+    /// Stars are drawn over the background, hiding what is behind them.
+    ///
+    /// Every star is drawn at the same infinite distance, so where two stars of the same starfield
+    /// overlap, the one later in [`Starfield::stars`] is drawn on top. Starfields with
+    /// [`StarfieldSettings::depth_sort`] enabled draw the nearer one on top instead.
+    /// Different starfields are drawn in the order set by [`StarfieldSettings::draw_order`].
+    #[default]
+    Alpha,
+    /// Stars add their light to the background, so overlapping layers brighten each other.
+    Additive,
+}
+
+/// Most stars a starfield can draw and still be sorted by [`StarfieldSettings::depth_sort`].
+pub const MAX_DEPTH_SORTED_STARS: usize = 16_384;
+
+/// Camera distances from a point over which a starfield fades in and out.
+///
+/// Stars are infinitely far away, so fading happens for a starfield as a whole rather than for
+/// individual stars. Giving each region of a game world its own layer with overlapping ranges
+/// crossfades between skies as the camera travels, instead of popping when layers are swapped.
+#[derive(Clone, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarfieldVisibilityRange {
+    /// World space point that distances are measured from.
+    pub center: Vec3,
+    /// Distances over which the starfield fades in as the camera moves away from `center`.
+    ///
+    /// Use `0.0..0.0` to have it fully visible at the center.
+    pub start_margin: Range<f32>,
+    /// Distances over which the starfield fades out as the camera moves away from `center`.
+    ///
+    /// Use `f32::MAX..f32::MAX` to never fade out.
+    pub end_margin: Range<f32>,
+}
+impl StarfieldVisibilityRange {
+    fn is_valid(&self) -> bool {
+        let edges = [
+            self.start_margin.start,
+            self.start_margin.end,
+            self.end_margin.start,
+            self.end_margin.end,
+        ];
+        self.center.is_finite()
+            && edges[0] >= 0.0
+            && edges.windows(2).all(|pair| pair[0] <= pair[1])
+    }
+}
+
+/// Runtime settings controlling how a starfield is drawn.
+///
+/// Changes to this component take effect on the next frame without re-uploading any star data.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarfieldSettings {
+    /// Stars with an apparent magnitude greater than this value are hidden.
+    ///
+    /// Defaults to 8.0 which shows every star in the bundled catalog. Naked eye visibility under
+    /// dark skies is around 6.5.
+    pub visible_magnitude_limit: f32,
+    /// Width in magnitudes of the band just below `visible_magnitude_limit` over which stars fade
+    /// out rather than abruptly disappearing.
+    ///
+    /// Defaults to 0.5.
+    pub magnitude_fade_width: f32,
+    /// How quickly in magnitudes per second the rendered limit follows changes to
+    /// `visible_magnitude_limit`.
+    ///
+    /// Defaults to 4.0. Set to `f32::INFINITY` to apply changes immediately.
+    pub magnitude_limit_speed: f32,
+    /// Number of stars drawn, counting from the start of [`Starfield::stars`].
+    ///
+    /// Changing this doesn't upload anything to the GPU, so it is a cheap way to thin out a
+    /// starfield or fade its stars in over time. For a starfield that is
+    /// [sorted by brightness](Starfield::sorted_by_brightness), the stars drawn are the
+    /// brightest ones. Defaults to `None`, which draws every star.
+    pub visible_count: Option<usize>,
+    /// Whether to store stars on the GPU in 8 bytes each rather than 16, which halves the memory
+    /// and bandwidth used by very large starfields.
+    ///
+    /// Directions lose some precision, up to about a quarter of an arcminute, and magnitudes are
+    /// rounded to about a thousandth. Magnitudes beyond ±32 and color indices beyond ±4 are
+    /// clamped. Changing this uploads the stars again. Ignored on WebGL2, where stars are stored
+    /// in textures. Defaults to false.
+    pub compact_stars: bool,
+    /// Size of the brightest stars.
+    ///
+    /// Defaults to 4 pixels.
+    pub star_size: StarSize,
+    /// How star size and brightness fall off with magnitude.
+    pub magnitude_curve: MagnitudeCurve,
+    /// Upper bound on the on-screen diameter of any star in pixels.
+    ///
+    /// Defaults to `None`.
+    pub max_star_pixels: Option<f32>,
+    /// Whether stars that would exceed `max_star_pixels` should also fade out in proportion to how
+    /// much they were shrunk.
+    ///
+    /// Defaults to false.
+    pub fade_oversized_stars: bool,
+    /// Multiplier applied to the brightness of every star.
+    ///
+    /// Defaults to 1.0.
+    pub intensity: f32,
+    /// Multiplier applied to the light of stars seen by HDR cameras.
+    ///
+    /// Light beyond what a fully opaque star can show makes it brighter than white, so values
+    /// above one let the brightest stars pick up bloom. Cameras without HDR ignore this. Defaults
+    /// to 1.0.
+    pub hdr_luminance: f32,
+    /// Rotation applied to the whole starfield in world space. For a [`StarLayer`] this is
+    /// relative to the orientation of its parent starfield.
+    ///
+    /// Defaults to the identity.
+    pub orientation: Quat,
+    /// Constant rotation in radians per second about the given world space axis, applied on top
+    /// of `orientation`.
+    ///
+    /// Giving each [`StarLayer`] a slightly different drift produces the classic parallax effect of
+    /// menu backgrounds without moving the camera. Ignored while [`ReducedMotion`] is enabled.
+    /// Defaults to zero.
+    pub angular_velocity: Vec3,
+    /// How stars are blended with the background.
+    ///
+    /// Defaults to [`StarBlendMode::Alpha`].
+    pub blend_mode: StarBlendMode,
+    /// Whether each camera draws the stars back to front by their depth in its view, so that
+    /// nearer stars are blended over farther ones where they overlap.
+    ///
+    /// Depths come from the distances set with [`Starfield::with_distances`], and starfields
+    /// without any are drawn in their usual order. Stars are sorted on the CPU every frame for
+    /// every camera, so this only happens while at most [`MAX_DEPTH_SORTED_STARS`] are drawn.
+    /// Defaults to false.
+    pub depth_sort: bool,
+    /// Starfields with a higher draw order are drawn over those with a lower one.
+    ///
+    /// Starfields with the same draw order are drawn in the order of their entities, which is
+    /// usually the order they were spawned in, so each [`StarLayer`] ends up over its parent.
+    /// Defaults to 0.
+    pub draw_order: i32,
+    /// Geometry each star is drawn with.
+    ///
+    /// Defaults to [`StarRenderMode::Quad`].
+    pub render_mode: StarRenderMode,
+    /// Mesh drawn for every star in place of the geometry picked by `render_mode`, such as a low
+    /// poly disc or a stylized star outline.
+    ///
+    /// The mesh is laid out in the plane of the screen, with x and y from -1 to 1 covering the area
+    /// a quad would. Only its positions are used and it must be a triangle list. Each star is
+    /// shaded evenly across the mesh, and stars drawn this way have no glow or diffraction spikes.
+    /// Until the mesh has loaded the starfield is drawn as usual. Defaults to `None`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub star_mesh: Option<Handle<Mesh>>,
+    /// Velocity of the observer relative to the stars in world space, as a fraction of the speed
+    /// of light.
+    ///
+    /// Any non-zero velocity enables relativistic aberration, which crowds stars towards the
+    /// direction of travel, along with Doppler brightening and color shifts. Defaults to zero.
+    pub velocity: Vec3,
+    /// Brightness of a soft glow around each star, relative to the star itself.
+    ///
+    /// The glow is drawn in the same draw call as the stars, from the same star buffer. Defaults
+    /// to 0.0, which disables it.
+    pub glow_intensity: f32,
+    /// Diameter of the glow as a multiple of the star's diameter.
+    ///
+    /// Defaults to 4.0.
+    pub glow_scale: f32,
+    /// Spikes drawn over the brightest stars, as in astronomical photographs.
+    ///
+    /// Defaults to `None`.
+    pub diffraction_spikes: Option<DiffractionSpikes>,
+    /// Fraction by which the brightness of each star flickers over time, to mimic atmospheric
+    /// scintillation.
+    ///
+    /// Every star twinkles with its own phase. Ignored while [`ReducedMotion`] is enabled. Defaults
+    /// to 0.0, which disables twinkling at no cost.
+    pub twinkle_amplitude: f32,
+    /// How many times per second stars twinkle.
+    ///
+    /// Defaults to 1.5.
+    pub twinkle_speed: f32,
+    /// How strongly stars are tinted by their color index, as in [`star_color`].
+    ///
+    /// Defaults to 1.0.
+    pub color_saturation: f32,
+    /// Range of camera positions from which the starfield is visible.
+    ///
+    /// Defaults to `None`, which shows it from everywhere.
+    pub visibility_range: Option<StarfieldVisibilityRange>,
+    /// How stars are laid out when drawn by a 2D camera.
+    ///
+    /// 2D cameras ignore `orientation`, `angular_velocity` and `velocity`, along with the
+    /// [`CloudCover`], [`MoonGlow`], [`HorizonHaze`], [`HorizonProfile`] and
+    /// [`AtmosphericExtinction`] resources. Star sizes given in radians are ignored as well, so
+    /// stars should be sized in pixels.
+    pub plane: StarPlane,
+    /// Whether stars are also drawn into the prepass of cameras with a
+    /// [`NormalPrepass`](bevy::core_pipeline::prepass::NormalPrepass).
+    ///
+    /// Stars write a normal facing the camera at the far plane's depth, so effects reading the
+    /// prepass see them as the distant background they are rather than as missing geometry. A
+    /// depth only prepass is left alone, since its depth buffer already starts out at the far
+    /// plane, and Bevy doesn't have a motion vector prepass yet. Defaults to false.
+    pub write_prepass: bool,
+}
+impl Default for StarfieldSettings {
+    fn default() -> Self {
+        Self {
+            visible_magnitude_limit: 8.0,
+            magnitude_fade_width: 0.5,
+            magnitude_limit_speed: 4.0,
+            star_size: StarSize::default(),
+            magnitude_curve: MagnitudeCurve::default(),
+            max_star_pixels: None,
+            fade_oversized_stars: false,
+            intensity: 1.0,
+            hdr_luminance: 1.0,
+            orientation: Quat::IDENTITY,
+            angular_velocity: Vec3::ZERO,
+            blend_mode: StarBlendMode::Alpha,
+            depth_sort: false,
+            draw_order: 0,
+            render_mode: StarRenderMode::Quad,
+            star_mesh: None,
+            velocity: Vec3::ZERO,
+            glow_intensity: 0.0,
+            glow_scale: 4.0,
+            diffraction_spikes: None,
+            twinkle_amplitude: 0.0,
+            twinkle_speed: 1.5,
+            color_saturation: 1.0,
+            visibility_range: None,
+            plane: StarPlane::default(),
+            write_prepass: false,
+            visible_count: None,
+            compact_stars: false,
+        }
+    }
+}
+
+impl StarfieldSettings {
+    /// Checks that the settings describe a sky that can actually be drawn.
+    pub fn validate(&self) -> Result<(), StarfieldSettingsError> {
+        if self.visible_magnitude_limit.is_nan() {
+            return Err(StarfieldSettingsError::InvalidMagnitudeLimit(
+                self.visible_magnitude_limit,
+            ));
+        }
+        if self.magnitude_fade_width.is_nan() || self.magnitude_fade_width < 0.0 {
+            return Err(StarfieldSettingsError::InvalidFadeWidth(
+                self.magnitude_fade_width,
+            ));
+        }
+        if self.magnitude_limit_speed.is_nan() || self.magnitude_limit_speed < 0.0 {
+            return Err(StarfieldSettingsError::InvalidMagnitudeLimitSpeed(
+                self.magnitude_limit_speed,
+            ));
+        }
+        let size = self.star_size.value();
+        if !(size > 0.0 && size.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidStarSize(self.star_size));
+        }
+        if !self.magnitude_curve.is_valid() {
+            return Err(StarfieldSettingsError::InvalidMagnitudeCurve(
+                self.magnitude_curve,
+            ));
+        }
+        if let Some(max_star_pixels) = self.max_star_pixels {
+            if max_star_pixels.is_nan() || max_star_pixels <= 0.0 {
+                return Err(StarfieldSettingsError::InvalidMaxStarPixels(
+                    max_star_pixels,
+                ));
+            }
+        }
+        if !(self.intensity >= 0.0 && self.intensity.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidIntensity(self.intensity));
+        }
+        if !(self.hdr_luminance >= 0.0 && self.hdr_luminance.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidHdrLuminance(
+                self.hdr_luminance,
+            ));
+        }
+        let speed = self.velocity.length();
+        if speed.is_nan() || speed >= 1.0 {
+            return Err(StarfieldSettingsError::InvalidVelocity(self.velocity));
+        }
+        if !(self.glow_intensity >= 0.0 && self.glow_intensity.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidGlowIntensity(
+                self.glow_intensity,
+            ));
+        }
+        if !(self.glow_scale >= 1.0 && self.glow_scale.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidGlowScale(self.glow_scale));
+        }
+        if let Some(spikes) = &self.diffraction_spikes {
+            if !spikes.is_valid() {
+                return Err(StarfieldSettingsError::InvalidDiffractionSpikes(*spikes));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.twinkle_amplitude) {
+            return Err(StarfieldSettingsError::InvalidTwinkleAmplitude(
+                self.twinkle_amplitude,
+            ));
+        }
+        if !(self.twinkle_speed >= 0.0 && self.twinkle_speed.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidTwinkleSpeed(
+                self.twinkle_speed,
+            ));
+        }
+        if !(self.color_saturation >= 0.0 && self.color_saturation.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidColorSaturation(
+                self.color_saturation,
+            ));
+        }
+        if let Some(range) = &self.visibility_range {
+            if !range.is_valid() {
+                return Err(StarfieldSettingsError::InvalidVisibilityRange(
+                    range.clone(),
+                ));
+            }
+        }
+        if !self.plane.is_valid() {
+            return Err(StarfieldSettingsError::InvalidStarPlane(self.plane.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when starfield settings are invalid.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StarfieldSettingsError {
+    /// The magnitude limit was NaN.
+    InvalidMagnitudeLimit(f32),
+    /// The magnitude fade width was negative or NaN.
+    InvalidFadeWidth(f32),
+    /// The magnitude limit speed was negative or NaN.
+    InvalidMagnitudeLimitSpeed(f32),
+    /// The star size was non-positive or not finite.
+    InvalidStarSize(StarSize),
+    /// The magnitude curve had non-finite values or sizes that weren't positive and in order.
+    InvalidMagnitudeCurve(MagnitudeCurve),
+    /// The maximum star size was non-positive or NaN.
+    InvalidMaxStarPixels(f32),
+    /// The intensity was negative or not finite.
+    InvalidIntensity(f32),
+    /// The HDR luminance was negative or not finite.
+    InvalidHdrLuminance(f32),
+    /// The velocity was not slower than light.
+    InvalidVelocity(Vec3),
+    /// The field of view was not between zero and π for a rectilinear projection, or between zero
+    /// and 2π for a fisheye one.
+    InvalidFieldOfView(f32),
+    /// The glow intensity was negative or not finite.
+    InvalidGlowIntensity(f32),
+    /// The glow scale was less than one or not finite.
+    InvalidGlowScale(f32),
+    /// The diffraction spikes had a non-finite threshold, a non-positive scale or a negative
+    /// intensity.
+    InvalidDiffractionSpikes(DiffractionSpikes),
+    /// The twinkle amplitude was not between zero and one.
+    InvalidTwinkleAmplitude(f32),
+    /// The twinkle speed was negative or not finite.
+    InvalidTwinkleSpeed(f32),
+    /// The color saturation was negative or not finite.
+    InvalidColorSaturation(f32),
+    /// The visibility range had a non-finite center, negative distances or margins out of order.
+    InvalidVisibilityRange(StarfieldVisibilityRange),
+    /// The star plane had a non-positive tile size, no layers or too many of them, or a layer with
+    /// a non-finite parallax, a density outside `0.0..=1.0` or a negative brightness. The densities
+    /// of all layers must also add up to at most 1.0.
+    InvalidStarPlane(StarPlane),
+}
+impl fmt::Display for StarfieldSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagnitudeLimit(v) => write!(f, "visible magnitude limit is {v}"),
+            Self::InvalidFadeWidth(v) => {
+                write!(f, "magnitude fade width must be non-negative, got {v}")
+            }
+            Self::InvalidMagnitudeLimitSpeed(v) => {
+                write!(f, "magnitude limit speed must be non-negative, got {v}")
+            }
+            Self::InvalidStarSize(v) => write!(f, "star size must be positive, got {v:?}"),
+            Self::InvalidMagnitudeCurve(v) => write!(f, "invalid magnitude curve {v:?}"),
+            Self::InvalidMaxStarPixels(v) => {
+                write!(f, "maximum star size must be positive, got {v}")
+            }
+            Self::InvalidIntensity(v) => {
+                write!(f, "intensity must be finite and non-negative, got {v}")
+            }
+            Self::InvalidHdrLuminance(v) => {
+                write!(f, "HDR luminance must be finite and non-negative, got {v}")
+            }
+            Self::InvalidVelocity(v) => {
+                write!(f, "velocity must be less than the speed of light, got {v}")
+            }
+            Self::InvalidFieldOfView(v) => {
+                write!(f, "field of view {v} is out of range for the projection")
+            }
+            Self::InvalidGlowIntensity(v) => {
+                write!(f, "glow intensity must be finite and non-negative, got {v}")
+            }
+            Self::InvalidGlowScale(v) => {
+                write!(f, "glow scale must be finite and at least 1, got {v}")
+            }
+            Self::InvalidDiffractionSpikes(v) => write!(f, "invalid diffraction spikes {v:?}"),
+            Self::InvalidTwinkleAmplitude(v) => {
+                write!(f, "twinkle amplitude must be between 0 and 1, got {v}")
+            }
+            Self::InvalidTwinkleSpeed(v) => {
+                write!(f, "twinkle speed must be finite and non-negative, got {v}")
+            }
+            Self::InvalidColorSaturation(v) => {
+                write!(
+                    f,
+                    "color saturation must be finite and non-negative, got {v}"
+                )
+            }
+            Self::InvalidVisibilityRange(v) => {
+                write!(
+                    f,
+                    "visibility range margins must be ordered and non-negative, got {v:?}"
+                )
+            }
+            Self::InvalidStarPlane(v) => write!(f, "invalid star plane {v:?}"),
+        }
+    }
+}
+impl std::error::Error for StarfieldSettingsError {}
+
+/// Accessibility toggle that disables every animated starfield effect.
+///
+/// Games should enable this to honor a user's or operating system's reduced motion preference.
+/// The sky still turns as simulated time advances, but purely cosmetic animations are compiled out
+/// of the shader entirely.
+#[derive(Clone, Copy, Debug, Default, Resource, ExtractResource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct ReducedMotion(pub bool);
+
+/// Insert this resource to replace the normal look of every star with a diagnostic coloring, for
+/// tuning star counts, sizes and blend modes when the sky is costing too much fill rate.
+///
+/// Remove it again to go back to normal rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Resource)]
+pub enum StarfieldDebugView {
+    /// Every fragment adds a little light, so areas covered by many stars glow brightest.
+    ///
+    /// Transparent corners of star quads count too, since they cost as much to shade.
+    Overdraw,
+    /// Stars are colored from blue to red by their size on screen, from 1 to 64 pixels.
+    PixelSize,
+    /// Stars are colored from blue to red by their apparent magnitude, one color per magnitude
+    /// from -2 to 8.
+    MagnitudeBin,
+}
 
-type DrawStarfield = (
-    SetItemPipeline,
-    SetMeshViewBindGroup<0>,
-    StarfieldRenderCommand,
-);
+/// Insert this resource to change how stars are drawn without forking the crate.
+///
+/// Remove it again to go back to the built-in shader.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct StarfieldShader {
+    /// Shader used in place of the built-in one, which must have the same entry points and bind
+    /// groups. Starting from a copy of `shader.wgsl` in this crate is the easiest way to get them
+    /// right.
+    pub shader: Option<Handle<Shader>>,
+    /// Shader with `#define_import_path bevy_starfield::star_hooks` that the built-in shader
+    /// imports to restyle stars, for changes too small to justify a whole replacement.
+    ///
+    /// It must define these functions:
+    ///
+    /// - `star_hook_color(color: vec3<f32>, magnitude: f32, star_index: u32) -> vec3<f32>` is
+    ///   given the final color of each star.
+    /// - `star_hook_size(size: vec2<f32>, magnitude: f32, star_index: u32) -> vec2<f32>` is
+    ///   given the size of each star in normalized device coordinates.
+    /// - `star_hook_falloff(texcoord: vec2<f32>, falloff: f32) -> f32` is given the opacity of
+    ///   the star across its sprite, with the center of its disc at 0.5, 0.5.
+    ///
+    /// Starfields aren't drawn until the shader has loaded.
+    pub hooks: Option<Handle<Shader>>,
+}
+
+/// Maximum number of bands supported by [`StarfieldModulation`].
+pub const MAX_MODULATION_BANDS: usize = 16;
 
-#[derive(Default, Clone, Resource, ExtractResource, Reflect, ShaderType)]
-#[reflect(Reso  urce)]
-struct StarfieldUniform {
-    pub world_to_ecef: Mat3,
-    pub sidereal_time: f32,
+/// External signal used to pulse the brightness and size of stars.
+///
+/// Insert this resource and update `bands` every frame (for instance from an audio spectrum
+/// analysis) to make the sky react to it. Each star follows one of the bands, so neighboring stars
+/// pulse independently. Ignored while [`ReducedMotion`] is enabled.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct StarfieldModulation {
+    /// Signal level of each band, nominally in the range 0.0 to 1.0. At most
+    /// [`MAX_MODULATION_BANDS`] are used.
+    pub bands: Vec<f32>,
+    /// How much a fully active band increases star brightness, as a fraction.
+    ///
+    /// Defaults to 1.0.
+    pub brightness: f32,
+    /// How much a fully active band increases star size, as a fraction.
+    ///
+    /// Defaults to 0.5.
+    pub size: f32,
+}
+impl Default for StarfieldModulation {
+    fn default() -> Self {
+        Self {
+            bands: Vec::new(),
+            brightness: 1.0,
+            size: 0.5,
+        }
+    }
 }
 
-#[derive(Resource, Default)]
-struct StarfieldUniformBuffer {
-    buffer: UniformBuffer<StarfieldUniform>,
+/// How stars are projected onto a camera's screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect, FromReflect)]
+pub enum StarfieldProjection {
+    /// Use the camera's own projection.
+    ///
+    /// Orthographic projections can't show anything infinitely far away, so orthographic cameras
+    /// instead draw stars as [`StarfieldProjection::Rectilinear`] with a 45° field of view.
+    #[default]
+    Perspective,
+    /// Perspective projection looking the same way as the camera, but with its own field of view.
+    ///
+    /// Useful for orthographic cameras, such as those of strategy games and space maps, to choose
+    /// how much of the sky they show. Zooming the camera doesn't zoom the sky.
+    Rectilinear {
+        /// Vertical angle in radians spanned by the viewport, less than π.
+        field_of_view: f32,
+    },
+    /// Azimuthal equidistant ("fisheye") projection centered on the camera's forward direction,
+    /// as used for planetarium domes and fulldome video.
+    ///
+    /// The visible sky forms a circle inscribed in the viewport. Only the starfield is drawn with
+    /// this projection; the rest of the scene still uses the camera's projection.
+    Fisheye {
+        /// Angle in radians spanned by the diameter of the circle. Use π for a hemispherical dome.
+        field_of_view: f32,
+    },
 }
 
-#[derive(Component)]
-struct StarfieldBindGroup(BindGroup);
+/// Per-camera overrides for how the starfield is drawn.
+///
+/// Add this component to a camera to have it see a different sky than other cameras rendering the
+/// same starfield.
+#[derive(Clone, Component, ExtractComponent, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct StarfieldCameraSettings {
+    /// Replaces [`StarfieldSettings::visible_magnitude_limit`] for this camera if set.
+    ///
+    /// Limits set by the sky, such as a [`SkyBrightness`] or [`Daylight`], and the star budget of
+    /// [`StarfieldQuality::max_stars`] still apply on top of it.
+    pub visible_magnitude_limit: Option<f32>,
+    /// Multiplier applied to the brightness of every star seen by this camera.
+    ///
+    /// Defaults to 1.0.
+    pub intensity: f32,
+    /// Projection used to draw stars for this camera.
+    ///
+    /// Defaults to [`StarfieldProjection::Perspective`].
+    pub projection: StarfieldProjection,
+    /// Whether to output premultiplied alpha.
+    ///
+    /// Enable this when the camera renders into a texture that is later composited with alpha,
+    /// such as a render-to-texture sky or UI image, to avoid dark fringes around stars. Defaults to
+    /// false.
+    pub premultiplied_alpha: bool,
+}
+impl Default for StarfieldCameraSettings {
+    fn default() -> Self {
+        Self {
+            visible_magnitude_limit: None,
+            intensity: 1.0,
+            projection: StarfieldProjection::Perspective,
+            premultiplied_alpha: false,
+        }
+    }
+}
+
+impl StarfieldCameraSettings {
+    /// Checks that the overrides describe a sky that can actually be drawn.
+    pub fn validate(&self) -> Result<(), StarfieldSettingsError> {
+        if let Some(limit) = self.visible_magnitude_limit {
+            if limit.is_nan() {
+                return Err(StarfieldSettingsError::InvalidMagnitudeLimit(limit));
+            }
+        }
+        if !(self.intensity >= 0.0 && self.intensity.is_finite()) {
+            return Err(StarfieldSettingsError::InvalidIntensity(self.intensity));
+        }
+        match self.projection {
+            StarfieldProjection::Perspective => {}
+            StarfieldProjection::Rectilinear { field_of_view } => {
+                if !(field_of_view > 0.0 && field_of_view < std::f32::consts::PI) {
+                    return Err(StarfieldSettingsError::InvalidFieldOfView(field_of_view));
+                }
+            }
+            StarfieldProjection::Fisheye { field_of_view } => {
+                if !(field_of_view > 0.0 && field_of_view <= std::f32::consts::TAU) {
+                    return Err(StarfieldSettingsError::InvalidFieldOfView(field_of_view));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the stars of a starfield come from.
+///
+/// Scenes store this component rather than [`Starfield`] itself, which is rebuilt from it whenever
+/// the source changes or a scene containing it is loaded. Changing the source at runtime, directly
+/// or with a [`RegenerateStarfield`] event, regenerates the stars and uploads them to the GPU again.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StarfieldSource {
+    /// The [`bright_star_catalog`].
+    ///
+    /// The catalog is embedded in the crate, so this is all that needs to be sent for another
+    /// machine to build the same starfield.
+    #[cfg(feature = "bsc")]
+    BrightStarCatalog,
+    /// Stars procedurally generated from a seed by [`StarGenerator::starfield`].
+    Generated {
+        /// How the stars are generated.
+        generator: StarGenerator,
+        /// Seed of the random number generator.
+        seed: u64,
+    },
+    /// An explicit list of stars.
+    Stars {
+        /// The stars.
+        stars: Vec<Star>,
+        /// Stable IDs for each star, or `None` to identify stars by their index.
+        ids: Option<Vec<StarId>>,
+    },
+}
+impl Default for StarfieldSource {
+    /// The [`bright_star_catalog`], or a generated sky if the `bsc` feature is disabled.
+    fn default() -> Self {
+        #[cfg(feature = "bsc")]
+        let source = StarfieldSource::BrightStarCatalog;
+        #[cfg(not(feature = "bsc"))]
+        let source = StarfieldSource::Generated {
+            generator: StarGenerator::default(),
+            seed: 0,
+        };
+        source
+    }
+}
+impl StarfieldSource {
+    pub(crate) fn build(&self) -> Starfield {
+        match self {
+            #[cfg(feature = "bsc")]
+            StarfieldSource::BrightStarCatalog => {
+                Starfield::new(bright_star_catalog()).sorted_by_brightness()
+            }
+            StarfieldSource::Generated { generator, seed } => generator.starfield(*seed),
+            StarfieldSource::Stars { stars, ids: None } => Starfield::new(stars.clone()),
+            StarfieldSource::Stars {
+                stars,
+                ids: Some(ids),
+            } => Starfield::with_ids(stars.clone(), ids.clone()),
+        }
+    }
+}
+
+/// Send this event to replace the stars of a starfield, for instance with a new seed or star count
+/// when traveling to a different star system.
+#[derive(Clone)]
+pub struct RegenerateStarfield {
+    /// The starfield entity to regenerate.
+    pub starfield: Entity,
+    /// Where its new stars come from.
+    pub source: StarfieldSource,
+}
+
+/// A collection of stars drawn as part of the sky.
+///
+/// Stars are projected as directions rather than points, so they sit at an infinite distance:
+/// only the rotation of a camera affects where they appear and moving it never produces parallax,
+/// however far it travels. There is no need to keep the starfield centered on the camera.
+///
+/// Use [`StarfieldBundle`] or [`SpawnStarfieldCommands::spawn_starfield`] to create one. Like
+/// meshes, a starfield with a [`RenderLayers`](bevy::render::view::RenderLayers) component is only
+/// drawn by cameras that share one of its layers, which limits it to some of the cameras in
+/// split-screen games or those with a minimap.
+#[derive(Clone, Component)]
+pub struct Starfield {
+    stars: Arc<[Star]>,
+    ids: Arc<[StarId]>,
+    scales: Option<Arc<[f32]>>,
+    distances: Option<Arc<[f32]>>,
+    metadata: Option<Arc<[StarMetadata]>>,
+    /// Unique for every starfield created from scratch, and kept by edits.
+    source: u64,
+    /// Number of edits made since creation.
+    generation: u64,
+    /// The most recent edits, tagged with the generation they produced.
+    edits: Vec<(u64, Range<usize>)>,
+}
+impl Starfield {
+    /// Number of edits remembered so that they can be uploaded incrementally.
+    const MAX_TRACKED_EDITS: usize = 32;
+
+    /// Create a starfield containing the given stars, identified by their index.
+    pub fn new(stars: Vec<Star>) -> Self {
+        let ids = (0..stars.len() as u32).map(StarId::catalog).collect();
+        Self::from_parts(stars.into(), ids)
+    }
+
+    fn from_parts(stars: Arc<[Star]>, ids: Arc<[StarId]>) -> Self {
+        static NEXT_SOURCE: AtomicU64 = AtomicU64::new(0);
+        Self {
+            stars,
+            ids,
+            scales: None,
+            distances: None,
+            metadata: None,
+            source: NEXT_SOURCE.fetch_add(1, Ordering::Relaxed),
+            generation: 0,
+            edits: Vec::new(),
+        }
+    }
+
+    /// Create a starfield containing the given stars and their IDs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stars` and `ids` have different lengths.
+    pub fn with_ids(stars: Vec<Star>, ids: Vec<StarId>) -> Self {
+        assert_eq!(stars.len(), ids.len(), "every star needs exactly one ID");
+        Self::from_parts(stars.into(), ids.into())
+    }
+
+    /// Give every star its own size, as a multiple of the size set by its [`StarfieldSettings`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly one scale per star.
+    pub fn with_scales(mut self, scales: Vec<f32>) -> Self {
+        assert_eq!(
+            self.stars.len(),
+            scales.len(),
+            "every star needs exactly one scale"
+        );
+        self.scales = Some(scales.into());
+        self
+    }
+
+    /// Give every star a distance from the observer, in any unit, for
+    /// [`StarfieldSettings::depth_sort`].
+    ///
+    /// Stars are still drawn at an infinite distance; this only decides which of two overlapping
+    /// stars is drawn on top. Use `f32::INFINITY` for stars whose distance isn't known.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly one distance per star.
+    pub fn with_distances(mut self, distances: Vec<f32>) -> Self {
+        assert_eq!(
+            self.stars.len(),
+            distances.len(),
+            "every star needs exactly one distance"
+        );
+        self.distances = Some(distances.into());
+        self
+    }
+
+    /// Attach names, designations and other metadata to the stars, in the same order as
+    /// [`Starfield::stars`].
+    ///
+    /// Metadata is never uploaded to the GPU, so it has no effect on rendering.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly one entry per star.
+    pub fn with_metadata(mut self, metadata: Vec<StarMetadata>) -> Self {
+        assert_eq!(
+            self.stars.len(),
+            metadata.len(),
+            "every star needs exactly one metadata entry"
+        );
+        self.metadata = Some(metadata.into());
+        self
+    }
+
+    /// Reorder the stars brightest first, carrying along their IDs, scales, distances and metadata.
+    ///
+    /// Drawing only the first stars of a sorted starfield, as [`StarfieldSettings::visible_count`]
+    /// and [`StarfieldQuality::max_stars`] do, then keeps the brightest of them. Stars keep their
+    /// IDs but not their indices.
+    pub fn sorted_by_brightness(self) -> Self {
+        let mut order: Vec<usize> = (0..self.stars.len()).collect();
+        order.sort_by(|&a, &b| self.stars[a].magnitude.total_cmp(&self.stars[b].magnitude));
+        let stars = order.iter().map(|&i| self.stars[i]).collect();
+        let ids = order.iter().map(|&i| self.ids[i]).collect();
+        let mut sorted = Self::from_parts(stars, ids);
+        sorted.scales = self
+            .scales
+            .map(|scales| order.iter().map(|&i| scales[i]).collect());
+        sorted.distances = self
+            .distances
+            .map(|distances| order.iter().map(|&i| distances[i]).collect());
+        sorted.metadata = self
+            .metadata
+            .map(|metadata| order.iter().map(|&i| metadata[i].clone()).collect());
+        sorted
+    }
+
+    /// Whether the stars are ordered brightest first.
+    pub fn is_sorted_by_brightness(&self) -> bool {
+        self.stars
+            .windows(2)
+            .all(|pair| pair[0].magnitude <= pair[1].magnitude)
+    }
+
+    /// Overwrite the stars starting at index `start`.
+    ///
+    /// The stars are edited in place and only the replaced range is uploaded to the GPU again,
+    /// which avoids a hitch when part of a very large starfield changes. Editing a starfield that
+    /// has been cloned copies its stars first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the replaced range extends past the end of the starfield.
+    pub fn replace_stars(&mut self, start: usize, stars: &[Star]) {
+        let range = start..start + stars.len();
+        self.write_stars(start, stars);
+
+        self.generation += 1;
+        if self.edits.len() == Self::MAX_TRACKED_EDITS {
+            self.edits.remove(0);
+        }
+        self.edits.push((self.generation, range));
+    }
+
+    /// Copy `stars` over the stars starting at index `start`, without recording an edit.
+    fn write_stars(&mut self, start: usize, stars: &[Star]) {
+        let range = start..start + stars.len();
+        match Arc::get_mut(&mut self.stars) {
+            Some(own) => own[range].copy_from_slice(stars),
+            None => {
+                let mut updated = self.stars.to_vec();
+                updated[range].copy_from_slice(stars);
+                self.stars = updated.into();
+            }
+        }
+    }
+
+    /// Clone that doesn't share its stars with this starfield, so that editing either one never
+    /// has to copy them.
+    fn detached(&self) -> Self {
+        Self {
+            stars: self.stars.to_vec().into(),
+            ..self.clone()
+        }
+    }
+
+    /// Bring a [detached](Self::detached) copy of a starfield up to date with `latest`, copying
+    /// only the stars edited since if they are all known.
+    fn update_from(&mut self, latest: &Starfield) {
+        let same_scales = match (&self.scales, &latest.scales) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        match latest.edits_since(self.source, self.generation) {
+            Some(edits) if same_scales && Arc::ptr_eq(&self.ids, &latest.ids) => {
+                for range in edits {
+                    self.write_stars(range.start, &latest.stars[range]);
+                }
+                self.generation = latest.generation;
+                self.edits = latest.edits.clone();
+                self.distances = latest.distances.clone();
+                self.metadata = latest.metadata.clone();
+            }
+            _ => *self = latest.detached(),
+        }
+    }
+
+    /// Ranges of stars edited since `generation` of the starfield created as `source`, or `None`
+    /// if they aren't all known.
+    fn edits_since(&self, source: u64, generation: u64) -> Option<Vec<Range<usize>>> {
+        let first_tracked = self.edits.first().map_or(self.generation, |(g, _)| g - 1);
+        if source != self.source || generation < first_tracked || generation > self.generation {
+            return None;
+        }
+        Some(
+            self.edits
+                .iter()
+                .filter(|(g, _)| *g > generation)
+                .map(|(_, range)| range.clone())
+                .collect(),
+        )
+    }
+
+    /// The stars in this starfield.
+    pub fn stars(&self) -> &[Star] {
+        &self.stars
+    }
+
+    /// The IDs of the stars in this starfield, in the same order as [`Starfield::stars`].
+    pub fn ids(&self) -> &[StarId] {
+        &self.ids
+    }
+
+    /// The size of each star relative to the others, if set with [`Starfield::with_scales`].
+    pub fn scales(&self) -> Option<&[f32]> {
+        self.scales.as_deref()
+    }
+
+    /// The distance of each star, if set with [`Starfield::with_distances`].
+    pub fn distances(&self) -> Option<&[f32]> {
+        self.distances.as_deref()
+    }
+
+    /// Metadata of every star, if set with [`Starfield::with_metadata`].
+    pub fn metadata(&self) -> Option<&[StarMetadata]> {
+        self.metadata.as_deref()
+    }
+
+    /// Metadata of the star at `index`, such as one returned by [`StarPicker`], if the starfield
+    /// has any.
+    pub fn star_metadata(&self, index: usize) -> Option<&StarMetadata> {
+        self.metadata.as_deref()?.get(index)
+    }
+
+    /// Index of the star with the given ID, if it is part of this starfield.
+    pub fn index_of(&self, id: StarId) -> Option<usize> {
+        self.ids.iter().position(|&i| i == id)
+    }
+
+    /// Index of the star closest to `direction`, given in the starfield's own celestial frame, if
+    /// any is within `max_angle` radians of it.
+    ///
+    /// Stars at or beyond `magnitude_limit` are skipped, so that only stars which are actually
+    /// drawn can be picked. Use [`StarPicker`] to pick from world space rays instead.
+    pub fn pick(&self, direction: Vec3, max_angle: f32, magnitude_limit: f32) -> Option<usize> {
+        let direction = direction.normalize_or_zero();
+        let min_cos = max_angle.cos();
+        let mut best: Option<(f32, usize)> = None;
+        for (index, star) in self.stars.iter().enumerate() {
+            if star.magnitude >= magnitude_limit {
+                continue;
+            }
+            let cos = direction.dot(star.direction());
+            let closer = match best {
+                Some((best_cos, _)) => cos > best_cos,
+                None => true,
+            };
+            if cos >= min_cos && closer {
+                best = Some((cos, index));
+            }
+        }
+        best.map(|(_, index)| index)
+    }
+}
+
+/// Components that make up a starfield.
+///
+/// Any number of starfields can be spawned side by side, each with its own settings, such as a
+/// dense layer of faint stars behind a sparse layer of bright ones.
+#[derive(Bundle, Clone)]
+pub struct StarfieldBundle {
+    /// The stars, built from `source`.
+    pub starfield: Starfield,
+    /// Where the stars come from. The starfield is rebuilt whenever this changes.
+    pub source: StarfieldSource,
+    /// How the starfield is drawn.
+    pub settings: StarfieldSettings,
+    /// Name of the entity.
+    pub name: Name,
+}
+impl Default for StarfieldBundle {
+    fn default() -> Self {
+        Self::new(StarfieldSource::default(), StarfieldSettings::default())
+    }
+}
+impl StarfieldBundle {
+    /// Create a bundle that draws the stars from `source` with `settings`.
+    pub fn new(source: StarfieldSource, settings: StarfieldSettings) -> Self {
+        Self {
+            starfield: source.build(),
+            source,
+            settings,
+            name: Name::new("Starfield"),
+        }
+    }
+
+    /// Create a bundle that starts out without any stars and builds them from `source` in the
+    /// background, as described for [`AsyncStarfieldBuild`].
+    pub fn new_async(
+        source: StarfieldSource,
+        settings: StarfieldSettings,
+    ) -> (Self, AsyncStarfieldBuild) {
+        let bundle = Self {
+            starfield: Starfield::new(Vec::new()),
+            source,
+            settings,
+            name: Name::new("Starfield"),
+        };
+        (bundle, AsyncStarfieldBuild)
+    }
+}
+
+/// Description of a starfield to spawn with [`SpawnStarfieldCommands::spawn_starfield`].
+///
+/// With the `serde` feature enabled, descriptors can be serialized so that a server can dictate the
+/// sky and have every client spawn the same starfield. Nothing in a descriptor depends on random
+/// state, but [`StarfieldSource::Generated`] skies are computed with the standard library's
+/// floating point functions, whose last bits may differ between platforms and compilers. Clients on
+/// different platforms get the same stars with the same [`StarId`]s, but positions and magnitudes
+/// can differ by a rounding error, and stars of almost equal magnitude may then swap places in
+/// brightness order. Refer to stars by ID rather than by index when they need to match across
+/// machines, or send the [`StarfieldSource::Stars`] themselves.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarfieldDescriptor {
+    /// The stars to draw.
+    ///
+    /// Defaults to the [`bright_star_catalog`], or a generated sky if the `bsc` feature is
+    /// disabled.
+    pub source: StarfieldSource,
+    /// How the starfield is drawn.
+    pub settings: StarfieldSettings,
+    /// Additional layers of stars owned by the starfield.
+    ///
+    /// Defaults to none.
+    pub layers: Vec<StarLayer>,
+}
+
+/// A named group of stars that belongs to a starfield but is drawn with its own settings, such as
+/// faint background dust or a handful of bright named stars.
+///
+/// Each layer is spawned as a child entity of its starfield, so it turns with the starfield's
+/// orientation and is despawned along with it.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarLayer {
+    /// Name of the layer's entity.
+    pub name: String,
+    /// The stars in the layer.
+    pub stars: Vec<Star>,
+    /// How the layer is drawn.
+    pub settings: StarfieldSettings,
+}
+
+/// Extension trait for spawning starfields from [`Commands`].
+pub trait SpawnStarfieldCommands<'w, 's> {
+    /// Spawn an entity with everything needed to render the described starfield.
+    ///
+    /// Starfields can be spawned at any point while the app is running, and are removed by
+    /// despawning the returned entity.
+    fn spawn_starfield<'a>(
+        &'a mut self,
+        descriptor: StarfieldDescriptor,
+    ) -> EntityCommands<'w, 's, 'a>;
+}
+impl<'w, 's> SpawnStarfieldCommands<'w, 's> for Commands<'w, 's> {
+    fn spawn_starfield<'a>(
+        &'a mut self,
+        descriptor: StarfieldDescriptor,
+    ) -> EntityCommands<'w, 's, 'a> {
+        let mut entity = self.spawn(StarfieldBundle::new(descriptor.source, descriptor.settings));
+        if !descriptor.layers.is_empty() {
+            entity.with_children(|parent| {
+                for layer in descriptor.layers {
+                    let source = StarfieldSource::Stars {
+                        stars: layer.stars,
+                        ids: None,
+                    };
+                    parent.spawn(StarfieldBundle {
+                        name: Name::new(layer.name),
+                        ..StarfieldBundle::new(source, layer.settings)
+                    });
+                }
+            });
+        }
+        entity
+    }
+}
 
 /// Render a sky filled with stars.
-pub struct StarfieldPlugin;
+///
+/// Starfields are drawn like a skybox: in the opaque pass after all other opaque geometry, at the
+/// far plane, with depth testing but without writing depth. Scene geometry always hides the stars
+/// and transparent objects are blended over them, regardless of how either is sorted. Starfields
+/// are left out of prepasses unless [`StarfieldSettings::write_prepass`] is set.
+///
+/// Every projection puts stars at a depth of zero, which is the far plane of Bevy's reversed depth
+/// buffer, so nothing about a starfield gives it a distance from the camera. Settings that make
+/// stars look nearer or farther, like their size, are purely cosmetic.
+pub struct StarfieldPlugin {
+    /// Whether to spawn a starfield showing the [`bright_star_catalog`] when the plugin is added,
+    /// or a generated sky if the `bsc` feature is disabled.
+    ///
+    /// Turn this off to only add the rendering machinery and spawn starfields yourself, when and
+    /// where they are needed. Defaults to true.
+    pub spawn_on_startup: bool,
+}
+impl Default for StarfieldPlugin {
+    fn default() -> Self {
+        Self {
+            spawn_on_startup: true,
+        }
+    }
+}
+impl StarfieldPlugin {
+    /// Show the real night sky: every star visible to the naked eye, from the embedded
+    /// [`bright_star_catalog`], with nothing to download.
+    ///
+    /// Combine it with a [`GameUnitsToCelestial`] for the player's place on the Earth and the date
+    /// to see the stars where they actually are.
+    #[cfg(feature = "bsc")]
+    pub fn real_sky() -> Self {
+        Self {
+            spawn_on_startup: true,
+        }
+    }
+}
 impl Plugin for StarfieldPlugin {
     fn build(&self, app: &mut App) {
         let mut shaders = app.world.resource_mut::<Assets<Shader>>();
-        let starfield_shader = Shader::from_wgsl(include_str!("shader.wgsl"));
-        shaders.set_untracked(STARFIELD_SHADER_HANDLE, starfield_shader);
+        let glint_shader = Shader::from_wgsl(include_str!("glint.wgsl"));
+        shaders.set_untracked(glint::GLINT_SHADER_HANDLE, glint_shader);
+        let nebula_shader = Shader::from_wgsl(include_str!("nebula.wgsl"));
+        shaders.set_untracked(nebula::NEBULA_SHADER_HANDLE, nebula_shader);
+        let skybox_shader = Shader::from_wgsl(include_str!("skybox.wgsl"));
+        shaders.set_untracked(skybox::SKYBOX_SHADER_HANDLE, skybox_shader);
+        let grid_shader = Shader::from_wgsl(include_str!("grid.wgsl"));
+        shaders.set_untracked(grid::GRID_SHADER_HANDLE, grid_shader);
 
         app.insert_resource(ClearColor(Color::BLACK))
             .init_resource::<GameUnitsToCelestial>()
-            .init_resource::<StarfieldUniformBuffer>();
+            .init_resource::<SkyTime>()
+            .init_resource::<ReducedMotion>()
+            .init_resource::<StarfieldQuality>()
+            .init_resource::<OverlayStyle>()
+            .add_plugin(MaterialPlugin::<StarGlintMaterial>::default())
+            .add_plugin(MaterialPlugin::<nebula::NebulaMaterial>::default())
+            .add_plugin(MaterialPlugin::<grid::CelestialGridMaterial>::default())
+            // The prepass would place the skybox cube at its actual size instead of the far plane.
+            .add_plugin(MaterialPlugin::<skybox::SkyboxMaterial> {
+                prepass_enabled: false,
+                ..default()
+            })
+            .register_type::<StarfieldSettings>()
+            .register_type::<StarSize>()
+            .register_type::<StarBlendMode>()
+            .register_type::<StarRenderMode>()
+            .register_type::<MagnitudeCurve>()
+            .register_type::<StarfieldCameraSettings>()
+            .register_type::<StarfieldProjection>()
+            .register_type::<StarfieldSource>()
+            .register_type::<StarGenerator>()
+            .register_type::<MilkyWay>()
+            .register_type::<Option<MilkyWay>>()
+            .register_type::<StarCluster>()
+            .register_type::<ClusterProfile>()
+            .register_type::<Vec<StarCluster>>()
+            .register_type::<Star>()
+            .register_type::<StarId>()
+            .register_type::<StarMetadata>()
+            .register_type::<Vec<Star>>()
+            .register_type::<Option<Vec<StarId>>>()
+            .register_type::<Option<f32>>()
+            .register_type::<StarfieldVisibilityRange>()
+            .register_type::<StarPlane>()
+            .register_type::<StarPlaneLayer>()
+            .register_type::<Vec<StarPlaneLayer>>()
+            .register_type::<Option<StarfieldVisibilityRange>>()
+            .register_type::<DiffractionSpikes>()
+            .register_type::<DiffractionSpikeShape>()
+            .register_type::<Option<DiffractionSpikes>>()
+            .register_type::<StarfieldReflections>()
+            .register_type::<StarfieldSkybox>()
+            .register_type::<StarfieldUiBackground>()
+            .register_type::<StarfieldRenderTarget>()
+            .register_type::<Nebula>()
+            .register_type::<CelestialGrid>()
+            .register_type::<GameUnitsToCelestial>()
+            .register_type::<Observer>()
+            .register_type::<SkyTime>()
+            .register_type::<CelestialBody>()
+            .register_type::<ReducedMotion>()
+            .register_type::<StarfieldQuality>()
+            .register_type::<StarfieldQualityPreset>()
+            .register_type::<StarfieldAutoQuality>()
+            .register_type::<Option<StarRenderMode>>()
+            .register_type::<Option<usize>>()
+            .register_type::<StarfieldModulation>()
+            .register_type::<CloudCover>()
+            .register_type::<CloudCoverMapping>()
+            .register_type::<MoonGlow>()
+            .register_type::<HorizonProfile>()
+            .register_type::<HorizonHaze>()
+            .register_type::<AtmosphericExtinction>()
+            .register_type::<SkyBrightness>()
+            .register_type::<Daylight>()
+            .register_type::<StarfieldSun>()
+            .register_type::<AmbientStarlight>()
+            .add_event::<RegenerateStarfield>()
+            .add_asset::<StarCatalog>()
+            .init_asset_loader::<StarCatalogLoader>()
+            .add_system(asset::apply_star_catalogs.before(rebuild_starfields))
+            .add_system(regenerate_starfields.before(rebuild_starfields))
+            .add_system(rebuild_starfields)
+            .add_system(validate_starfield_settings)
+            .add_system(flash::update_star_flashes)
+            .add_system(reflections::bake_starfield_reflections)
+            .add_system(skybox::bake_starfield_skyboxes)
+            .add_system(skybox::remove_skybox_cubes)
+            .add_system(nebula::build_nebulae)
+            .add_system(nebula::orient_nebulae)
+            .add_system(grid::build_celestial_grids)
+            .add_system(observer::advance_sky_time.before(observer::orient_observers))
+            .add_system(observer::orient_observers)
+            .add_system(grid::orient_celestial_grids.after(observer::orient_observers))
+            .add_system(starlight::update_ambient_starlight)
+            .add_system(quality::adjust_starfield_quality)
+            .add_system(sky::follow_starfield_sun)
+            .init_resource::<CatalogLoadState>()
+            .add_event::<CatalogLoadEvent>()
+            .add_system(loading::start_catalog_fetches)
+            .add_system(loading::poll_catalog_fetches.after(loading::start_catalog_fetches))
+            .add_event::<StarfieldReady>()
+            .add_system(loading::poll_starfield_builds.after(rebuild_starfields))
+            .add_event::<StarHoverEvent>()
+            .add_system(picking::update_star_hover.after(observer::orient_observers))
+            .add_system(labels::update_star_labels)
+            .add_system(
+                labels::position_star_labels
+                    .after(labels::update_star_labels)
+                    .after(observer::orient_observers),
+            )
+            .add_system(target::update_render_targets)
+            .add_system(ui::update_ui_backgrounds)
+            .add_system(ui::cleanup_ui_cameras.after(ui::update_ui_backgrounds));
+        #[cfg(feature = "gaia")]
+        app.add_system(gaia::start_gaia_streams)
+            .add_system(gaia::poll_gaia_streams.after(gaia::start_gaia_streams));
 
-        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
-            render_app
-                .init_resource::<StarfieldPipeline>()
-                .init_resource::<StarfieldUniformBuffer>()
-                .init_resource::<SpecializedRenderPipelines<StarfieldPipeline>>()
-                .add_system(extract_starfield.in_schedule(ExtractSchedule))
-                .add_system(prepare_starfield.in_set(RenderSet::Prepare))
-                .add_system(queue_starfield.in_set(RenderSet::Queue))
-                .add_render_command::<Opaque3d, DrawStarfield>();
+        if self.spawn_on_startup {
+            app.world.spawn(StarfieldBundle::default());
         }
+
+        render::build(app);
     }
 }
 
-fn extract_starfield(mut commands: Commands, r: Extract<Res<GameUnitsToCelestial>>) {
-    commands.insert_resource(r.clone())
+fn regenerate_starfields(
+    mut commands: Commands,
+    mut events: EventReader<RegenerateStarfield>,
+    mut sources: Query<&mut StarfieldSource>,
+) {
+    for event in events.iter() {
+        match sources.get_mut(event.starfield) {
+            Ok(mut source) => *source = event.source.clone(),
+            Err(_) => {
+                if let Some(mut entity) = commands.get_entity(event.starfield) {
+                    entity.insert((event.source.build(), event.source.clone()));
+                }
+            }
+        }
+    }
 }
 
-fn prepare_starfield(
-    render_device: Res<RenderDevice>,
-    render_queue: Res<RenderQueue>,
-    mut starfield_buffer: ResMut<StarfieldUniformBuffer>,
-    game_units_to_celestial: Res<GameUnitsToCelestial>,
-    time: Res<Time>,
-) {
-    let buffer = starfield_buffer.buffer.get_mut();
-
-    buffer.world_to_ecef = /*Mat3::from_cols(
-        Vec3::new(0.0, 1.0, 0.0),
-        Vec3::new(1.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -1.0),
-    ).transpose();*/
-        Mat3::from_euler(EulerRot::ZXY,
-        game_units_to_celestial.origin_longitude.to_radians(),
-        game_units_to_celestial.origin_latitude.to_radians(),
-        (180.0-game_units_to_celestial.heading).to_radians(),
-    )
-    .transpose();
-    buffer.sidereal_time = astro::mn_sidr(
-        game_units_to_celestial.initial_julian_date
-            + game_units_to_celestial.time_scale * time.elapsed_seconds_f64() / 86400.0,
-    ) as f32;
-
-    starfield_buffer
-        .buffer
-        .write_buffer(&render_device, &render_queue);
-}
-
-fn queue_starfield(
+/// Rebuild the stars of starfields whose [`StarfieldSource`] changed without the [`Starfield`]
+/// being replaced alongside it, or that were loaded from a scene without them.
+fn rebuild_starfields(
     mut commands: Commands,
-    starfield_pipeline: Res<StarfieldPipeline>,
-    starfield_buffer: Res<StarfieldUniformBuffer>,
-    mut pipelines: ResMut<SpecializedRenderPipelines<StarfieldPipeline>>,
-    pipeline_cache: Res<PipelineCache>,
-    draw_functions: Res<DrawFunctions<Opaque3d>>,
-    render_device: Res<RenderDevice>,
-    view_uniforms: Res<ViewUniforms>,
-    msaa: Res<Msaa>,
-    mut views: Query<(Entity, &mut RenderPhase<Opaque3d>, &ViewTarget)>,
+    sources: Query<(
+        Entity,
+        Ref<StarfieldSource>,
+        Option<Ref<Starfield>>,
+        Option<Ref<AsyncStarfieldBuild>>,
+    )>,
 ) {
-    let draw_function = draw_functions.read().id::<DrawStarfield>();
-    if let (Some(view_uniforms), Some(starfield_buffer)) = (
-        view_uniforms.uniforms.binding(),
-        starfield_buffer.buffer.binding(),
-    ) {
-        for (entity, mut opaque3d, view_target) in views.iter_mut() {
-            opaque3d.add(Opaque3d {
-                distance: f32::MAX,
-                pipeline: pipelines.specialize(
-                    &pipeline_cache,
-                    &starfield_pipeline,
-                    (msaa.samples(), view_target.main_texture_format()),
-                ),
-                entity: commands.spawn_empty().id(),
-                draw_function,
-            });
+    for (entity, source, starfield, build_async) in sources.iter() {
+        let missing = starfield.is_none();
+        let stale = match (starfield, &build_async) {
+            // Starfields spawned to be built asynchronously come with an empty placeholder.
+            (Some(starfield), Some(build_async)) if build_async.is_added() => {
+                starfield.stars().is_empty()
+            }
+            (Some(starfield), _) => source.is_changed() && !starfield.is_changed(),
+            (None, _) => true,
+        };
+        if !stale {
+            continue;
+        }
+        if build_async.is_some() {
+            loading::build_starfield_async(&mut commands, entity, &source);
+            if missing {
+                commands.entity(entity).insert(Starfield::new(Vec::new()));
+            }
+        } else {
+            commands.entity(entity).insert(source.build());
+        }
+    }
+}
 
-            commands
-                .entity(entity)
-                .insert(StarfieldBindGroup(render_device.create_bind_group(
-                    &BindGroupDescriptor {
-                        label: Some("starfield_bind_group"),
-                        layout: &starfield_pipeline.stars_layout,
-                        entries: &[
-                            BindGroupEntry {
-                                binding: 0,
-                                resource: view_uniforms.clone(),
-                            },
-                            BindGroupEntry {
-                                binding: 1,
-                                resource: starfield_buffer.clone(),
-                            },
-                            BindGroupEntry {
-                                binding: 2,
-                                resource: BindingResource::Buffer(BufferBinding {
-                                    buffer: &starfield_pipeline.stars_buffer,
-                                    offset: 0,
-                                    size: None,
-                                }),
-                            },
-                        ],
-                    },
-                )));
-        }
-    }
-}
-
-const STARFIELD_SHADER_HANDLE: HandleUntyped =
-    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 17029892201246543411);
-
-#[derive(Resource)]
-struct StarfieldPipeline {
-    stars_buffer: Buffer,
-    stars_layout: BindGroupLayout,
-}
-impl FromWorld for StarfieldPipeline {
-    fn from_world(world: &mut World) -> Self {
-        let mut system_state: SystemState<(
-            Res<RenderDevice>,
-            Res<DefaultImageSampler>,
-            Res<RenderQueue>,
-        )> = SystemState::new(world);
-        let (render_device, _default_sampler, _render_queue) = system_state.get_mut(world);
-
-        let mut stars = vec![0.0f32; 4 * 9096];
-        bytemuck::cast_slice_mut(&mut stars).copy_from_slice(include_bytes!("../stars.bin"));
-        for star in stars.chunks_mut(4) {
-            let (gal_lat, gal_long) = (star[0] as f64, star[1] as f64);
-            star[0] = crate::astro::dec_frm_gal(gal_long, gal_lat) as f32;
-            star[1] = crate::astro::asc_frm_gal(gal_long, gal_lat) as f32;
-        }
-
-        let stars_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
-            label: Some("starfield_buffer"),
-            contents: bytemuck::cast_slice(&stars),
-            usage: BufferUsages::STORAGE,
-        });
-
-        let stars_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: true,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new(4 * 9096),
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("starfield_layout"),
-        });
+fn validate_starfield_settings(
+    starfields: Query<(Entity, &StarfieldSettings), Changed<StarfieldSettings>>,
+    cameras: Query<&StarfieldCameraSettings, Changed<StarfieldCameraSettings>>,
+    empty_starfields: Query<
+        (
+            Entity,
+            &Starfield,
+            Option<Ref<AsyncStarfieldBuild>>,
+            Option<&loading::StarfieldBuildTask>,
+        ),
+        Changed<Starfield>,
+    >,
+) {
+    for (entity, settings) in starfields.iter() {
+        if let Err(e) = settings.validate() {
+            warn!("Invalid settings for starfield {entity:?}: {e}");
+        }
+    }
+    for (entity, starfield, build_async, task) in empty_starfields.iter() {
+        // Starfields being built asynchronously are empty until the build finishes.
+        let building = task.is_some() || build_async.is_some_and(|build| build.is_added());
+        if starfield.stars().is_empty() && !building {
+            warn!("Starfield {entity:?} has no stars, so nothing is drawn");
+        }
+    }
+    for camera_settings in cameras.iter() {
+        if let Err(e) = camera_settings.validate() {
+            warn!("Invalid starfield camera settings: {e}");
+        }
+    }
+}
 
-        Self {
-            stars_buffer,
-            stars_layout,
-        }
-    }
-}
-impl SpecializedRenderPipeline for StarfieldPipeline {
-    type Key = (u32, TextureFormat);
-    fn specialize(&self, (samples, texture_format): Self::Key) -> RenderPipelineDescriptor {
-        RenderPipelineDescriptor {
-            label: Some("starfield_pipeline".into()),
-            layout: vec![self.stars_layout.clone()],
-            push_constant_ranges: vec![],
-            vertex: VertexState {
-                shader: STARFIELD_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: Vec::new(),
-                entry_point: "vertex".into(),
-                buffers: Vec::new(),
-            },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-                unclipped_depth: false,
-            },
-            depth_stencil: Some(DepthStencilState {
-                format: TextureFormat::Depth32Float,
-                depth_write_enabled: false,
-                depth_compare: CompareFunction::GreaterEqual,
-                stencil: Default::default(),
-                bias: Default::default(),
-            }),
-            multisample: MultisampleState {
-                count: samples,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(FragmentState {
-                shader: STARFIELD_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: Vec::new(),
-                entry_point: "fragment".into(),
-                targets: vec![Some(ColorTargetState {
-                    format: texture_format,
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::ALL,
-                })],
-            }),
-        }
-    }
-}
-
-struct StarfieldRenderCommand;
-impl<P: PhaseItem> RenderCommand<P> for StarfieldRenderCommand {
-    type Param = ();
-    type ViewWorldQuery = (Read<ViewUniformOffset>, Read<StarfieldBindGroup>);
-    type ItemWorldQuery = ();
-
-    fn render<'w>(
-        _item: &P,
-        (view_uniform, bind_group): <<Self::ViewWorldQuery as WorldQuery>::ReadOnly as WorldQuery>::Item<'w>,
-        _entity: <<Self::ItemWorldQuery as WorldQuery>::ReadOnly as WorldQuery>::Item<'w>,
-        _param: <Self::Param as SystemParam>::Item<'w, '_>,
-        pass: &mut TrackedRenderPass<'w>,
-    ) -> RenderCommandResult {
-        pass.set_bind_group(0, &bind_group.0, &[view_uniform.offset]);
-        pass.draw(0..6 * 9096, 0..1);
-        RenderCommandResult::Success
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stars(magnitudes: std::ops::Range<usize>) -> Vec<Star> {
+        magnitudes.map(|m| Star::new(0.0, 0.0, m as f32)).collect()
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn edits_since_lists_later_edits() {
+        let mut starfield = Starfield::new(stars(0..10));
+        starfield.replace_stars(2, &stars(20..23));
+        starfield.replace_stars(5, &stars(30..31));
+
+        let source = starfield.source;
+        assert_eq!(starfield.edits_since(source, 0), Some(vec![2..5, 5..6]));
+        assert_eq!(starfield.edits_since(source, 1), Some(vec![5..6]));
+        assert_eq!(starfield.edits_since(source, 2), Some(vec![]));
+        assert_eq!(&starfield.stars()[2..5], &stars(20..23)[..]);
+        assert_eq!(starfield.stars()[5].magnitude, 30.0);
+    }
+
+    #[test]
+    fn edits_since_forgets_evicted_edits() {
+        let mut starfield = Starfield::new(stars(0..4));
+        for _ in 0..Starfield::MAX_TRACKED_EDITS + 1 {
+            starfield.replace_stars(1, &stars(0..1));
+        }
+
+        let source = starfield.source;
+        assert_eq!(starfield.edits_since(source, 0), None);
+        assert_eq!(
+            starfield.edits_since(source, 1).map(|edits| edits.len()),
+            Some(Starfield::MAX_TRACKED_EDITS)
+        );
+    }
+
+    #[test]
+    fn edits_since_rejects_other_starfields() {
+        let mut starfield = Starfield::new(stars(0..4));
+        let other = Starfield::new(stars(0..4));
+        starfield.replace_stars(1, &stars(0..1));
+
+        assert_eq!(starfield.edits_since(other.source, 0), None);
+        assert_eq!(starfield.edits_since(starfield.source, 2), None);
+        // Rebuilding from the same stars starts a new history.
+        let rebuilt = Starfield::new(starfield.stars().to_vec());
+        assert_eq!(rebuilt.edits_since(starfield.source, 0), None);
+    }
+
+    #[test]
+    fn replace_stars_leaves_clones_unchanged() {
+        let mut starfield = Starfield::new(stars(0..4));
+        let clone = starfield.clone();
+        starfield.replace_stars(1, &stars(10..11));
+
+        assert_eq!(starfield.stars()[1].magnitude, 10.0);
+        assert_eq!(clone.stars(), &stars(0..4)[..]);
+    }
+
+    #[test]
+    fn update_from_applies_edits_and_replacements() {
+        let mut starfield = Starfield::new(stars(0..8));
+        let mut copy = starfield.detached();
+        starfield.replace_stars(3, &stars(10..12));
+        copy.update_from(&starfield);
+        assert_eq!(copy.stars(), starfield.stars());
+        assert_eq!(copy.generation, starfield.generation);
+
+        let replacement = Starfield::new(stars(20..25));
+        copy.update_from(&replacement);
+        assert_eq!(copy.stars(), replacement.stars());
+        assert_eq!(copy.source, replacement.source);
+        assert!(!Arc::ptr_eq(&copy.stars, &replacement.stars));
     }
 }
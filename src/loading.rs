@@ -0,0 +1,204 @@
+//! Fetching star catalogs without blocking the app, mainly for web builds where catalog files are
+//! downloaded rather than read from disk.
+
+use std::path::Path;
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, IoTaskPool, Task},
+};
+use futures_lite::future;
+
+use crate::{catalog::stars_from_le_bytes, Star, Starfield, StarfieldSource};
+
+/// Add to a starfield to replace its stars with a catalog file fetched through the
+/// [`AssetServer`]'s IO, which on the web is an HTTP request.
+///
+/// The starfield keeps drawing its current stars, such as a procedural fallback sky, until the
+/// catalog arrives. The file holds one [`Star`] after another as little-endian `f32` declination,
+/// right ascension, magnitude and color index, for 16 bytes per star.
+#[derive(Clone, Component)]
+pub struct FetchStarCatalog {
+    /// Path of the catalog, relative to the asset folder.
+    pub path: String,
+}
+
+/// Progress of the most recently updated catalog fetch, for showing a loading screen.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Resource)]
+pub enum CatalogLoadState {
+    /// No catalog has been fetched.
+    #[default]
+    Idle,
+    /// Waiting for the file to download. Asset IO delivers the whole file at once, so there is no
+    /// finer grained progress than this.
+    Fetching {
+        /// Path of the catalog being fetched.
+        path: String,
+    },
+    /// The file has been fetched and is being decoded in the background.
+    Parsing {
+        /// Size of the fetched file.
+        bytes: usize,
+    },
+    /// The starfield now shows the catalog.
+    Ready {
+        /// Number of stars in the catalog.
+        stars: usize,
+    },
+    /// The catalog couldn't be fetched or decoded. The starfield keeps its previous stars.
+    Failed {
+        /// Description of what went wrong.
+        error: String,
+    },
+}
+
+/// Sent whenever the [`CatalogLoadState`] of a starfield changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogLoadEvent {
+    /// The starfield the catalog is for.
+    pub starfield: Entity,
+    /// The new state of the fetch.
+    pub state: CatalogLoadState,
+}
+
+/// Background work for a [`FetchStarCatalog`] that hasn't finished yet.
+#[derive(Component)]
+pub(crate) enum CatalogTask {
+    Fetching(Task<Result<Vec<u8>, String>>),
+    Parsing(Task<Option<Vec<Star>>>),
+}
+
+pub(crate) fn start_catalog_fetches(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    fetches: Query<(Entity, &FetchStarCatalog), Changed<FetchStarCatalog>>,
+    mut load_state: ResMut<CatalogLoadState>,
+    mut events: EventWriter<CatalogLoadEvent>,
+) {
+    for (entity, fetch) in fetches.iter() {
+        let asset_server = asset_server.clone();
+        let path = fetch.path.clone();
+        let task = IoTaskPool::get().spawn(async move {
+            let asset_io = asset_server.asset_io();
+            asset_io
+                .load_path(Path::new(&path))
+                .await
+                .map_err(|e| e.to_string())
+        });
+        commands.entity(entity).insert(CatalogTask::Fetching(task));
+
+        let state = CatalogLoadState::Fetching {
+            path: fetch.path.clone(),
+        };
+        *load_state = state.clone();
+        events.send(CatalogLoadEvent {
+            starfield: entity,
+            state,
+        });
+    }
+}
+
+pub(crate) fn poll_catalog_fetches(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut CatalogTask)>,
+    mut load_state: ResMut<CatalogLoadState>,
+    mut events: EventWriter<CatalogLoadEvent>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let state = match &mut *task {
+            CatalogTask::Fetching(fetch) => match future::block_on(future::poll_once(fetch)) {
+                None => continue,
+                Some(Ok(bytes)) => {
+                    let len = bytes.len();
+                    let parse = AsyncComputeTaskPool::get()
+                        .spawn(async move { stars_from_le_bytes(&bytes) });
+                    *task = CatalogTask::Parsing(parse);
+                    CatalogLoadState::Parsing { bytes: len }
+                }
+                Some(Err(error)) => {
+                    commands.entity(entity).remove::<CatalogTask>();
+                    CatalogLoadState::Failed { error }
+                }
+            },
+            CatalogTask::Parsing(parse) => match future::block_on(future::poll_once(parse)) {
+                None => continue,
+                Some(Some(stars)) => {
+                    let count = stars.len();
+                    let source = StarfieldSource::Stars { stars, ids: None };
+                    commands
+                        .entity(entity)
+                        .remove::<CatalogTask>()
+                        .insert((source.build(), source));
+                    CatalogLoadState::Ready { stars: count }
+                }
+                Some(None) => {
+                    commands.entity(entity).remove::<CatalogTask>();
+                    CatalogLoadState::Failed {
+                        error: "catalog size is not a multiple of 16 bytes".to_owned(),
+                    }
+                }
+            },
+        };
+
+        *load_state = state.clone();
+        events.send(CatalogLoadEvent {
+            starfield: entity,
+            state,
+        });
+    }
+}
+
+/// Add to a starfield to build its stars from its [`StarfieldSource`] on the
+/// [`AsyncComputeTaskPool`] rather than on the main thread, so that generating or decoding
+/// hundreds of thousands of stars doesn't stall the app.
+///
+/// The starfield keeps drawing its current stars, or none if it was spawned with
+/// [`StarfieldBundle::new_async`](crate::StarfieldBundle::new_async), until the new ones are ready.
+/// A [`StarfieldReady`] event is sent each time a build finishes.
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct AsyncStarfieldBuild;
+
+/// Sent when the stars of a starfield with [`AsyncStarfieldBuild`] have been built and swapped in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StarfieldReady {
+    /// The starfield whose stars were built.
+    pub starfield: Entity,
+    /// Number of stars it now holds.
+    pub stars: usize,
+}
+
+/// Stars being built for an [`AsyncStarfieldBuild`]. Replacing it drops and so cancels the
+/// previous build.
+#[derive(Component)]
+pub(crate) struct StarfieldBuildTask(Task<Starfield>);
+
+/// Start building the stars of `source` in the background for `entity`.
+pub(crate) fn build_starfield_async(
+    commands: &mut Commands,
+    entity: Entity,
+    source: &StarfieldSource,
+) {
+    let source = source.clone();
+    let task = AsyncComputeTaskPool::get().spawn(async move { source.build() });
+    commands.entity(entity).insert(StarfieldBuildTask(task));
+}
+
+pub(crate) fn poll_starfield_builds(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut StarfieldBuildTask)>,
+    mut events: EventWriter<StarfieldReady>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let Some(starfield) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        events.send(StarfieldReady {
+            starfield: entity,
+            stars: starfield.stars().len(),
+        });
+        commands
+            .entity(entity)
+            .remove::<StarfieldBuildTask>()
+            .insert(starfield);
+    }
+}
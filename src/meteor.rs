@@ -0,0 +1,207 @@
+//! Occasional shooting stars streaking across the sky.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    direction_to_radec, GameUnitsToCelestial, ReducedMotion, Star, Starfield, StarfieldBundle,
+    StarfieldSettings, StarfieldSource,
+};
+
+/// Maximum number of meteors in the sky at the same time.
+const MAX_METEORS: usize = 8;
+
+/// Number of stars drawn along each meteor's trail, including its head.
+const TRAIL_POINTS: usize = 24;
+
+/// Magnitude given to unused stars, far too faint to ever be drawn.
+const HIDDEN_MAGNITUDE: f32 = 100.0;
+
+/// How often meteors appear and how they look, used by [`MeteorPlugin`].
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct MeteorSettings {
+    /// Average number of meteors per minute.
+    ///
+    /// Defaults to 2.0.
+    pub rate: f32,
+    /// Direction in the celestial frame that meteors radiate from, as during a meteor shower, or
+    /// `None` for sporadic meteors that head in random directions.
+    ///
+    /// Defaults to `None`.
+    pub radiant: Option<Vec3>,
+    /// Angular speed of meteors in degrees per second.
+    ///
+    /// Defaults to 20.0.
+    pub speed: f32,
+    /// Length of the glowing trail behind each meteor in degrees.
+    ///
+    /// Defaults to 8.0.
+    pub trail_length: f32,
+    /// Magnitudes by which the trail fades from the head of a meteor to the end of its tail.
+    ///
+    /// Defaults to 4.0.
+    pub trail_fade: f32,
+    /// How long each meteor lasts in seconds.
+    ///
+    /// Defaults to 0.8.
+    pub duration: f32,
+    /// Apparent magnitude of the brightest meteors at their peak.
+    ///
+    /// Defaults to -1.0.
+    pub brightest_magnitude: f32,
+    /// Apparent magnitude of the faintest meteors at their peak.
+    ///
+    /// Defaults to 3.0.
+    pub faintest_magnitude: f32,
+}
+impl Default for MeteorSettings {
+    fn default() -> Self {
+        Self {
+            rate: 2.0,
+            radiant: None,
+            speed: 20.0,
+            trail_length: 8.0,
+            trail_fade: 4.0,
+            duration: 0.8,
+            brightest_magnitude: -1.0,
+            faintest_magnitude: 3.0,
+        }
+    }
+}
+
+/// The starfield that [`MeteorPlugin`] draws meteors with, along with the meteors in flight.
+#[derive(Clone, Component, Default)]
+pub struct Meteors {
+    meteors: Vec<Meteor>,
+}
+
+#[derive(Clone)]
+struct Meteor {
+    /// Celestial direction where the meteor appeared.
+    start: Vec3,
+    /// Axis the meteor's great circle path turns around.
+    axis: Vec3,
+    /// Magnitude at the meteor's peak.
+    magnitude: f32,
+    age: f32,
+}
+
+/// Add occasional shooting stars, configured by the [`MeteorSettings`] resource.
+///
+/// Meteors are drawn by a starfield of their own, marked with [`Meteors`], as a row of stars along
+/// each trail that fade towards the tail. New meteors only appear above the horizon, and none do
+/// while [`ReducedMotion`] is enabled.
+pub struct MeteorPlugin;
+impl Plugin for MeteorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MeteorSettings>()
+            .register_type::<MeteorSettings>()
+            .register_type::<Option<Vec3>>()
+            .add_startup_system(spawn_meteors)
+            .add_system(update_meteors);
+    }
+}
+
+fn hidden_stars() -> Vec<Star> {
+    vec![Star::new(0.0, 0.0, HIDDEN_MAGNITUDE); MAX_METEORS * TRAIL_POINTS]
+}
+
+fn spawn_meteors(mut commands: Commands) {
+    commands.spawn((
+        StarfieldBundle {
+            name: Name::new("Meteors"),
+            ..StarfieldBundle::new(
+                StarfieldSource::Stars {
+                    stars: hidden_stars(),
+                    ids: None,
+                },
+                StarfieldSettings::default(),
+            )
+        },
+        Meteors::default(),
+    ));
+}
+
+fn update_meteors(
+    settings: Res<MeteorSettings>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    reduced_motion: Res<ReducedMotion>,
+    time: Res<Time>,
+    mut starfields: Query<(&mut Starfield, &mut Meteors)>,
+) {
+    let mut rng = rand::thread_rng();
+    let zenith = game_units_to_celestial
+        .celestial_to_world(time.elapsed_seconds_f64())
+        .transpose()
+        * Vec3::Y;
+
+    for (mut starfield, mut meteors) in starfields.iter_mut() {
+        for meteor in &mut meteors.meteors {
+            meteor.age += time.delta_seconds();
+        }
+        meteors
+            .meteors
+            .retain(|meteor| meteor.age < settings.duration);
+
+        let chance = settings.rate.max(0.0) / 60.0 * time.delta_seconds();
+        if !reduced_motion.0 && meteors.meteors.len() < MAX_METEORS && rng.gen::<f32>() < chance {
+            // Start somewhere at least 20° above the horizon.
+            let start = loop {
+                let z = rng.gen_range(-1.0f32..=1.0);
+                let (sin, cos) = rng.gen_range(0.0..2.0 * PI).sin_cos();
+                let direction =
+                    Vec3::new(cos * (1.0 - z * z).sqrt(), sin * (1.0 - z * z).sqrt(), z);
+                if direction.dot(zenith) > 20f32.to_radians().sin() {
+                    break direction;
+                }
+            };
+            let axis = match settings.radiant {
+                // Turning around this axis carries the meteor directly away from the radiant.
+                Some(radiant) => radiant.cross(start),
+                None => {
+                    let (u, v) = start.any_orthonormal_pair();
+                    let (sin, cos) = rng.gen_range(0.0..2.0 * PI).sin_cos();
+                    cos * u + sin * v
+                }
+            };
+            if let Some(axis) = axis.try_normalize() {
+                let magnitude_range = settings.faintest_magnitude - settings.brightest_magnitude;
+                meteors.meteors.push(Meteor {
+                    start,
+                    axis,
+                    magnitude: settings.brightest_magnitude + rng.gen::<f32>() * magnitude_range,
+                    age: 0.0,
+                });
+            }
+        }
+
+        let mut stars = hidden_stars();
+        let spacing = (settings.trail_length / TRAIL_POINTS as f32).to_radians();
+        for (meteor, trail) in meteors
+            .meteors
+            .iter()
+            .zip(stars.chunks_exact_mut(TRAIL_POINTS))
+        {
+            // Flare up and die out over the meteor's lifetime.
+            let envelope = (PI * meteor.age / settings.duration).sin().max(1e-3);
+            let head_magnitude = meteor.magnitude - 2.5 * envelope.log10();
+            let travelled = (settings.speed * meteor.age).to_radians();
+            for (k, star) in trail.iter_mut().enumerate() {
+                let angle = travelled - k as f32 * spacing;
+                if angle < 0.0 {
+                    break;
+                }
+                let direction = Quat::from_axis_angle(meteor.axis, angle) * meteor.start;
+                let (right_ascension, declination) = direction_to_radec(direction);
+                let fade = settings.trail_fade * k as f32 / TRAIL_POINTS as f32;
+                *star = Star::new(declination, right_ascension, head_magnitude + fade);
+            }
+        }
+        if starfield.stars().len() == stars.len() && starfield.stars() != stars {
+            starfield.replace_stars(0, &stars);
+        }
+    }
+}
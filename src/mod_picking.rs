@@ -0,0 +1,74 @@
+//! A picking backend for `bevy_mod_picking`, so that starfields receive pointer events.
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_mod_picking::backend::prelude::*;
+
+use crate::StarPicker;
+
+/// Report the star under each pointer to `bevy_mod_picking`, as a hit on its starfield entity.
+///
+/// Starfields then receive the usual pointer events, like `PointerEvent<Click>`. Stars sit behind
+/// everything else in the scene, so any mesh in front of a star takes the hit instead. The hit
+/// position is the world space direction towards the star, which [`StarPicker::pick_direction`]
+/// turns back into the [`StarPick`](crate::StarPick) that was clicked on.
+pub struct StarfieldPickingPlugin {
+    /// Maximum angle in degrees between a pointer and a star for it to count as hit.
+    ///
+    /// Defaults to 1.0.
+    pub threshold: f32,
+}
+impl Default for StarfieldPickingPlugin {
+    fn default() -> Self {
+        Self { threshold: 1.0 }
+    }
+}
+impl Plugin for StarfieldPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(StarPickingThreshold(self.threshold.to_radians()))
+            .add_system(update_star_hits.in_set(PickSet::Backend));
+    }
+}
+
+#[derive(Resource)]
+struct StarPickingThreshold(f32);
+
+fn update_star_hits(
+    threshold: Res<StarPickingThreshold>,
+    pointers: Query<(&PointerId, &PointerLocation)>,
+    cameras: Query<(Entity, &Camera, &GlobalTransform)>,
+    windows: Query<&Window>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    images: Res<Assets<Image>>,
+    picker: StarPicker,
+    mut output: EventWriter<PointerHits>,
+) {
+    for (pointer, location) in pointers.iter() {
+        let Some(location) = location.location() else {
+            continue;
+        };
+        for (camera_entity, camera, transform) in cameras.iter() {
+            if !camera.is_active
+                || !location.is_in_viewport(camera, &windows, &primary_window, &images)
+            {
+                continue;
+            }
+            let Some(ray) = camera.viewport_to_world(transform, location.position) else {
+                continue;
+            };
+            let Some(pick) = picker.pick(ray, threshold.0) else {
+                continue;
+            };
+            let hit = HitData {
+                camera: camera_entity,
+                depth: f32::MAX,
+                position: Some(ray.direction),
+                normal: None,
+            };
+            output.send(PointerHits {
+                pointer: *pointer,
+                picks: vec![(pick.starfield, hit)],
+                order: camera.order,
+            });
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Soft, colored clouds drawn behind the stars.
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::Indices,
+        render_resource::{AsBindGroup, PrimitiveTopology, ShaderRef, ShaderType},
+        view::NoFrustumCulling,
+    },
+};
+
+use crate::{radec_to_direction, GameUnitsToCelestial};
+
+pub(crate) const NEBULA_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 6062402412848307981);
+
+/// A nebula drawn as a large billboard at a fixed direction in the sky.
+///
+/// Spawn an entity with just this component to add a nebula. It is drawn infinitely far away like
+/// the stars, turns with the sky according to [`GameUnitsToCelestial`], and only shows where
+/// nothing else has been drawn. Nebulae blend additively, so overlapping ones combine their light.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct Nebula {
+    /// Right ascension in radians of the center of the nebula.
+    ///
+    /// Defaults to 0.0.
+    pub right_ascension: f32,
+    /// Declination in radians of the center of the nebula.
+    ///
+    /// Defaults to 0.0.
+    pub declination: f32,
+    /// Angular radius in radians of the billboard along its length.
+    ///
+    /// Defaults to 5°.
+    pub radius: f32,
+    /// Width of the billboard relative to its length, to draw elongated objects like galaxies seen
+    /// at an angle.
+    ///
+    /// Defaults to 1.0.
+    pub aspect_ratio: f32,
+    /// Angle in radians from celestial north towards east of the billboard's length, and of the
+    /// top of `texture`.
+    ///
+    /// Defaults to 0.0.
+    pub position_angle: f32,
+    /// Color of the nebula.
+    ///
+    /// Defaults to the deep red of hydrogen emission.
+    pub color: Color,
+    /// Brightness the color is scaled by at the center of the nebula.
+    ///
+    /// Defaults to 0.2.
+    pub brightness: f32,
+    /// Seed of the noise that gives the nebula its shape, so that every nebula looks different.
+    ///
+    /// Defaults to 0.
+    pub seed: u32,
+    /// How strongly the noise breaks the nebula up into wisps, from 0.0 for a smooth glow to 1.0.
+    ///
+    /// Defaults to 1.0.
+    pub wispiness: f32,
+    /// Image to draw instead of noise, tinted by `color`. Its red, green and blue channels are
+    /// added to the sky, so it should have a black background.
+    ///
+    /// Defaults to `None`.
+    pub texture: Option<Handle<Image>>,
+}
+impl Default for Nebula {
+    fn default() -> Self {
+        Self {
+            right_ascension: 0.0,
+            declination: 0.0,
+            radius: 5f32.to_radians(),
+            aspect_ratio: 1.0,
+            position_angle: 0.0,
+            color: Color::rgb(0.9, 0.25, 0.35),
+            brightness: 0.2,
+            seed: 0,
+            wispiness: 1.0,
+            texture: None,
+        }
+    }
+}
+
+#[derive(Clone, AsBindGroup, TypeUuid)]
+#[uuid = "0b1b7d4e-3a5e-4a43-9a30-3f1f2b7c9d61"]
+pub(crate) struct NebulaMaterial {
+    #[uniform(0)]
+    params: NebulaParams,
+    #[texture(1)]
+    #[sampler(2)]
+    texture: Option<Handle<Image>>,
+}
+
+#[derive(Clone, Copy, ShaderType)]
+struct NebulaParams {
+    /// Linear color scaled by brightness.
+    color: Vec3,
+    seed: f32,
+    wispiness: f32,
+    textured: u32,
+}
+
+impl Material for NebulaMaterial {
+    fn vertex_shader() -> ShaderRef {
+        NEBULA_SHADER_HANDLE.typed().into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        NEBULA_SHADER_HANDLE.typed().into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Add
+    }
+}
+
+/// Quad covering the nebula, with positions given as directions in the celestial frame.
+///
+/// As in sky charts, the top of the quad points along the position angle and its right side
+/// towards the west.
+fn nebula_mesh(nebula: &Nebula) -> Mesh {
+    let center = radec_to_direction(nebula.right_ascension, nebula.declination);
+    let north = (Vec3::Z - center * center.z)
+        .try_normalize()
+        .unwrap_or(Vec3::X);
+    let east = north.cross(center);
+    let (sin, cos) = nebula.position_angle.sin_cos();
+    let up = north * cos + east * sin;
+    let right = (north * sin - east * cos) * nebula.aspect_ratio;
+    let half_size = nebula.radius.min(1.5).tan();
+    let corners = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        corners
+            .iter()
+            .map(|&[x, y]| (center + (x * right + y * up) * half_size).to_array())
+            .collect::<Vec<_>>(),
+    );
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        corners
+            .iter()
+            .map(|[x, y]| [(x + 1.0) * 0.5, (1.0 - y) * 0.5])
+            .collect::<Vec<_>>(),
+    );
+    mesh.set_indices(Some(Indices::U16(vec![0, 1, 2, 0, 2, 3])));
+    mesh
+}
+
+pub(crate) fn build_nebulae(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<NebulaMaterial>>,
+    nebulae: Query<
+        (
+            Entity,
+            &Nebula,
+            Option<&Handle<NebulaMaterial>>,
+            Option<&ComputedVisibility>,
+        ),
+        Changed<Nebula>,
+    >,
+) {
+    for (entity, nebula, material, visibility) in nebulae.iter() {
+        let mesh = meshes.add(nebula_mesh(nebula));
+        let new_material = NebulaMaterial {
+            params: NebulaParams {
+                color: Vec4::from(nebula.color.as_linear_rgba_f32()).truncate() * nebula.brightness,
+                // Large seeds would lose the shader's hash to floating point precision.
+                seed: (nebula.seed % 4096) as f32,
+                wispiness: nebula.wispiness.clamp(0.0, 1.0),
+                textured: nebula.texture.is_some().into(),
+            },
+            texture: nebula.texture.clone(),
+        };
+        let material = match material {
+            Some(handle) => materials.set(handle, new_material),
+            None => materials.add(new_material),
+        };
+        // The mesh sits on a unit sphere around the origin rather than where it is drawn, so its
+        // bounding box is meaningless.
+        let mut entity = commands.entity(entity);
+        entity.insert((mesh, material, NoFrustumCulling));
+        if visibility.is_none() {
+            entity.insert(SpatialBundle::default());
+        }
+    }
+}
+
+pub(crate) fn orient_nebulae(
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+    mut nebulae: Query<&mut Transform, With<Nebula>>,
+) {
+    let rotation =
+        Quat::from_mat3(&game_units_to_celestial.celestial_to_world(time.elapsed_seconds_f64()));
+    for mut transform in nebulae.iter_mut() {
+        transform.rotation = rotation;
+    }
+}
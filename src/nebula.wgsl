@@ -0,0 +1,84 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+struct NebulaParams {
+    color: vec3<f32>,
+    seed: f32,
+    wispiness: f32,
+    textured: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> params: NebulaParams;
+
+@group(1) @binding(1)
+var nebula_texture: texture_2d<f32>;
+
+@group(1) @binding(2)
+var nebula_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    // Positions are celestial directions, which the mesh transform rotates into the world. Leaving
+    // out translation and placing the vertex on the far plane keeps it infinitely far away.
+    let direction = (mesh.model * vec4(vertex.position, 0.0)).xyz;
+    out.clip_position = view.view_proj * vec4(direction, 0.0);
+    out.clip_position.z = 0.0;
+    out.uv = vertex.uv;
+    return out;
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7)) + params.seed * 17.13) * 43758.5453);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(hash(i), hash(i + vec2(1.0, 0.0)), u.x),
+        mix(hash(i + vec2(0.0, 1.0)), hash(i + vec2(1.0, 1.0)), u.x),
+        u.y);
+}
+
+fn fbm(p: vec2<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var octave = 0; octave < 5; octave += 1) {
+        value += amplitude * value_noise(q);
+        q = q * 2.03 + vec2(1.7, 9.2);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var light: vec3<f32>;
+    if (params.textured != 0u) {
+        light = textureSample(nebula_texture, nebula_sampler, in.uv).rgb * params.color;
+    } else {
+        // Wispy noise inside a soft disc that fades out well before the edges of the quad.
+        let v = in.uv * 2.0 - 1.0;
+        let warp = vec2(fbm(in.uv * 3.0), fbm(in.uv * 3.0 + vec2(5.2, 1.3)));
+        let density = fbm(in.uv * 4.0 + warp * 2.0);
+        let falloff = smoothstep(1.0, 0.2, length(v + (warp - 0.5) * 0.4));
+        light = params.color * mix(0.5, pow(density, 2.0) * 2.0, params.wispiness) * falloff;
+    }
+
+    // Additive blending: the output is premultiplied with an alpha of zero.
+    return vec4(light, 0.0);
+}
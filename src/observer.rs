@@ -0,0 +1,149 @@
+//! Starfields seen from their own place on a body's surface, turning with local sidereal time.
+
+use bevy::prelude::*;
+
+use crate::GameUnitsToCelestial;
+
+/// Sees a starfield from a point on the surface of [`GameUnitsToCelestial::body`] instead of from
+/// [`GameUnitsToCelestial`]'s origin, at the date kept by [`SkyTime`].
+///
+/// Every frame the entity's [`Transform`] is rotated from equatorial (ICRF) directions to world
+/// space for the local sidereal time at this point, and one is added if the entity has none. The
+/// starfield, its layers, its [`CelestialGrid`](crate::CelestialGrid), labels and picking all
+/// follow that rotation, while the heading of the world still comes from `GameUnitsToCelestial`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct Observer {
+    /// Geodetic latitude in degrees.
+    pub latitude: f32,
+    /// Longitude in degrees, positive to the east.
+    pub longitude: f32,
+}
+impl Observer {
+    /// Rotation from equatorial directions to world space at `julian_date`.
+    pub fn celestial_to_world(
+        &self,
+        game_units_to_celestial: &GameUnitsToCelestial,
+        julian_date: f64,
+    ) -> Mat3 {
+        self.at(game_units_to_celestial, julian_date)
+            .celestial_to_world(0.0)
+    }
+
+    /// Local sidereal time in radians at `julian_date`, which is the right ascension currently
+    /// crossing the observer's meridian.
+    pub fn local_sidereal_time(
+        &self,
+        game_units_to_celestial: &GameUnitsToCelestial,
+        julian_date: f64,
+    ) -> f64 {
+        self.at(game_units_to_celestial, julian_date)
+            .local_sidereal_time(0.0)
+    }
+
+    fn at(
+        &self,
+        game_units_to_celestial: &GameUnitsToCelestial,
+        julian_date: f64,
+    ) -> GameUnitsToCelestial {
+        GameUnitsToCelestial {
+            origin_latitude: self.latitude,
+            origin_longitude: self.longitude,
+            initial_julian_date: julian_date,
+            time_scale: 0.0,
+            ..game_units_to_celestial.clone()
+        }
+    }
+}
+
+/// The date and time seen by every [`Observer`], advanced each frame.
+#[derive(Clone, Copy, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct SkyTime {
+    /// The current [Julian date](https://en.wikipedia.org/wiki/Julian_date).
+    ///
+    /// Defaults to 2451544.5 which corresponds to midnight UTC on January 1st, 2000. Set it to
+    /// jump the sky to a different date or time of night.
+    pub julian_date: f64,
+    /// Scale factor between the game's time and the real world's time.
+    ///
+    /// Defaults to 1.0. Set to 0.0 to stop the sky, or to large values to have stars move quickly
+    /// across it.
+    pub time_scale: f64,
+}
+impl Default for SkyTime {
+    fn default() -> Self {
+        Self {
+            julian_date: 2451544.5,
+            time_scale: 1.0,
+        }
+    }
+}
+
+pub(crate) fn advance_sky_time(time: Res<Time>, mut sky_time: ResMut<SkyTime>) {
+    let days = sky_time.time_scale * time.delta_seconds_f64() / 86400.0;
+    if days != 0.0 {
+        sky_time.julian_date += days;
+    }
+}
+
+pub(crate) fn orient_observers(
+    mut commands: Commands,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    sky_time: Res<SkyTime>,
+    mut observers: Query<(Entity, &Observer, Option<&mut Transform>)>,
+) {
+    for (entity, observer, transform) in observers.iter_mut() {
+        let rotation = Quat::from_mat3(
+            &observer.celestial_to_world(&game_units_to_celestial, sky_time.julian_date),
+        );
+        match transform {
+            Some(mut transform) => transform.rotation = rotation,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(TransformBundle::from_transform(Transform::from_rotation(
+                        rotation,
+                    )));
+            }
+        }
+    }
+}
+
+/// Rotation from equatorial directions to world space for a starfield seen by `observer`, or
+/// `celestial_to_world` from [`GameUnitsToCelestial`] if it has none.
+pub(crate) fn sky_rotation(
+    observer: Option<(&Observer, &Transform)>,
+    celestial_to_world: Mat3,
+) -> Mat3 {
+    observer.map_or(celestial_to_world, |(_, transform)| {
+        Mat3::from_quat(transform.rotation)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radec_to_direction;
+
+    #[test]
+    fn local_sidereal_time_crosses_the_zenith() {
+        let game_units_to_celestial = GameUnitsToCelestial::default();
+        for (latitude, longitude) in [(0.0, 0.0), (51.5, -0.1), (-33.9, 151.2)] {
+            let observer = Observer {
+                latitude,
+                longitude,
+            };
+            for julian_date in [2451544.5, 2451544.8, 2460000.25] {
+                let lst = observer.local_sidereal_time(&game_units_to_celestial, julian_date);
+                let zenith = radec_to_direction(lst as f32, latitude.to_radians());
+                let up =
+                    observer.celestial_to_world(&game_units_to_celestial, julian_date) * zenith;
+                assert!(
+                    up.dot(Vec3::Y) > 0.999,
+                    "{latitude} {longitude} {julian_date}: {up}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Shared styling for chart overlays drawn on top of the sky.
+
+use bevy::prelude::*;
+
+/// A kind of element drawn by a chart overlay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverlayElement {
+    /// Right ascension and declination grid lines.
+    Grid,
+    /// The celestial equator.
+    Equator,
+    /// The ecliptic.
+    Ecliptic,
+    /// Lines connecting the stars of a constellation.
+    ConstellationLine,
+    /// Markers around stars that gameplay wants to draw attention to.
+    Highlight,
+    /// Marker around the currently selected star.
+    Selection,
+    /// Star and object names.
+    Label,
+}
+impl OverlayElement {
+    /// The line pattern used for this element.
+    ///
+    /// Elements are distinguished by line style as well as color, so that no encoding relies on
+    /// hue alone.
+    pub fn line_style(&self) -> LineStyle {
+        match self {
+            OverlayElement::Grid => LineStyle::Dotted,
+            OverlayElement::Ecliptic | OverlayElement::Selection => LineStyle::Dashed,
+            OverlayElement::Equator
+            | OverlayElement::ConstellationLine
+            | OverlayElement::Highlight
+            | OverlayElement::Label => LineStyle::Solid,
+        }
+    }
+}
+
+/// How a line is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LineStyle {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+/// Set of colors used for chart overlays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverlayPalette {
+    /// The default palette.
+    #[default]
+    Standard,
+    /// The [Okabe-Ito](https://jfly.uni-koeln.de/color/) palette, which remains distinguishable
+    /// under all common forms of color vision deficiency.
+    OkabeIto,
+    /// Shades of white and yellow with maximal contrast against the night sky, for users who can't
+    /// rely on color at all.
+    HighContrast,
+}
+impl OverlayPalette {
+    /// The color this palette uses for an overlay element.
+    pub fn color(&self, element: OverlayElement) -> Color {
+        match self {
+            OverlayPalette::Standard => match element {
+                OverlayElement::Grid => Color::rgba(0.3, 0.5, 0.8, 0.5),
+                OverlayElement::Equator => Color::rgb(0.4, 0.6, 1.0),
+                OverlayElement::Ecliptic => Color::rgb(1.0, 0.8, 0.3),
+                OverlayElement::ConstellationLine => Color::rgba(0.5, 0.7, 1.0, 0.7),
+                OverlayElement::Highlight => Color::rgb(0.3, 1.0, 0.4),
+                OverlayElement::Selection => Color::rgb(1.0, 0.3, 0.3),
+                OverlayElement::Label => Color::rgb(0.8, 0.85, 0.9),
+            },
+            OverlayPalette::OkabeIto => match element {
+                OverlayElement::Grid => Color::rgba_u8(0x00, 0x72, 0xB2, 0xA0),
+                OverlayElement::Equator => Color::rgb_u8(0x56, 0xB4, 0xE9),
+                OverlayElement::Ecliptic => Color::rgb_u8(0xE6, 0x9F, 0x00),
+                OverlayElement::ConstellationLine => Color::rgb_u8(0x00, 0x9E, 0x73),
+                OverlayElement::Highlight => Color::rgb_u8(0xF0, 0xE4, 0x42),
+                OverlayElement::Selection => Color::rgb_u8(0xD5, 0x5E, 0x00),
+                OverlayElement::Label => Color::rgb_u8(0xCC, 0x79, 0xA7),
+            },
+            OverlayPalette::HighContrast => match element {
+                OverlayElement::Grid => Color::rgba(1.0, 1.0, 1.0, 0.4),
+                OverlayElement::Equator | OverlayElement::Ecliptic => Color::rgb(0.8, 0.8, 0.8),
+                OverlayElement::ConstellationLine => Color::rgba(1.0, 1.0, 1.0, 0.7),
+                OverlayElement::Highlight | OverlayElement::Selection => Color::rgb(1.0, 1.0, 0.0),
+                OverlayElement::Label => Color::WHITE,
+            },
+        }
+    }
+}
+
+/// Styling applied to every chart overlay.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct OverlayStyle {
+    /// The palette overlay elements are colored with.
+    pub palette: OverlayPalette,
+}
+impl OverlayStyle {
+    /// The color and line style to draw an overlay element with.
+    pub fn style(&self, element: OverlayElement) -> (Color, LineStyle) {
+        (self.palette.color(element), element.line_style())
+    }
+}
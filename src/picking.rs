@@ -0,0 +1,199 @@
+//! Finding the star under the cursor.
+
+use bevy::{ecs::system::SystemParam, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    observer::sky_rotation, GameUnitsToCelestial, Observer, StarId, Starfield, StarfieldSettings,
+};
+
+/// A star picked out of a starfield.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StarPick {
+    /// The starfield entity the star belongs to.
+    pub starfield: Entity,
+    /// Index of the star within [`Starfield::stars`].
+    pub index: usize,
+    /// Stable ID of the star.
+    pub id: StarId,
+}
+
+/// Sent when the cursor starts or stops hovering over a star.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StarHoverEvent {
+    /// The cursor moved onto a star.
+    Enter(StarPick),
+    /// The cursor moved off of a star.
+    Exit(StarPick),
+}
+
+/// Insert this resource to track which star is under the cursor, for tooltips showing star names.
+///
+/// Searching the sky is throttled to `rate` times per second so that it stays cheap even for
+/// large starfields. Changes are reported through [`StarHoverEvent`]s.
+#[derive(Clone, Debug, Resource)]
+pub struct StarHover {
+    /// Camera the cursor is over. If `None`, the first active 3D camera is used.
+    ///
+    /// Defaults to `None`.
+    pub camera: Option<Entity>,
+    /// Maximum angle in degrees between the cursor and a star for it to count as hovered.
+    ///
+    /// Defaults to 1.0.
+    pub threshold: f32,
+    /// Number of times per second to look for the star under the cursor.
+    ///
+    /// Defaults to 10.0.
+    pub rate: f32,
+    /// The star currently under the cursor.
+    pub hovered: Option<StarPick>,
+}
+impl Default for StarHover {
+    fn default() -> Self {
+        Self {
+            camera: None,
+            threshold: 1.0,
+            rate: 10.0,
+            hovered: None,
+        }
+    }
+}
+
+/// Find the visible star closest to the world space `direction`, if any is within `max_angle`
+/// radians of it.
+pub(crate) fn nearest_star<'a>(
+    direction: Vec3,
+    max_angle: f32,
+    celestial_to_world: Mat3,
+    starfields: impl IntoIterator<
+        Item = (
+            Entity,
+            &'a Starfield,
+            &'a StarfieldSettings,
+            Option<(&'a Observer, &'a Transform)>,
+        ),
+    >,
+) -> Option<StarPick> {
+    let direction = direction.normalize_or_zero();
+    let mut best: Option<(f32, StarPick)> = None;
+    for (entity, starfield, settings, observer) in starfields {
+        // Rotate the query into the starfield's frame instead of rotating every star.
+        let rotation =
+            Mat3::from_quat(settings.orientation) * sky_rotation(observer, celestial_to_world);
+        let local = rotation.transpose() * direction;
+        let Some(index) = starfield.pick(local, max_angle, settings.visible_magnitude_limit) else {
+            continue;
+        };
+        let cos = local.dot(starfield.stars()[index].direction());
+        let closer = match best {
+            Some((best_cos, _)) => cos > best_cos,
+            None => true,
+        };
+        if closer {
+            let pick = StarPick {
+                starfield: entity,
+                index,
+                id: starfield.ids()[index],
+            };
+            best = Some((cos, pick));
+        }
+    }
+    best.map(|(_, pick)| pick)
+}
+
+/// System parameter for finding the star a player clicked on, across every starfield.
+///
+/// Pass it a ray from [`Camera::viewport_to_world`] under the cursor. Like [`StarHover`], only
+/// stars brighter than their starfield's magnitude limit can be picked.
+#[derive(SystemParam)]
+pub struct StarPicker<'w, 's> {
+    starfields: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Starfield,
+            &'static StarfieldSettings,
+            Option<(&'static Observer, &'static Transform)>,
+        ),
+    >,
+    game_units_to_celestial: Res<'w, GameUnitsToCelestial>,
+    time: Res<'w, Time>,
+}
+impl StarPicker<'_, '_> {
+    /// The visible star closest to the direction of `ray`, if any is within `max_angle` radians.
+    ///
+    /// Stars are infinitely far away, so only the direction of the ray matters.
+    pub fn pick(&self, ray: Ray, max_angle: f32) -> Option<StarPick> {
+        self.pick_direction(ray.direction, max_angle)
+    }
+
+    /// The visible star closest to the world space `direction`, if any is within `max_angle`
+    /// radians.
+    pub fn pick_direction(&self, direction: Vec3, max_angle: f32) -> Option<StarPick> {
+        nearest_star(
+            direction,
+            max_angle,
+            self.game_units_to_celestial
+                .celestial_to_world(self.time.elapsed_seconds_f64()),
+            self.starfields.iter(),
+        )
+    }
+}
+
+/// World space direction under the cursor of the given camera, or of the first active 3D camera.
+pub(crate) fn cursor_direction(
+    camera: Option<Entity>,
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(Entity, &Camera, &GlobalTransform), With<Camera3d>>,
+) -> Option<Vec3> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (_, camera, transform) = match camera {
+        Some(entity) => cameras.get(entity).ok()?,
+        None => cameras.iter().find(|(_, camera, _)| camera.is_active)?,
+    };
+    Some(camera.viewport_to_world(transform, cursor)?.direction)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_star_hover(
+    hover: Option<ResMut<StarHover>>,
+    mut events: EventWriter<StarHoverEvent>,
+    mut next_check: Local<f64>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(Entity, &Camera, &GlobalTransform), With<Camera3d>>,
+    starfields: Query<(
+        Entity,
+        &Starfield,
+        &StarfieldSettings,
+        Option<(&Observer, &Transform)>,
+    )>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+) {
+    let Some(mut hover) = hover else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+    if now < *next_check {
+        return;
+    }
+    *next_check = now + 1.0 / hover.rate.max(1e-3) as f64;
+
+    let hovered = cursor_direction(hover.camera, &windows, &cameras).and_then(|direction| {
+        nearest_star(
+            direction,
+            hover.threshold.to_radians(),
+            game_units_to_celestial.celestial_to_world(now),
+            starfields.iter(),
+        )
+    });
+    if hovered != hover.hovered {
+        if let Some(previous) = hover.hovered {
+            events.send(StarHoverEvent::Exit(previous));
+        }
+        if let Some(current) = hovered {
+            events.send(StarHoverEvent::Enter(current));
+        }
+        hover.hovered = hovered;
+    }
+}
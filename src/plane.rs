@@ -0,0 +1,111 @@
+//! Laying stars out on a flat, scrolling plane for 2D cameras.
+
+use bevy::prelude::*;
+
+/// Maximum number of layers in a [`StarPlane`].
+pub const MAX_STAR_PLANE_LAYERS: usize = 4;
+
+/// How a starfield is drawn by 2D cameras.
+///
+/// A 2D camera has no sky to look into, so its stars are instead spread over a square tile that
+/// repeats in every direction behind the scene. Each star keeps the same place on the tile,
+/// mapped from its right ascension and declination so that every part of the sky covers an equal
+/// area, and the tile scrolls by a fraction of the camera's movement to give a sense of depth.
+///
+/// Stars can be split between several layers that scroll at different speeds, for the classic
+/// side-scroller effect of near stars drifting past far ones.
+#[derive(Clone, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarPlane {
+    /// Width and height of the tile in world units.
+    ///
+    /// It should be at least as large as the area a camera shows, or stars will appear more than
+    /// once on screen. Defaults to 2048.0.
+    pub tile_size: f32,
+    /// Layers the stars are split between, at most [`MAX_STAR_PLANE_LAYERS`] of them.
+    ///
+    /// Defaults to a single layer holding every star.
+    pub layers: Vec<StarPlaneLayer>,
+}
+impl Default for StarPlane {
+    fn default() -> Self {
+        Self {
+            tile_size: 2048.0,
+            layers: vec![StarPlaneLayer::default()],
+        }
+    }
+}
+impl StarPlane {
+    /// Three layers of far, middle and near stars, with most stars in the far layer and the
+    /// nearer layers sparser, brighter and faster.
+    pub fn layered() -> Self {
+        Self {
+            layers: vec![
+                StarPlaneLayer {
+                    parallax: 0.05,
+                    density: 0.6,
+                    brightness: 0.6,
+                },
+                StarPlaneLayer {
+                    parallax: 0.15,
+                    density: 0.3,
+                    brightness: 0.9,
+                },
+                StarPlaneLayer {
+                    parallax: 0.35,
+                    density: 0.1,
+                    brightness: 1.3,
+                },
+            ],
+            ..default()
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        let total_density: f32 = self.layers.iter().map(|layer| layer.density).sum();
+        self.tile_size > 0.0
+            && self.tile_size.is_finite()
+            && !self.layers.is_empty()
+            && self.layers.len() <= MAX_STAR_PLANE_LAYERS
+            && self.layers.iter().all(StarPlaneLayer::is_valid)
+            && total_density <= 1.0 + 1e-4
+    }
+}
+
+/// One layer of a [`StarPlane`].
+#[derive(Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarPlaneLayer {
+    /// How fast the layer scrolls past as the camera moves, relative to objects in the world: 0.0
+    /// keeps it fixed on screen, while 1.0 scrolls it along with the world.
+    ///
+    /// Defaults to 0.1.
+    pub parallax: f32,
+    /// Fraction of the starfield's stars drawn in this layer, between 0.0 and 1.0. Stars are
+    /// picked at random, and no star is in more than one layer, so the densities of all layers
+    /// may add up to at most 1.0.
+    ///
+    /// Defaults to 1.0.
+    pub density: f32,
+    /// Multiplier on the brightness of the layer's stars.
+    ///
+    /// Defaults to 1.0.
+    pub brightness: f32,
+}
+impl Default for StarPlaneLayer {
+    fn default() -> Self {
+        Self {
+            parallax: 0.1,
+            density: 1.0,
+            brightness: 1.0,
+        }
+    }
+}
+impl StarPlaneLayer {
+    fn is_valid(&self) -> bool {
+        self.parallax.is_finite()
+            && (0.0..=1.0).contains(&self.density)
+            && self.brightness >= 0.0
+            && self.brightness.is_finite()
+    }
+}
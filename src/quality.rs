@@ -0,0 +1,197 @@
+//! A single "star detail" graphics setting covering every starfield.
+
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    render::extract_resource::ExtractResource,
+};
+
+use crate::{StarRenderMode, Starfield};
+
+/// Preset levels of [`StarfieldQuality`], for listing in a graphics settings menu.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StarfieldQualityPreset {
+    /// A few thousand single pixel stars without any effects, for integrated GPUs and the web.
+    Low,
+    /// Tens of thousands of stars drawn as triangles, with glow but no other effects.
+    Medium,
+    /// A hundred thousand stars with every effect.
+    High,
+    /// Every star with every effect, exactly as each starfield's settings describe.
+    #[default]
+    Ultra,
+}
+
+/// Caps on how much detail every starfield is drawn with, for games to offer as a graphics
+/// setting.
+///
+/// Each field can only take detail away from what a starfield's own [`StarfieldSettings`] ask for,
+/// never add it. Changes take effect without re-uploading any stars.
+///
+/// [`StarfieldSettings`]: crate::StarfieldSettings
+#[derive(Clone, Copy, Debug, PartialEq, Resource, ExtractResource, Reflect, FromReflect)]
+#[reflect(Resource)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StarfieldQuality {
+    /// Number of stars drawn per starfield. If a starfield has more, only the brightest ones are
+    /// drawn. This is cheapest for starfields whose stars are sorted brightest first, where the
+    /// rest are skipped without running any shaders for them. The cap holds for cameras with
+    /// their own [`StarfieldCameraSettings::visible_magnitude_limit`] as well.
+    ///
+    /// Defaults to `None`, which draws every star.
+    ///
+    /// [`StarfieldCameraSettings::visible_magnitude_limit`]: crate::StarfieldCameraSettings::visible_magnitude_limit
+    pub max_stars: Option<usize>,
+    /// Geometry every star is drawn with, in place of each starfield's own.
+    ///
+    /// Defaults to `None`, which keeps each starfield's own.
+    pub render_mode: Option<StarRenderMode>,
+    /// Whether glow sprites are drawn around bright stars.
+    ///
+    /// Defaults to true.
+    pub glow: bool,
+    /// Whether diffraction spikes are drawn.
+    ///
+    /// Defaults to true.
+    pub diffraction_spikes: bool,
+    /// Whether stars twinkle.
+    ///
+    /// Defaults to true.
+    pub twinkle: bool,
+}
+impl Default for StarfieldQuality {
+    fn default() -> Self {
+        Self::from_preset(StarfieldQualityPreset::Ultra)
+    }
+}
+impl StarfieldQuality {
+    /// The caps of a preset level.
+    pub fn from_preset(preset: StarfieldQualityPreset) -> Self {
+        match preset {
+            StarfieldQualityPreset::Low => Self {
+                max_stars: Some(2_000),
+                render_mode: Some(StarRenderMode::Point),
+                glow: false,
+                diffraction_spikes: false,
+                twinkle: false,
+            },
+            StarfieldQualityPreset::Medium => Self {
+                max_stars: Some(20_000),
+                render_mode: Some(StarRenderMode::Triangle),
+                glow: true,
+                diffraction_spikes: false,
+                twinkle: false,
+            },
+            StarfieldQualityPreset::High => Self {
+                max_stars: Some(100_000),
+                render_mode: None,
+                glow: true,
+                diffraction_spikes: true,
+                twinkle: true,
+            },
+            StarfieldQualityPreset::Ultra => Self {
+                max_stars: None,
+                render_mode: None,
+                glow: true,
+                diffraction_spikes: true,
+                twinkle: true,
+            },
+        }
+    }
+}
+impl From<StarfieldQualityPreset> for StarfieldQuality {
+    fn from(preset: StarfieldQualityPreset) -> Self {
+        Self::from_preset(preset)
+    }
+}
+
+/// Insert this resource to have [`StarfieldQuality::max_stars`] follow the frame rate, drawing
+/// fewer stars while frames take longer than the target and more again once there is headroom.
+///
+/// Frame times are read from the [`FrameTimeDiagnosticsPlugin`], which must be added to the app
+/// for this to do anything. A slow frame rate doesn't always mean rendering is the bottleneck, so
+/// games that are limited by their own simulation may want to raise `target_frame_time` or leave
+/// this out entirely.
+#[derive(Clone, Copy, Debug, PartialEq, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct StarfieldAutoQuality {
+    /// Frame time in seconds to stay under.
+    ///
+    /// Defaults to 1/60 of a second.
+    pub target_frame_time: f32,
+    /// Fewest stars to ever draw per starfield.
+    ///
+    /// Defaults to 1000.
+    pub min_stars: usize,
+    /// Most stars to ever draw per starfield, or `None` to allow every star once there is enough
+    /// headroom.
+    ///
+    /// Defaults to `None`.
+    pub max_stars: Option<usize>,
+    /// Seconds between adjustments, which gives each one time to show up in the frame rate.
+    ///
+    /// Defaults to 0.5.
+    pub interval: f32,
+}
+impl Default for StarfieldAutoQuality {
+    fn default() -> Self {
+        Self {
+            target_frame_time: 1.0 / 60.0,
+            min_stars: 1000,
+            max_stars: None,
+            interval: 0.5,
+        }
+    }
+}
+
+pub(crate) fn adjust_starfield_quality(
+    auto_quality: Option<Res<StarfieldAutoQuality>>,
+    diagnostics: Res<Diagnostics>,
+    time: Res<Time>,
+    starfields: Query<&Starfield>,
+    mut quality: ResMut<StarfieldQuality>,
+    mut since_adjustment: Local<f32>,
+) {
+    let Some(auto_quality) = auto_quality else {
+        return;
+    };
+    *since_adjustment += time.delta_seconds();
+    if *since_adjustment < auto_quality.interval {
+        return;
+    }
+    // FPS is smoothed over several frames, unlike the raw frame time.
+    let Some(fps) = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+    else {
+        return;
+    };
+    *since_adjustment = 0.0;
+
+    let frame_time = 1.0 / fps.max(1e-3) as f32;
+    let largest = starfields
+        .iter()
+        .map(|starfield| starfield.stars().len())
+        .max()
+        .unwrap_or(0);
+    let ceiling = auto_quality.max_stars.unwrap_or(usize::MAX).min(largest);
+    let current = quality.max_stars.unwrap_or(usize::MAX).min(ceiling);
+    // Cut quickly while over budget, and recover slowly so as not to oscillate.
+    let budget = if frame_time > auto_quality.target_frame_time * 1.1 {
+        current as f32 * 0.8
+    } else if frame_time < auto_quality.target_frame_time * 0.85 {
+        current as f32 * 1.1 + 100.0
+    } else {
+        return;
+    };
+    let budget =
+        (budget as usize).clamp(auto_quality.min_stars, ceiling.max(auto_quality.min_stars));
+    let max_stars = match auto_quality.max_stars {
+        None if budget >= largest => None,
+        _ => Some(budget),
+    };
+    if quality.max_stars != max_stars {
+        quality.max_stars = max_stars;
+    }
+}
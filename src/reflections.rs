@@ -0,0 +1,94 @@
+//! Baking the starfield into environment maps so that the scene reflects the night sky.
+
+use bevy::{pbr::EnvironmentMapLight, prelude::*, utils::HashMap};
+
+use crate::{
+    bake::{bake_cube_faces, cubemap_image, shrink_cube_faces},
+    BakeOptions, GameUnitsToCelestial, Starfield, StarfieldSettings,
+};
+
+/// Add to a camera to light its scene with an [`EnvironmentMapLight`] baked from the starfield, so
+/// that shiny surfaces and water reflect the stars.
+///
+/// Any environment map already on the camera is replaced. Reflections come from the baked sky
+/// blurred more and more for rougher surfaces, while diffuse lighting comes from a heavily blurred
+/// copy so that matte surfaces pick up the overall glow of the sky rather than individual stars.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct StarfieldReflections {
+    /// Resolution of the baked environment map, as in [`BakeOptions::resolution`].
+    ///
+    /// Defaults to 1024.
+    pub resolution: u32,
+    /// Seconds of game time between re-bakes as the sky turns.
+    ///
+    /// Defaults to `None`, which only bakes when the component is added or changed.
+    pub refresh_interval: Option<f32>,
+}
+impl Default for StarfieldReflections {
+    fn default() -> Self {
+        Self {
+            resolution: 1024,
+            refresh_interval: None,
+        }
+    }
+}
+
+/// Width of each face of the diffuse map, which is small enough to blur individual stars away.
+const DIFFUSE_MAP_SIZE: u32 = 4;
+
+pub(crate) fn bake_starfield_reflections(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut baked: Local<HashMap<Entity, (f64, EnvironmentMapLight)>>,
+    cameras: Query<(Entity, Ref<StarfieldReflections>)>,
+    starfields: Query<(&Starfield, &StarfieldSettings)>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+    baked.retain(|&entity, _| cameras.contains(entity));
+
+    for (entity, reflections) in cameras.iter() {
+        let stale = match (baked.get(&entity), reflections.refresh_interval) {
+            (None, _) => true,
+            (Some(_), _) if reflections.is_changed() => true,
+            (Some(&(last_bake, _)), Some(interval)) => now - last_bake >= interval as f64,
+            (Some(_), None) => false,
+        };
+        if !stale {
+            continue;
+        }
+
+        let layers: Vec<_> = starfields.iter().collect();
+        let (size, faces) = bake_cube_faces(
+            &layers,
+            &BakeOptions {
+                resolution: reflections.resolution,
+                celestial_to_world: game_units_to_celestial.celestial_to_world(now),
+                ..default()
+            },
+        );
+        // Bevy picks the mip level to sample from the roughness of the surface.
+        let mip_levels = u32::BITS - size.leading_zeros();
+        let specular = cubemap_image(size, &faces, mip_levels);
+        let diffuse_size = size.min(DIFFUSE_MAP_SIZE);
+        let diffuse = cubemap_image(
+            diffuse_size,
+            &shrink_cube_faces(size, &faces, diffuse_size),
+            1,
+        );
+        let environment_map = match baked.remove(&entity) {
+            Some((_, previous)) => EnvironmentMapLight {
+                diffuse_map: images.set(previous.diffuse_map, diffuse),
+                specular_map: images.set(previous.specular_map, specular),
+            },
+            None => EnvironmentMapLight {
+                diffuse_map: images.add(diffuse),
+                specular_map: images.add(specular),
+            },
+        };
+        commands.entity(entity).insert(environment_map.clone());
+        baked.insert(entity, (now, environment_map));
+    }
+}
@@ -0,0 +1,2079 @@
+//! Drawing starfields.
+//!
+//! Everything that touches Bevy's renderer lives here, so that the rest of the crate only deals with
+//! components and resources in the main world. The main world hands starfields over through the
+//! extract stage and nothing in this module is public.
+
+use bevy::{
+    core_pipeline::{
+        core_2d::Transparent2d,
+        core_3d::Opaque3d,
+        prepass::{NormalPrepass, Opaque3dPrepass, NORMAL_PREPASS_FORMAT},
+    },
+    ecs::{
+        query::WorldQuery,
+        system::{
+            lifetimeless::{Read, SRes},
+            SystemParam,
+        },
+    },
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_component::ExtractComponentPlugin,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        mesh::{GpuBufferInfo, GpuMesh, MeshVertexBufferLayout},
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, RenderCommand, RenderCommandResult,
+            RenderPhase, SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+            BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, BufferBindingType,
+            BufferInitDescriptor, BufferUsages, ColorTargetState, ColorWrites, CompareFunction,
+            DepthStencilState, DynamicUniformBuffer, Extent3d, FilterMode, FragmentState,
+            FrontFace, MultisampleState, PipelineCache, PolygonMode, PrimitiveState,
+            PrimitiveTopology, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+            SamplerDescriptor, ShaderDefVal, ShaderStages, ShaderType, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+            TextureViewDimension, UniformBuffer, VertexState,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::FallbackImage,
+        view::{ExtractedView, RenderLayers, ViewTarget, ViewUniformOffset, ViewUniforms},
+        Extract, RenderApp, RenderSet,
+    },
+    utils::{FloatOrd, HashMap},
+};
+use bytemuck::{Pod, Zeroable};
+use std::{num::NonZeroU64, sync::Arc};
+
+use crate::{
+    observer::sky_rotation,
+    spikes::{spike_texture_data, SPIKE_TEXTURE_SIZE},
+    target, AtmosphericExtinction, CloudCover, CloudCoverMapping, Daylight, DiffractionSpikeShape,
+    GameUnitsToCelestial, HorizonHaze, HorizonProfile, MoonGlow, Observer, ReducedMotion,
+    SkyBrightness, Star, StarBlendMode, StarFlashes, StarRenderMode, Starfield,
+    StarfieldCameraSettings, StarfieldDebugView, StarfieldModulation, StarfieldProjection,
+    StarfieldQuality, StarfieldRenderTarget, StarfieldSettings, StarfieldShader, StarfieldSkybox,
+    MAX_DEPTH_SORTED_STARS, MAX_HORIZON_SAMPLES, MAX_MODULATION_BANDS, MAX_STAR_FLASHES,
+    MAX_STAR_PLANE_LAYERS,
+};
+
+/// Set up the render world to draw every [`Starfield`].
+///
+/// This is the only entry point into the module, so porting to a new version of Bevy's renderer
+/// shouldn't need to touch anything outside of it.
+pub(crate) fn build(app: &mut App) {
+    let mut shaders = app.world.resource_mut::<Assets<Shader>>();
+    let starfield_shader = Shader::from_wgsl(include_str!("shader.wgsl"));
+    shaders.set_untracked(STARFIELD_SHADER_HANDLE, starfield_shader);
+
+    app.add_plugin(ExtractComponentPlugin::<StarfieldCameraSettings>::default())
+        .add_plugin(ExtractComponentPlugin::<target::StarfieldTargetCamera>::default())
+        .add_plugin(ExtractComponentPlugin::<StarfieldSkybox>::default())
+        .add_plugin(ExtractResourcePlugin::<ReducedMotion>::default())
+        .add_plugin(ExtractResourcePlugin::<StarfieldQuality>::default());
+
+    if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+        render_app
+            .init_resource::<StarfieldPipeline>()
+            .init_resource::<StarfieldUniformBuffer>()
+            .init_resource::<StarfieldViewUniforms>()
+            .init_resource::<GpuStarfields>()
+            .init_resource::<ExtractedStarfields>()
+            .init_resource::<SpecializedRenderPipelines<StarfieldPipeline>>()
+            // Normally added along with the prepass of the first material.
+            .init_resource::<DrawFunctions<Opaque3dPrepass>>()
+            .add_system(extract_starfield.in_schedule(ExtractSchedule))
+            .add_systems(
+                (
+                    prepare_starfield,
+                    prepare_starfield_buffers,
+                    prepare_starfield_views.run_if(any_starfield_views),
+                )
+                    .distributive_run_if(any_starfields)
+                    .in_set(RenderSet::Prepare),
+            )
+            .add_system(
+                queue_starfield
+                    .run_if(any_starfields)
+                    .run_if(any_starfield_views)
+                    .in_set(RenderSet::Queue),
+            )
+            .add_render_command::<Opaque3d, DrawStarfield>()
+            .add_render_command::<Transparent2d, DrawStarfield>()
+            .add_render_command::<Opaque3dPrepass, DrawStarfield>();
+    }
+}
+
+/// Stars bring their own view bind group and pull every vertex from their own buffers, so the same
+/// draw works in 2D and 3D views without anything from Bevy's mesh pipeline.
+type DrawStarfield = (SetItemPipeline, StarfieldRenderCommand);
+
+#[derive(Default, Clone, Resource, ExtractResource, Reflect, ShaderType)]
+#[reflect(Resource)]
+struct StarfieldUniform {
+    pub world_to_ecef: Mat3,
+    pub celestial_to_body: Mat3,
+    pub modulation_bands: [Vec4; MAX_MODULATION_BANDS / 4],
+    pub modulation_band_count: u32,
+    pub modulation_brightness: f32,
+    pub modulation_size: f32,
+    pub cloud_mapping: u32,
+    pub cloud_opacity: f32,
+    pub moon_direction: Vec3,
+    pub moon_magnitude_reduction: f32,
+    pub moon_glow_radius: f32,
+    pub moon_background_reduction: f32,
+    pub haze_tint: Vec3,
+    pub haze_height: f32,
+    pub haze_dimming: f32,
+    pub horizon_altitudes: [Vec4; MAX_HORIZON_SAMPLES / 4],
+    pub horizon_sample_count: u32,
+    pub horizon_fade: f32,
+    pub extinction_up: Vec3,
+    pub extinction_coefficient: f32,
+}
+
+#[derive(Resource, Default)]
+struct StarfieldUniformBuffer {
+    buffer: UniformBuffer<StarfieldUniform>,
+}
+
+/// Parameters specific to a single starfield.
+#[derive(Clone, Default, ShaderType)]
+struct StarfieldParams {
+    orientation: Mat3,
+    time: f32,
+    intensity: f32,
+    hdr_luminance: f32,
+    visible_magnitude_limit: f32,
+    /// Limit set by the sky and quality settings, which also applies to cameras that override the
+    /// starfield's.
+    environment_magnitude_limit: f32,
+    magnitude_fade_width: f32,
+    pixel_size: f32,
+    angular_size: f32,
+    fov_compensation: f32,
+    size_magnitude_scaling: f32,
+    /// Reference magnitude, falloff, minimum and maximum of the size curve.
+    size_curve: Vec4,
+    /// Reference magnitude and falloff of the brightness curve.
+    brightness_curve: Vec2,
+    max_star_pixels: f32,
+    fade_oversized_stars: f32,
+    velocity: Vec3,
+    glow_intensity: f32,
+    glow_scale: f32,
+    spike_magnitude_threshold: f32,
+    spike_scale: f32,
+    spike_intensity: f32,
+    twinkle_amplitude: f32,
+    twinkle_speed: f32,
+    color_saturation: f32,
+    visibility_center: Vec3,
+    /// Start and end of the fade in margin followed by those of the fade out margin.
+    visibility_range: Vec4,
+    flash_indices: [UVec4; MAX_STAR_FLASHES / 4],
+    /// Color of each flash with its current strength in `w`.
+    flash_colors: [Vec4; MAX_STAR_FLASHES],
+    flash_count: u32,
+    plane_tile_size: f32,
+    plane_layer_count: u32,
+    /// Parallax of each layer, the range of random numbers that picks its stars, and the
+    /// brightness of its stars.
+    plane_layers: [Vec4; MAX_STAR_PLANE_LAYERS],
+}
+
+#[derive(Clone, ShaderType)]
+struct StarfieldViewUniform {
+    /// Projection of directions onto the screen, which differs from the camera's own for
+    /// rectilinear projections.
+    view_proj: Mat4,
+    magnitude_limit: f32,
+    magnitude_limit_override: f32,
+    intensity: f32,
+    zoom: f32,
+    pixels_to_ndc: Vec2,
+    radians_to_ndc: Vec2,
+}
+
+#[derive(Resource, Default)]
+struct StarfieldViewUniforms {
+    uniforms: DynamicUniformBuffer<StarfieldViewUniform>,
+}
+
+#[derive(Component)]
+struct StarfieldViewUniformOffset {
+    offset: u32,
+}
+
+#[derive(Component)]
+struct StarfieldViewBindGroup(BindGroup);
+
+/// Order in which a view draws the stars of each depth sorted starfield, bound as the third bind
+/// group.
+#[derive(Component, Default)]
+struct StarfieldDrawOrders(HashMap<Entity, BindGroup>);
+
+/// Width in texels of the textures that hold stars on devices without storage buffers.
+const STAR_TEXTURE_WIDTH: usize = 2048;
+
+/// Most stars of `star_size` bytes each that fit in a single buffer or texture on this device.
+fn star_capacity(render_device: &RenderDevice, storage_buffers: bool, star_size: usize) -> usize {
+    let limits = render_device.limits();
+    if storage_buffers {
+        limits.max_storage_buffer_binding_size as usize / star_size
+    } else {
+        STAR_TEXTURE_WIDTH * limits.max_texture_dimension_2d as usize
+    }
+}
+
+/// Array of stars or scales on the GPU.
+///
+/// WebGL2 has no storage buffers, so there the array is packed into the rows of a texture that the
+/// shader reads one texel at a time.
+enum StarData {
+    Buffer(Buffer),
+    Texture(TextureView),
+}
+impl StarData {
+    /// Upload `data`, which holds one texel of `format` per element.
+    fn new(
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        storage_buffers: bool,
+        label: &'static str,
+        format: TextureFormat,
+        data: &[u8],
+    ) -> Self {
+        if storage_buffers {
+            return Self::Buffer(
+                render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some(label),
+                    contents: data,
+                    usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                }),
+            );
+        }
+
+        let texel_size = format.describe().block_size as usize;
+        let texels = data.len() / texel_size;
+        let width = texels.min(STAR_TEXTURE_WIDTH);
+        let height = texels.div_ceil(STAR_TEXTURE_WIDTH);
+        let mut padded = data.to_vec();
+        padded.resize(width * height * texel_size, 0);
+        let texture = render_device.create_texture_with_data(
+            render_queue,
+            &TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width: width as u32,
+                    height: height as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &padded,
+        );
+        Self::Texture(texture.create_view(&TextureViewDescriptor::default()))
+    }
+
+    fn binding(&self) -> BindingResource<'_> {
+        match self {
+            Self::Buffer(buffer) => buffer.as_entire_binding(),
+            Self::Texture(view) => BindingResource::TextureView(view),
+        }
+    }
+}
+
+/// A [`Star`] packed into half the space, for [`StarfieldSettings::compact_stars`].
+///
+/// The direction to the star is octahedral encoded into two 16-bit signed normalized values, and
+/// its magnitude and color index are scaled into two more.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct CompactStar {
+    direction: u32,
+    magnitude_color_index: u32,
+}
+impl CompactStar {
+    /// Largest magnitude that can be stored, in either direction. Must match the shader.
+    const MAX_MAGNITUDE: f32 = 32.0;
+    /// Largest color index that can be stored, in either direction. Must match the shader.
+    const MAX_COLOR_INDEX: f32 = 4.0;
+
+    fn new(star: &Star) -> Self {
+        let direction = star.direction();
+        let octahedral = direction.truncate() / direction.abs().dot(Vec3::ONE);
+        let octahedral = if direction.z < 0.0 {
+            let sign = Vec2::select(octahedral.cmpge(Vec2::ZERO), Vec2::ONE, Vec2::NEG_ONE);
+            (Vec2::ONE - Vec2::new(octahedral.y, octahedral.x).abs()) * sign
+        } else {
+            octahedral
+        };
+        Self {
+            direction: pack_snorm2x16(octahedral),
+            magnitude_color_index: pack_snorm2x16(Vec2::new(
+                star.magnitude / Self::MAX_MAGNITUDE,
+                star.color_index / Self::MAX_COLOR_INDEX,
+            )),
+        }
+    }
+}
+
+/// Same as WGSL's `pack2x16snorm`.
+fn pack_snorm2x16(v: Vec2) -> u32 {
+    let pack = |x: f32| (x.clamp(-1.0, 1.0) * 32767.0).round() as i16 as u16 as u32;
+    pack(v.x) | pack(v.y) << 16
+}
+
+/// Bytes uploaded for `stars`, in the compact layout or the same layout as [`Star`].
+fn star_bytes(stars: &[Star], compact: bool) -> Vec<u8> {
+    if compact {
+        let compact: Vec<CompactStar> = stars.iter().map(CompactStar::new).collect();
+        bytemuck::cast_slice(&compact).to_vec()
+    } else {
+        bytemuck::cast_slice(stars).to_vec()
+    }
+}
+
+/// GPU copy of a starfield's stars, kept across frames so it is only uploaded when it changes.
+struct GpuStarfield {
+    /// Number of stars in the starfield, which may be more than were uploaded.
+    len: usize,
+    scales: Option<Arc<[f32]>>,
+    source: u64,
+    generation: u64,
+    buffer: StarData,
+    /// Whether `buffer` holds [`CompactStar`]s.
+    compact: bool,
+    scale_buffer: StarData,
+    params: UniformBuffer<StarfieldParams>,
+    bind_group: BindGroup,
+    /// Point spread function bound in `bind_group`.
+    spike_shape: DiffractionSpikeShape,
+    /// Only cameras on one of these layers draw the starfield.
+    render_layers: RenderLayers,
+    /// Position of the starfield among the others, as set by [`StarfieldSettings::draw_order`].
+    draw_order: i32,
+    /// The magnitude limit currently being rendered, which trails the configured one.
+    magnitude_limit: f32,
+    /// The part of `magnitude_limit` that comes from the sky and [`StarfieldQuality`] rather than
+    /// the starfield's settings, trailing the configured one in the same way.
+    environment_magnitude_limit: f32,
+    /// Magnitude of the faintest star kept by [`StarfieldQuality::max_stars`], cached along with
+    /// the star count it was found for.
+    quality_magnitude_limit: Option<(usize, f32)>,
+    /// Whether the stars are sorted brightest first, so that a prefix of them is the brightest.
+    sorted: bool,
+    /// Number of stars drawn, which is less than all of them when only the brightest are needed.
+    draw_count: u32,
+    max_star_size: bool,
+    relativistic: bool,
+    additive: bool,
+    glow: bool,
+    spikes: bool,
+    visibility_range: bool,
+    flashes: bool,
+    triangles: bool,
+    points: bool,
+    twinkle: bool,
+    /// Whether the starfield is drawn into prepasses, as set by
+    /// [`StarfieldSettings::write_prepass`].
+    prepass: bool,
+    /// Whether the stars are drawn back to front, as set by [`StarfieldSettings::depth_sort`].
+    depth_sort: bool,
+    /// Distance of every star, which is only needed on the CPU for sorting.
+    distances: Option<Arc<[f32]>>,
+    /// Mesh drawn for each star in place of the built-in sprites.
+    mesh: Option<Handle<Mesh>>,
+}
+impl GpuStarfield {
+    /// Whether the buffers hold the stars and scales of `starfield`.
+    fn is_up_to_date(&self, starfield: &Starfield) -> bool {
+        let same_scales = match (&self.scales, &starfield.scales) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.source == starfield.source && self.generation == starfield.generation && same_scales
+    }
+
+    /// Magnitude limit that leaves only the `max_stars` brightest of `stars` visible.
+    fn quality_magnitude_limit(&mut self, stars: &[Star], max_stars: usize) -> f32 {
+        match self.quality_magnitude_limit {
+            Some((count, limit)) if count == max_stars => limit,
+            _ => {
+                let limit = if max_stars >= stars.len() {
+                    f32::INFINITY
+                } else {
+                    let mut magnitudes: Vec<f32> =
+                        stars.iter().map(|star| star.magnitude).collect();
+                    // Stars at the limit are hidden, so this is the brightest star left out.
+                    let (_, &mut limit, _) =
+                        magnitudes.select_nth_unstable_by(max_stars, f32::total_cmp);
+                    limit
+                };
+                self.quality_magnitude_limit = Some((max_stars, limit));
+                limit
+            }
+        }
+    }
+
+    /// Indices of the drawn stars from farthest to nearest along `forward`, or `None` if they
+    /// aren't depth sorted. `to_world` turns star directions into world space.
+    fn depth_order(&self, stars: &[Star], to_world: Mat3, forward: Vec3) -> Option<Vec<u32>> {
+        let distances = self.distances.as_deref().filter(|_| self.depth_sort)?;
+        let count = self.draw_count as usize;
+        if count == 0 || count > MAX_DEPTH_SORTED_STARS || count > stars.len() {
+            return None;
+        }
+        Some(back_to_front(&stars[..count], distances, to_world, forward))
+    }
+
+    /// Number of vertices drawn for each star: one sprite for the star, plus one for its glow and
+    /// one for its diffraction spikes if those are enabled.
+    fn vertices_per_star(&self) -> u32 {
+        if self.points {
+            return 1;
+        }
+        let sprite_vertices = if self.triangles { 3 } else { 6 };
+        let sprites = 1 + u32::from(self.glow) + u32::from(self.spikes);
+        sprites * sprite_vertices
+    }
+
+    /// The custom mesh to draw each star with, once it has loaded. Only triangle lists with
+    /// positions can be used, and until then stars are drawn as sprites.
+    fn mesh<'a>(&self, meshes: &'a RenderAssets<Mesh>) -> Option<&'a GpuMesh> {
+        meshes.get(self.mesh.as_ref()?).filter(|mesh| {
+            mesh.primitive_topology == PrimitiveTopology::TriangleList
+                && mesh.layout.contains(Mesh::ATTRIBUTE_POSITION)
+        })
+    }
+}
+
+#[derive(Resource, Default)]
+struct GpuStarfields(HashMap<Entity, GpuStarfield>);
+
+/// Stars of every starfield in the main world, which are only copied over again when they change.
+///
+/// Unlike the components extracted each frame, this persists in the render world. Its copies don't
+/// share their stars with the main world, so edits on either side happen in place.
+#[derive(Resource, Default)]
+struct ExtractedStarfields(HashMap<Entity, Starfield>);
+
+/// Run condition that skips the render systems while there are no starfields to draw, or to clean
+/// up after.
+fn any_starfields(
+    starfields: Query<(), With<StarfieldSettings>>,
+    gpu_starfields: Res<GpuStarfields>,
+) -> bool {
+    !starfields.is_empty() || !gpu_starfields.0.is_empty()
+}
+
+/// Run condition that skips the per-view render systems while no camera could show a starfield.
+fn any_starfield_views(
+    views: Query<
+        (),
+        Or<(
+            With<RenderPhase<Opaque3d>>,
+            With<RenderPhase<Transparent2d>>,
+        )>,
+    >,
+) -> bool {
+    !views.is_empty()
+}
+
+fn extract_starfield(
+    mut commands: Commands,
+    (r, main_time): (Extract<Res<GameUnitsToCelestial>>, Extract<Res<Time>>),
+    modulation: Extract<Option<Res<StarfieldModulation>>>,
+    cloud_cover: Extract<Option<Res<CloudCover>>>,
+    moon_glow: Extract<Option<Res<MoonGlow>>>,
+    horizon_haze: Extract<Option<Res<HorizonHaze>>>,
+    horizon_profile: Extract<Option<Res<HorizonProfile>>>,
+    extinction: Extract<Option<Res<AtmosphericExtinction>>>,
+    sky_brightness: Extract<Option<Res<SkyBrightness>>>,
+    daylight: Extract<Option<Res<Daylight>>>,
+    debug_view: Extract<Option<Res<StarfieldDebugView>>>,
+    shader: Extract<Option<Res<StarfieldShader>>>,
+    mut extracted_starfields: ResMut<ExtractedStarfields>,
+    starfields: Extract<
+        Query<(
+            Entity,
+            Ref<Starfield>,
+            Option<&StarfieldSettings>,
+            Option<&Parent>,
+            Option<&StarFlashes>,
+            Option<&StarfieldRenderTarget>,
+            Option<&RenderLayers>,
+            Option<(&Observer, &Transform)>,
+        )>,
+    >,
+    parent_settings: Extract<Query<(&StarfieldSettings, Option<(&Observer, &Transform)>)>>,
+    camera_layers: Extract<Query<(Entity, &RenderLayers), With<Camera>>>,
+) {
+    commands.insert_resource(r.clone());
+    match &*modulation {
+        Some(modulation) => commands.insert_resource((**modulation).clone()),
+        None => commands.remove_resource::<StarfieldModulation>(),
+    }
+    match &*cloud_cover {
+        Some(cloud_cover) => commands.insert_resource((**cloud_cover).clone()),
+        None => commands.remove_resource::<CloudCover>(),
+    }
+    match &*moon_glow {
+        Some(moon_glow) => commands.insert_resource((**moon_glow).clone()),
+        None => commands.remove_resource::<MoonGlow>(),
+    }
+    match &*horizon_haze {
+        Some(horizon_haze) => commands.insert_resource((**horizon_haze).clone()),
+        None => commands.remove_resource::<HorizonHaze>(),
+    }
+    match &*horizon_profile {
+        Some(horizon_profile) => commands.insert_resource((**horizon_profile).clone()),
+        None => commands.remove_resource::<HorizonProfile>(),
+    }
+    match &*extinction {
+        Some(extinction) => commands.insert_resource((**extinction).clone()),
+        None => commands.remove_resource::<AtmosphericExtinction>(),
+    }
+    match &*sky_brightness {
+        Some(sky_brightness) => commands.insert_resource(**sky_brightness),
+        None => commands.remove_resource::<SkyBrightness>(),
+    }
+    match &*daylight {
+        Some(daylight) => commands.insert_resource((**daylight).clone()),
+        None => commands.remove_resource::<Daylight>(),
+    }
+    match &*debug_view {
+        Some(debug_view) => commands.insert_resource(**debug_view),
+        None => commands.remove_resource::<StarfieldDebugView>(),
+    }
+    match &*shader {
+        Some(shader) => commands.insert_resource((**shader).clone()),
+        None => commands.remove_resource::<StarfieldShader>(),
+    }
+    extracted_starfields
+        .0
+        .retain(|entity, _| starfields.contains(*entity));
+    for (entity, starfield, ..) in starfields.iter() {
+        match extracted_starfields.0.get_mut(&entity) {
+            Some(extracted) if starfield.is_changed() => extracted.update_from(&starfield),
+            Some(_) => {}
+            None => {
+                extracted_starfields.0.insert(entity, starfield.detached());
+            }
+        }
+    }
+    let celestial_to_world = r.celestial_to_world(main_time.elapsed_seconds_f64());
+    commands.insert_or_spawn_batch(
+        starfields
+            .iter()
+            .map(|(entity, _, settings, parent, .., layers, mut observer)| {
+                let mut settings = settings.cloned().unwrap_or_default();
+                if let Some((parent, parent_observer)) =
+                    parent.and_then(|p| parent_settings.get(p.get()).ok())
+                {
+                    settings.orientation = parent.orientation * settings.orientation;
+                    observer = observer.or(parent_observer);
+                }
+                // The shader turns every starfield with `GameUnitsToCelestial`, so starfields with
+                // their own observer undo that rotation before applying their own.
+                if observer.is_some() {
+                    let rotation =
+                        sky_rotation(observer, celestial_to_world) * celestial_to_world.transpose();
+                    settings.orientation *= Quat::from_mat3(&rotation);
+                }
+                let layers = layers.copied().unwrap_or_default();
+                (entity, (settings, layers))
+            })
+            .collect::<Vec<_>>(),
+    );
+    commands.insert_or_spawn_batch(
+        starfields
+            .iter()
+            .filter(|(.., target, _, _)| target.is_some())
+            .map(|(entity, ..)| (entity, target::ExclusiveStarfield))
+            .collect::<Vec<_>>(),
+    );
+    commands.insert_or_spawn_batch(
+        starfields
+            .iter()
+            .filter_map(|(entity, .., flashes, _, _, _)| Some((entity, flashes?.clone())))
+            .collect::<Vec<_>>(),
+    );
+    commands.insert_or_spawn_batch(
+        camera_layers
+            .iter()
+            .map(|(entity, layers)| (entity, *layers))
+            .collect::<Vec<_>>(),
+    );
+}
+
+/// Indices of `stars` ordered from the largest depth along `forward` to the smallest.
+fn back_to_front(stars: &[Star], distances: &[f32], to_world: Mat3, forward: Vec3) -> Vec<u32> {
+    let depths: Vec<f32> = stars
+        .iter()
+        .zip(distances)
+        .map(|(star, distance)| distance * forward.dot(to_world * star.direction()))
+        .collect();
+    let mut order: Vec<u32> = (0..stars.len() as u32).collect();
+    order.sort_by(|&a, &b| depths[b as usize].total_cmp(&depths[a as usize]));
+    order
+}
+
+fn prepare_starfield(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut starfield_buffer: ResMut<StarfieldUniformBuffer>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    modulation: Option<Res<StarfieldModulation>>,
+    cloud_cover: Option<Res<CloudCover>>,
+    moon_glow: Option<Res<MoonGlow>>,
+    horizon_haze: Option<Res<HorizonHaze>>,
+    horizon_profile: Option<Res<HorizonProfile>>,
+    extinction: Option<Res<AtmosphericExtinction>>,
+    time: Res<Time>,
+) {
+    let buffer = starfield_buffer.buffer.get_mut();
+
+    buffer.world_to_ecef = game_units_to_celestial.body_to_world();
+    buffer.celestial_to_body =
+        game_units_to_celestial.celestial_to_body(time.elapsed_seconds_f64());
+
+    let modulation = modulation.as_deref().cloned().unwrap_or_default();
+    let mut bands = [0.0; MAX_MODULATION_BANDS];
+    let band_count = modulation.bands.len().min(MAX_MODULATION_BANDS);
+    bands[..band_count].copy_from_slice(&modulation.bands[..band_count]);
+    for (i, band) in buffer.modulation_bands.iter_mut().enumerate() {
+        *band = Vec4::from_slice(&bands[i * 4..]);
+    }
+    buffer.modulation_band_count = band_count as u32;
+    buffer.modulation_brightness = modulation.brightness;
+    buffer.modulation_size = modulation.size;
+
+    if let Some(cloud_cover) = cloud_cover {
+        buffer.cloud_mapping = match cloud_cover.mapping {
+            CloudCoverMapping::Equirectangular => 0,
+            CloudCoverMapping::ScreenSpace => 1,
+        };
+        buffer.cloud_opacity = cloud_cover.opacity;
+    }
+
+    if let Some(moon_glow) = moon_glow {
+        let direction = moon_glow.direction.normalize_or_zero();
+        // Fade out over the few degrees around the horizon rather than switching off at once.
+        let above_horizon = ((direction.y + 0.05) / 0.1).clamp(0.0, 1.0);
+        let strength = moon_glow.phase.clamp(0.0, 1.0) * above_horizon;
+        buffer.moon_direction = direction;
+        buffer.moon_magnitude_reduction = moon_glow.magnitude_reduction * strength;
+        buffer.moon_glow_radius = moon_glow.radius.to_radians().max(1e-4);
+        buffer.moon_background_reduction = moon_glow.background_reduction * strength;
+    }
+
+    if let Some(horizon_haze) = horizon_haze {
+        let [r, g, b, _] = horizon_haze.tint.as_linear_rgba_f32();
+        buffer.haze_tint = Vec3::new(r, g, b);
+        buffer.haze_height = horizon_haze.height.to_radians().max(1e-4);
+        buffer.haze_dimming = horizon_haze.dimming;
+    }
+
+    if let Some(horizon_profile) = horizon_profile {
+        let mut altitudes = [0.0; MAX_HORIZON_SAMPLES];
+        let sample_count = horizon_profile.altitudes.len().min(MAX_HORIZON_SAMPLES);
+        for (altitude, sample) in altitudes.iter_mut().zip(&horizon_profile.altitudes) {
+            *altitude = sample.to_radians();
+        }
+        for (i, samples) in buffer.horizon_altitudes.iter_mut().enumerate() {
+            *samples = Vec4::from_slice(&altitudes[i * 4..]);
+        }
+        buffer.horizon_sample_count = sample_count as u32;
+        buffer.horizon_fade = horizon_profile.fade.to_radians().max(1e-4);
+    }
+
+    if let Some(extinction) = extinction {
+        buffer.extinction_up = extinction.up.try_normalize().unwrap_or(Vec3::Y);
+        // Extinction in magnitudes per airmass, which is around 0.3 on a clear night at sea level.
+        buffer.extinction_coefficient = 0.6 * extinction.strength.clamp(0.0, 1.0);
+    }
+
+    starfield_buffer
+        .buffer
+        .write_buffer(&render_device, &render_queue);
+}
+
+/// Magnitude of the faintest stars the sky lets through, whatever the limits of the starfield or
+/// camera.
+fn environment_magnitude_limit(
+    sky_brightness: Option<&SkyBrightness>,
+    daylight: Option<&Daylight>,
+) -> f32 {
+    let mut limit = f32::MAX;
+    if let Some(sky_brightness) = sky_brightness {
+        limit = limit.min(sky_brightness.limiting_magnitude());
+    }
+    if let Some(daylight) = daylight {
+        limit = limit.min(daylight.limiting_magnitude());
+    }
+    limit
+}
+
+fn prepare_starfield_buffers(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    starfield_pipeline: Res<StarfieldPipeline>,
+    mut gpu_starfields: ResMut<GpuStarfields>,
+    extracted_starfields: Res<ExtractedStarfields>,
+    starfields: Query<(
+        Entity,
+        &StarfieldSettings,
+        &RenderLayers,
+        Option<&StarFlashes>,
+    )>,
+    reduced_motion: Res<ReducedMotion>,
+    quality: Res<StarfieldQuality>,
+    sky_brightness: Option<Res<SkyBrightness>>,
+    daylight: Option<Res<Daylight>>,
+    time: Res<Time>,
+) {
+    gpu_starfields
+        .0
+        .retain(|entity, _| starfields.contains(*entity));
+
+    for (entity, settings, render_layers, flashes) in starfields.iter() {
+        let Some(starfield) = extracted_starfields.0.get(&entity) else {
+            continue;
+        };
+        // Without spikes the parameters and texture are bound but never used.
+        let spikes = settings.diffraction_spikes.unwrap_or_default();
+        let spike_shape = spikes.shape;
+        if starfield.stars.is_empty() {
+            gpu_starfields.0.remove(&entity);
+            continue;
+        }
+
+        // Textures hold one star per texel, so they always use the full layout.
+        let compact = settings.compact_stars && starfield_pipeline.storage_buffers;
+        let star_size = if compact {
+            std::mem::size_of::<CompactStar>()
+        } else {
+            std::mem::size_of::<Star>()
+        };
+        // Stars past what the device can hold are left out rather than failing to upload.
+        let capacity = star_capacity(
+            &render_device,
+            starfield_pipeline.storage_buffers,
+            star_size,
+        );
+        if let Some(gpu_starfield) = gpu_starfields.0.get_mut(&entity) {
+            if !gpu_starfield.is_up_to_date(starfield) {
+                // Edits to stars stored in a texture are uploaded by rebuilding it instead.
+                if let (StarData::Buffer(buffer), Some(edits), true) = (
+                    &gpu_starfield.buffer,
+                    starfield.edits_since(gpu_starfield.source, gpu_starfield.generation),
+                    gpu_starfield.compact == compact,
+                ) {
+                    // Stars stay sorted if every edited star is in order with its neighbors, which
+                    // saves looking at the rest of a large starfield.
+                    let stars = &starfield.stars;
+                    gpu_starfield.sorted = gpu_starfield.sorted
+                        && edits.iter().all(|range| {
+                            let window =
+                                range.start.saturating_sub(1)..(range.end + 1).min(stars.len());
+                            stars[window]
+                                .windows(2)
+                                .all(|pair| pair[0].magnitude <= pair[1].magnitude)
+                        });
+                    for range in edits {
+                        let range = range.start.min(capacity)..range.end.min(capacity);
+                        if range.is_empty() {
+                            continue;
+                        }
+                        render_queue.write_buffer(
+                            buffer,
+                            (range.start * star_size) as u64,
+                            &star_bytes(&starfield.stars[range], gpu_starfield.compact),
+                        );
+                    }
+                    gpu_starfield.generation = starfield.generation;
+                    gpu_starfield.quality_magnitude_limit = None;
+                }
+            }
+        }
+
+        let up_to_date = gpu_starfields.0.get(&entity).is_some_and(|gpu_starfield| {
+            gpu_starfield.is_up_to_date(starfield) && gpu_starfield.compact == compact
+        });
+        if !up_to_date {
+            let (magnitude_limit, environment_magnitude_limit) = gpu_starfields
+                .0
+                .remove(&entity)
+                .map_or((settings.visible_magnitude_limit, f32::MAX), |previous| {
+                    (
+                        previous.magnitude_limit,
+                        previous.environment_magnitude_limit,
+                    )
+                });
+
+            let count = starfield.stars.len().min(capacity);
+            if count < starfield.stars.len() {
+                warn!(
+                    "Starfield {entity:?} has {} stars but this device can only hold {capacity}, \
+                     so only the first {capacity} are drawn",
+                    starfield.stars.len(),
+                );
+            }
+            let buffer = StarData::new(
+                &render_device,
+                &render_queue,
+                starfield_pipeline.storage_buffers,
+                "starfield_buffer",
+                TextureFormat::Rgba32Float,
+                &star_bytes(&starfield.stars[..count], compact),
+            );
+            // Stars without scales still need something bound, which the shader never reads.
+            let scales = match &starfield.scales {
+                Some(scales) => &scales[..count],
+                None => &[1.0],
+            };
+            let scale_buffer = StarData::new(
+                &render_device,
+                &render_queue,
+                starfield_pipeline.storage_buffers,
+                "starfield_scale_buffer",
+                TextureFormat::R32Float,
+                bytemuck::cast_slice(scales),
+            );
+            let mut params = UniformBuffer::<StarfieldParams>::default();
+            params.write_buffer(&render_device, &render_queue);
+            let Some(bind_group) = starfield_pipeline.stars_bind_group(
+                &render_device,
+                &buffer,
+                &params,
+                &scale_buffer,
+                spike_shape,
+            ) else {
+                continue;
+            };
+            gpu_starfields.0.insert(
+                entity,
+                GpuStarfield {
+                    len: starfield.stars.len(),
+                    scales: starfield.scales.clone(),
+                    source: starfield.source,
+                    generation: starfield.generation,
+                    buffer,
+                    compact,
+                    scale_buffer,
+                    params,
+                    bind_group,
+                    spike_shape,
+                    render_layers: *render_layers,
+                    draw_order: settings.draw_order,
+                    magnitude_limit,
+                    environment_magnitude_limit,
+                    quality_magnitude_limit: None,
+                    sorted: starfield.is_sorted_by_brightness(),
+                    draw_count: starfield.stars.len() as u32,
+                    max_star_size: false,
+                    relativistic: false,
+                    additive: false,
+                    glow: false,
+                    spikes: false,
+                    visibility_range: false,
+                    flashes: false,
+                    triangles: false,
+                    points: false,
+                    twinkle: false,
+                    prepass: false,
+                    depth_sort: false,
+                    distances: None,
+                    mesh: None,
+                },
+            );
+        }
+
+        let gpu_starfield = gpu_starfields.0.get_mut(&entity).unwrap();
+        gpu_starfield.render_layers = *render_layers;
+        gpu_starfield.draw_order = settings.draw_order;
+        if gpu_starfield.spike_shape != spike_shape {
+            if let Some(bind_group) = starfield_pipeline.stars_bind_group(
+                &render_device,
+                &gpu_starfield.buffer,
+                &gpu_starfield.params,
+                &gpu_starfield.scale_buffer,
+                spike_shape,
+            ) {
+                gpu_starfield.bind_group = bind_group;
+                gpu_starfield.spike_shape = spike_shape;
+            }
+        }
+
+        let mut environment_limit =
+            environment_magnitude_limit(sky_brightness.as_deref(), daylight.as_deref());
+        gpu_starfield.draw_count = settings
+            .visible_count
+            .unwrap_or(usize::MAX)
+            .min(gpu_starfield.len)
+            .min(capacity) as u32;
+        if let Some(max_stars) = quality.max_stars {
+            environment_limit = environment_limit
+                .min(gpu_starfield.quality_magnitude_limit(&starfield.stars, max_stars));
+            // Stars past the limit produce no fragments either way, but skipping them entirely
+            // also saves their vertices.
+            if gpu_starfield.sorted {
+                gpu_starfield.draw_count = gpu_starfield.draw_count.min(max_stars as u32);
+            }
+        }
+        let target_limit = settings.visible_magnitude_limit.min(environment_limit);
+        let max_step = settings.magnitude_limit_speed.max(0.0) * time.delta_seconds();
+        gpu_starfield.magnitude_limit += (target_limit - gpu_starfield.magnitude_limit)
+            .max(-max_step)
+            .min(max_step);
+        // Ease in from no fainter than the rendered limit, so that a sky which only just started
+        // hiding stars doesn't have to come all the way down from no limit at all.
+        let environment_magnitude_limit = gpu_starfield
+            .environment_magnitude_limit
+            .min(environment_limit.max(gpu_starfield.magnitude_limit));
+        gpu_starfield.environment_magnitude_limit = environment_magnitude_limit
+            + (environment_limit - environment_magnitude_limit)
+                .max(-max_step)
+                .min(max_step);
+        gpu_starfield.max_star_size = settings.max_star_pixels.is_some();
+        gpu_starfield.relativistic = settings.velocity != Vec3::ZERO;
+        gpu_starfield.additive = settings.blend_mode == StarBlendMode::Additive;
+        let render_mode = quality.render_mode.unwrap_or(settings.render_mode);
+        gpu_starfield.points = render_mode == StarRenderMode::Point;
+        gpu_starfield.glow = settings.glow_intensity > 0.0 && quality.glow && !gpu_starfield.points;
+        gpu_starfield.spikes = settings.diffraction_spikes.is_some()
+            && quality.diffraction_spikes
+            && !gpu_starfield.points;
+        gpu_starfield.triangles = render_mode == StarRenderMode::Triangle;
+        gpu_starfield.twinkle = settings.twinkle_amplitude > 0.0 && quality.twinkle;
+        gpu_starfield.visibility_range = settings.visibility_range.is_some();
+        gpu_starfield.prepass = settings.write_prepass;
+        gpu_starfield.depth_sort = settings.depth_sort;
+        gpu_starfield.distances = starfield.distances.clone();
+        gpu_starfield.mesh = settings.star_mesh.clone();
+
+        // Each layer takes the stars whose random number falls in the next `density` wide range.
+        let mut plane_layers = [Vec4::ZERO; MAX_STAR_PLANE_LAYERS];
+        let mut density_start = 0.0;
+        for (layer, out) in settings.plane.layers.iter().zip(&mut plane_layers) {
+            let density_end = density_start + layer.density;
+            *out = Vec4::new(layer.parallax, density_start, density_end, layer.brightness);
+            density_start = density_end;
+        }
+
+        let mut flash_indices = [UVec4::ZERO; MAX_STAR_FLASHES / 4];
+        let mut flash_colors = [Vec4::ZERO; MAX_STAR_FLASHES];
+        let mut flash_count = 0;
+        for (index, color, strength) in flashes
+            .into_iter()
+            .flat_map(|flashes| flashes.active(starfield, reduced_motion.0))
+        {
+            flash_indices[flash_count / 4][flash_count % 4] = index;
+            flash_colors[flash_count] = color.extend(strength);
+            flash_count += 1;
+        }
+        gpu_starfield.flashes = flash_count > 0;
+
+        let drift = match settings.angular_velocity.try_normalize() {
+            Some(axis) if !reduced_motion.0 => {
+                let speed = settings.angular_velocity.length() as f64;
+                let angle = (speed * time.elapsed_seconds_f64()) % std::f64::consts::TAU;
+                Quat::from_axis_angle(axis, angle as f32)
+            }
+            _ => Quat::IDENTITY,
+        };
+        let (pixel_size, angular_size, fov_compensation) = settings.star_size.shader_params();
+        let (visibility_center, visibility_range) = match &settings.visibility_range {
+            Some(range) => (
+                range.center,
+                Vec4::new(
+                    range.start_margin.start,
+                    range.start_margin.end,
+                    range.end_margin.start,
+                    range.end_margin.end,
+                ),
+            ),
+            None => (Vec3::ZERO, Vec4::new(0.0, 0.0, f32::MAX, f32::MAX)),
+        };
+        gpu_starfield.params.set(StarfieldParams {
+            orientation: Mat3::from_quat(drift * settings.orientation),
+            time: time.elapsed_seconds_wrapped(),
+            intensity: settings.intensity,
+            hdr_luminance: settings.hdr_luminance,
+            visible_magnitude_limit: gpu_starfield.magnitude_limit,
+            environment_magnitude_limit: gpu_starfield.environment_magnitude_limit,
+            magnitude_fade_width: settings.magnitude_fade_width,
+            pixel_size,
+            angular_size,
+            fov_compensation: if fov_compensation { 1.0 } else { 0.0 },
+            size_magnitude_scaling: if settings.star_size.scales_with_magnitude() {
+                1.0
+            } else {
+                0.0
+            },
+            size_curve: Vec4::new(
+                settings.magnitude_curve.size_reference_magnitude,
+                settings.magnitude_curve.size_falloff,
+                settings.magnitude_curve.min_size,
+                settings.magnitude_curve.max_size,
+            ),
+            brightness_curve: Vec2::new(
+                settings.magnitude_curve.brightness_reference_magnitude,
+                settings.magnitude_curve.brightness_falloff,
+            ),
+            max_star_pixels: settings.max_star_pixels.unwrap_or(f32::MAX),
+            fade_oversized_stars: if settings.fade_oversized_stars {
+                1.0
+            } else {
+                0.0
+            },
+            velocity: settings.velocity,
+            glow_intensity: settings.glow_intensity,
+            glow_scale: settings.glow_scale,
+            spike_magnitude_threshold: spikes.magnitude_threshold,
+            spike_scale: spikes.scale,
+            spike_intensity: spikes.intensity,
+            twinkle_amplitude: settings.twinkle_amplitude,
+            twinkle_speed: settings.twinkle_speed,
+            color_saturation: settings.color_saturation,
+            visibility_center,
+            visibility_range,
+            flash_indices,
+            flash_colors,
+            flash_count: flash_count as u32,
+            plane_tile_size: settings.plane.tile_size,
+            plane_layer_count: settings.plane.layers.len() as u32,
+            plane_layers,
+        });
+        gpu_starfield
+            .params
+            .write_buffer(&render_device, &render_queue);
+    }
+}
+
+fn prepare_starfield_views(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut view_uniforms: ResMut<StarfieldViewUniforms>,
+    views: Query<(
+        Entity,
+        &ExtractedView,
+        Option<&StarfieldCameraSettings>,
+        Option<&RenderPhase<Transparent2d>>,
+    )>,
+) {
+    view_uniforms.uniforms.clear();
+    for (entity, view, camera_settings, phase_2d) in views.iter() {
+        let camera_settings = camera_settings.cloned().unwrap_or_default();
+        // Sizes are relative to the camera's viewport rather than the whole render target, so
+        // that picture-in-picture views get round stars of the right size.
+        let viewport_size = UVec2::new(view.viewport.z, view.viewport.w)
+            .max(UVec2::ONE)
+            .as_vec2();
+        // Only perspective projections have a last row of (0, 0, -1, 0).
+        let orthographic = view.projection.w_axis.w != 0.0;
+        let projection = match camera_settings.projection {
+            StarfieldProjection::Perspective if orthographic => StarfieldProjection::Rectilinear {
+                field_of_view: std::f32::consts::FRAC_PI_4,
+            },
+            projection => projection,
+        };
+        let (_, rotation, _) = view.transform.to_scale_rotation_translation();
+        let sky_projection = match projection {
+            StarfieldProjection::Rectilinear { field_of_view } => {
+                Mat4::perspective_infinite_reverse_rh(
+                    field_of_view,
+                    viewport_size.x / viewport_size.y,
+                    0.1,
+                )
+            }
+            _ => view.projection,
+        };
+        let (zoom, radians_to_ndc) = match projection {
+            // Stars on a plane have no angular size.
+            _ if phase_2d.is_some() => (1.0, Vec2::ZERO),
+            StarfieldProjection::Perspective | StarfieldProjection::Rectilinear { .. } => (
+                sky_projection.y_axis.y * 22.5f32.to_radians().tan(),
+                Vec2::new(sky_projection.x_axis.x, sky_projection.y_axis.y),
+            ),
+            StarfieldProjection::Fisheye { field_of_view } => (
+                std::f32::consts::FRAC_PI_4 / field_of_view,
+                2.0 / field_of_view * viewport_size.min_element() / viewport_size,
+            ),
+        };
+        let offset = view_uniforms.uniforms.push(StarfieldViewUniform {
+            // Stars are infinitely far away, so only the camera's rotation matters.
+            view_proj: sky_projection * Mat4::from_quat(rotation.inverse()),
+            magnitude_limit: camera_settings.visible_magnitude_limit.unwrap_or(0.0),
+            magnitude_limit_override: if camera_settings.visible_magnitude_limit.is_some() {
+                1.0
+            } else {
+                0.0
+            },
+            intensity: camera_settings.intensity,
+            zoom,
+            pixels_to_ndc: 2.0 / viewport_size,
+            radians_to_ndc,
+        });
+        commands
+            .entity(entity)
+            .insert(StarfieldViewUniformOffset { offset });
+    }
+    view_uniforms
+        .uniforms
+        .write_buffer(&render_device, &render_queue);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_starfield(
+    mut commands: Commands,
+    starfield_pipeline: Res<StarfieldPipeline>,
+    starfield_buffer: Res<StarfieldUniformBuffer>,
+    starfield_view_uniforms: Res<StarfieldViewUniforms>,
+    (gpu_starfields, extracted_starfields): (Res<GpuStarfields>, Res<ExtractedStarfields>),
+    mut pipelines: ResMut<SpecializedRenderPipelines<StarfieldPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    (draw_functions, draw_functions_2d, draw_functions_prepass): (
+        Res<DrawFunctions<Opaque3d>>,
+        Res<DrawFunctions<Transparent2d>>,
+        Res<DrawFunctions<Opaque3dPrepass>>,
+    ),
+    (render_device, render_queue): (Res<RenderDevice>, Res<RenderQueue>),
+    view_uniforms: Res<ViewUniforms>,
+    msaa: Res<Msaa>,
+    reduced_motion: Res<ReducedMotion>,
+    (
+        modulation,
+        cloud_cover,
+        moon_glow,
+        horizon_haze,
+        horizon_profile,
+        extinction,
+        debug_view,
+        shader,
+    ): (
+        Option<Res<StarfieldModulation>>,
+        Option<Res<CloudCover>>,
+        Option<Res<MoonGlow>>,
+        Option<Res<HorizonHaze>>,
+        Option<Res<HorizonProfile>>,
+        Option<Res<AtmosphericExtinction>>,
+        Option<Res<StarfieldDebugView>>,
+        Option<Res<StarfieldShader>>,
+    ),
+    (images, meshes, fallback_image): (
+        Res<RenderAssets<Image>>,
+        Res<RenderAssets<Mesh>>,
+        Res<FallbackImage>,
+    ),
+    (mut views, mut views_2d): (
+        Query<(
+            Entity,
+            &mut RenderPhase<Opaque3d>,
+            &ViewTarget,
+            &ExtractedView,
+            Option<&StarfieldCameraSettings>,
+            Option<&target::StarfieldTargetCamera>,
+            Option<&RenderLayers>,
+            Option<&StarfieldSkybox>,
+            Option<&mut RenderPhase<Opaque3dPrepass>>,
+            Option<&NormalPrepass>,
+        )>,
+        Query<(
+            Entity,
+            &mut RenderPhase<Transparent2d>,
+            &ViewTarget,
+            Option<&StarfieldCameraSettings>,
+            Option<&RenderLayers>,
+        )>,
+    ),
+    exclusive_starfields: Query<(), With<target::ExclusiveStarfield>>,
+) {
+    let draw_function = draw_functions.read().id::<DrawStarfield>();
+    // Every starfield gets the same sort key and both phases sort stably, so they are drawn in the
+    // order queued here rather than that of the hash map.
+    let mut ordered: Vec<_> = gpu_starfields.0.iter().collect();
+    ordered.sort_by_key(|&(entity, gpu_starfield)| {
+        (
+            gpu_starfield.draw_order,
+            entity.index(),
+            entity.generation(),
+        )
+    });
+    let draw_function_2d = draw_functions_2d.read().id::<DrawStarfield>();
+    let draw_function_prepass = draw_functions_prepass.read().id::<DrawStarfield>();
+    // Directions of stars are turned into world space as in the shader, apart from aberration.
+    let celestial_to_world = {
+        let uniform = starfield_buffer.buffer.get();
+        uniform.world_to_ecef * uniform.celestial_to_body
+    };
+    let cloud_image = cloud_cover
+        .as_ref()
+        .and_then(|cloud_cover| images.get(&cloud_cover.image));
+    let features = StarfieldFeatures {
+        modulation: modulation.is_some() && !reduced_motion.0,
+        max_star_size: false,
+        relativistic: false,
+        additive: false,
+        glow: false,
+        spikes: false,
+        visibility_range: false,
+        flashes: false,
+        triangles: false,
+        points: false,
+        twinkle: false,
+        star_scales: false,
+        compact_stars: false,
+        mesh: false,
+        hooks: shader.as_ref().is_some_and(|shader| shader.hooks.is_some()),
+        hdr: false,
+        fisheye: false,
+        premultiplied_alpha: false,
+        plane: false,
+        cloud_cover: cloud_image.is_some(),
+        moon_glow: moon_glow.is_some(),
+        horizon_haze: horizon_haze.is_some(),
+        horizon_profile: horizon_profile.is_some(),
+        extinction: extinction.is_some(),
+        prepass: false,
+        depth_sort: false,
+        debug_view: debug_view.map(|debug_view| *debug_view),
+    };
+    let shader = shader
+        .as_ref()
+        .and_then(|shader| shader.shader.clone())
+        .unwrap_or_else(|| STARFIELD_SHADER_HANDLE.typed());
+    let (cloud_texture_view, cloud_sampler) = match cloud_image {
+        Some(image) => (&image.texture_view, &image.sampler),
+        None => (&fallback_image.texture_view, &fallback_image.sampler),
+    };
+    let Some((view_uniforms, starfield_buffer, starfield_view_uniforms)) = view_uniforms
+        .uniforms
+        .binding()
+        .zip(starfield_buffer.buffer.binding())
+        .zip(starfield_view_uniforms.uniforms.binding())
+        .map(|((a, b), c)| (a, b, c))
+    else {
+        return;
+    };
+    let view_bind_group = || {
+        StarfieldViewBindGroup(render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("starfield_view_bind_group"),
+            layout: &starfield_pipeline.view_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: view_uniforms.clone(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: starfield_buffer.clone(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: starfield_view_uniforms.clone(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(cloud_texture_view),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::Sampler(cloud_sampler),
+                },
+            ],
+        }))
+    };
+    let mut specialize = |gpu_starfield: &GpuStarfield,
+                          view_target: &ViewTarget,
+                          view_features: StarfieldFeatures| {
+        // A custom mesh replaces the sprites, along with the glow and spikes drawn with them.
+        let mesh_layout = gpu_starfield.mesh(&meshes).map(|mesh| mesh.layout.clone());
+        let sprites = mesh_layout.is_none();
+        pipelines.specialize(
+            &pipeline_cache,
+            &starfield_pipeline,
+            StarfieldPipelineKey {
+                samples: msaa.samples(),
+                texture_format: view_target.main_texture_format(),
+                features: StarfieldFeatures {
+                    max_star_size: gpu_starfield.max_star_size,
+                    relativistic: gpu_starfield.relativistic && !view_features.plane,
+                    additive: gpu_starfield.additive,
+                    glow: gpu_starfield.glow && sprites,
+                    spikes: gpu_starfield.spikes && sprites,
+                    visibility_range: gpu_starfield.visibility_range,
+                    flashes: gpu_starfield.flashes,
+                    triangles: gpu_starfield.triangles && sprites,
+                    points: gpu_starfield.points && sprites,
+                    twinkle: gpu_starfield.twinkle && !reduced_motion.0,
+                    star_scales: gpu_starfield.scales.is_some(),
+                    compact_stars: gpu_starfield.compact,
+                    mesh: !sprites,
+                    ..view_features
+                },
+                mesh_layout,
+                shader: shader.clone(),
+            },
+        )
+    };
+
+    for (
+        entity,
+        mut opaque3d,
+        view_target,
+        view,
+        camera_settings,
+        target_camera,
+        view_layers,
+        skybox,
+        mut prepass,
+        normal_prepass,
+    ) in views.iter_mut()
+    {
+        // The camera shows a baked copy of the sky instead.
+        if skybox.is_some() {
+            continue;
+        }
+        let view_layers = view_layers.copied().unwrap_or_default();
+        let view_features = StarfieldFeatures {
+            hdr: view_target.main_texture_format() == ViewTarget::TEXTURE_FORMAT_HDR,
+            fisheye: camera_settings.is_some_and(|settings| {
+                matches!(settings.projection, StarfieldProjection::Fisheye { .. })
+            }),
+            premultiplied_alpha: camera_settings
+                .is_some_and(|settings| settings.premultiplied_alpha),
+            ..features
+        };
+        let mut draw_orders = StarfieldDrawOrders::default();
+        for &(&starfield, gpu_starfield) in &ordered {
+            let visible = match target_camera {
+                Some(target_camera) => target_camera.starfield == starfield,
+                None => {
+                    !exclusive_starfields.contains(starfield)
+                        && view_layers.intersects(&gpu_starfield.render_layers)
+                }
+            };
+            if !visible {
+                continue;
+            }
+            let order = extracted_starfields
+                .0
+                .get(&starfield)
+                .and_then(|extracted| {
+                    let to_world = gpu_starfield.params.get().orientation * celestial_to_world;
+                    gpu_starfield.depth_order(&extracted.stars, to_world, view.transform.forward())
+                });
+            let starfield_features = match order {
+                Some(order) => {
+                    let order = StarData::new(
+                        &render_device,
+                        &render_queue,
+                        starfield_pipeline.storage_buffers,
+                        "starfield_draw_order",
+                        TextureFormat::R32Uint,
+                        bytemuck::cast_slice(&order),
+                    );
+                    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("starfield_draw_order_bind_group"),
+                        layout: &starfield_pipeline.order_layout,
+                        entries: &[BindGroupEntry {
+                            binding: 0,
+                            resource: order.binding(),
+                        }],
+                    });
+                    draw_orders.0.insert(starfield, bind_group);
+                    StarfieldFeatures {
+                        depth_sort: true,
+                        ..view_features
+                    }
+                }
+                None => view_features,
+            };
+            opaque3d.add(Opaque3d {
+                distance: f32::MAX,
+                pipeline: specialize(gpu_starfield, view_target, starfield_features),
+                entity: starfield,
+                draw_function,
+            });
+            // Drawing stars into a depth only prepass would change nothing, since they are at the
+            // far plane the depth buffer is cleared to.
+            let prepass = prepass
+                .as_mut()
+                .filter(|_| gpu_starfield.prepass && normal_prepass.is_some());
+            if let Some(prepass) = prepass {
+                let prepass_features = StarfieldFeatures {
+                    prepass: true,
+                    ..starfield_features
+                };
+                prepass.add(Opaque3dPrepass {
+                    distance: f32::MAX,
+                    pipeline_id: specialize(gpu_starfield, view_target, prepass_features),
+                    entity: starfield,
+                    draw_function: draw_function_prepass,
+                });
+            }
+        }
+        commands
+            .entity(entity)
+            .insert((view_bind_group(), draw_orders));
+    }
+
+    for (entity, mut transparent2d, view_target, camera_settings, view_layers) in
+        views_2d.iter_mut()
+    {
+        let view_layers = view_layers.copied().unwrap_or_default();
+        // The sky's environment doesn't apply to stars on a plane.
+        let view_features = StarfieldFeatures {
+            hdr: view_target.main_texture_format() == ViewTarget::TEXTURE_FORMAT_HDR,
+            premultiplied_alpha: camera_settings
+                .is_some_and(|settings| settings.premultiplied_alpha),
+            plane: true,
+            cloud_cover: false,
+            moon_glow: false,
+            horizon_haze: false,
+            horizon_profile: false,
+            extinction: false,
+            ..features
+        };
+        for &(&starfield, gpu_starfield) in &ordered {
+            if exclusive_starfields.contains(starfield)
+                || !view_layers.intersects(&gpu_starfield.render_layers)
+            {
+                continue;
+            }
+            transparent2d.add(Transparent2d {
+                // Behind every sprite.
+                sort_key: FloatOrd(f32::NEG_INFINITY),
+                entity: starfield,
+                pipeline: specialize(gpu_starfield, view_target, view_features),
+                draw_function: draw_function_2d,
+                batch_range: None,
+            });
+        }
+        commands.entity(entity).insert(view_bind_group());
+    }
+}
+
+const STARFIELD_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 17029892201246543411);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct StarfieldPipelineKey {
+    samples: u32,
+    texture_format: TextureFormat,
+    features: StarfieldFeatures,
+    /// Vertex layout of the custom mesh stars are drawn with, if any.
+    mesh_layout: Option<MeshVertexBufferLayout>,
+    /// Either the built-in shader or one from [`StarfieldShader`].
+    shader: Handle<Shader>,
+}
+
+/// Optional shader features. Each is compiled into the shader only when enabled so that disabled
+/// features cost nothing on the GPU.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct StarfieldFeatures {
+    modulation: bool,
+    max_star_size: bool,
+    relativistic: bool,
+    additive: bool,
+    glow: bool,
+    spikes: bool,
+    visibility_range: bool,
+    flashes: bool,
+    triangles: bool,
+    points: bool,
+    twinkle: bool,
+    star_scales: bool,
+    compact_stars: bool,
+    /// Each star is an instance of a custom mesh rather than a set of sprites.
+    mesh: bool,
+    /// The shader imports the hooks of a [`StarfieldShader`].
+    hooks: bool,
+    hdr: bool,
+    fisheye: bool,
+    premultiplied_alpha: bool,
+    /// Stars are laid out on a [`StarPlane`](crate::StarPlane) for a 2D camera.
+    plane: bool,
+    cloud_cover: bool,
+    moon_glow: bool,
+    horizon_haze: bool,
+    horizon_profile: bool,
+    extinction: bool,
+    /// Stars are drawn into the normal prepass instead of the main pass.
+    prepass: bool,
+    /// Stars are drawn in the order given by a [`StarfieldDrawOrders`] bind group.
+    depth_sort: bool,
+    debug_view: Option<StarfieldDebugView>,
+}
+impl StarfieldFeatures {
+    fn blend_state(&self) -> BlendState {
+        if self.debug_view == Some(StarfieldDebugView::Overdraw) {
+            return BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            };
+        }
+        match (self.additive, self.premultiplied_alpha) {
+            (false, false) => BlendState::ALPHA_BLENDING,
+            (false, true) => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            (true, premultiplied_alpha) => BlendState {
+                color: BlendComponent {
+                    src_factor: if premultiplied_alpha {
+                        BlendFactor::One
+                    } else {
+                        BlendFactor::SrcAlpha
+                    },
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+        }
+    }
+
+    fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        [
+            (self.modulation, "MODULATION"),
+            (self.max_star_size, "MAX_STAR_SIZE"),
+            (self.relativistic, "RELATIVISTIC"),
+            (self.glow, "GLOW"),
+            (self.spikes, "DIFFRACTION_SPIKES"),
+            (self.visibility_range, "VISIBILITY_RANGE"),
+            (self.flashes, "STAR_FLASHES"),
+            (self.triangles, "STAR_TRIANGLES"),
+            (self.points, "STAR_POINTS"),
+            (self.twinkle, "TWINKLE"),
+            (self.star_scales, "STAR_SCALES"),
+            (self.compact_stars, "COMPACT_STARS"),
+            (self.mesh, "STAR_MESH"),
+            (self.hooks, "STAR_HOOKS"),
+            (self.hdr, "HDR"),
+            (self.fisheye, "FISHEYE"),
+            (self.premultiplied_alpha, "PREMULTIPLIED_ALPHA"),
+            (self.plane, "STAR_PLANE"),
+            (self.cloud_cover, "CLOUD_COVER"),
+            (self.moon_glow, "MOON_GLOW"),
+            (self.horizon_haze, "HORIZON_HAZE"),
+            (self.horizon_profile, "HORIZON_PROFILE"),
+            (self.extinction, "ATMOSPHERIC_EXTINCTION"),
+            (self.prepass, "NORMAL_PREPASS"),
+            (self.depth_sort, "DEPTH_SORT"),
+            (
+                self.debug_view == Some(StarfieldDebugView::Overdraw),
+                "DEBUG_OVERDRAW",
+            ),
+            (
+                self.debug_view == Some(StarfieldDebugView::PixelSize),
+                "DEBUG_PIXEL_SIZE",
+            ),
+            (
+                self.debug_view == Some(StarfieldDebugView::MagnitudeBin),
+                "DEBUG_MAGNITUDE_BIN",
+            ),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _)| enabled)
+        .map(|(_, def)| def.into())
+        .collect()
+    }
+}
+
+#[derive(Resource)]
+struct StarfieldPipeline {
+    view_layout: BindGroupLayout,
+    stars_layout: BindGroupLayout,
+    order_layout: BindGroupLayout,
+    /// Whether stars are read from storage buffers rather than textures, which WebGL2 lacks.
+    storage_buffers: bool,
+    four_point_spikes: TextureView,
+    six_point_spikes: TextureView,
+    spike_sampler: Sampler,
+}
+impl FromWorld for StarfieldPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let view_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(StarfieldViewUniform::min_size()),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("starfield_view_layout"),
+        });
+
+        let storage_buffers = render_device.limits().max_storage_buffers_per_shader_stage > 0;
+        let star_data = |size: usize| {
+            if storage_buffers {
+                BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(size as u64),
+                }
+            } else {
+                BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                }
+            }
+        };
+        let stars_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    // Stars take 8 or 16 bytes depending on their layout, so leave checking the
+                    // size until they are drawn.
+                    ty: star_data(0),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(StarfieldParams::min_size()),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: star_data(std::mem::size_of::<f32>()),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("starfield_stars_layout"),
+        });
+
+        let order_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: if storage_buffers {
+                    star_data(std::mem::size_of::<u32>())
+                } else {
+                    BindingType::Texture {
+                        sample_type: TextureSampleType::Uint,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    }
+                },
+                count: None,
+            }],
+            label: Some("starfield_order_layout"),
+        });
+
+        let render_queue = world.resource::<RenderQueue>();
+        let spike_texture = |shape| {
+            let texture = render_device.create_texture_with_data(
+                render_queue,
+                &TextureDescriptor {
+                    label: Some("starfield_spike_texture"),
+                    size: Extent3d {
+                        width: SPIKE_TEXTURE_SIZE,
+                        height: SPIKE_TEXTURE_SIZE,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::R8Unorm,
+                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                    view_formats: &[],
+                },
+                &spike_texture_data(shape),
+            );
+            texture.create_view(&TextureViewDescriptor::default())
+        };
+        let spike_sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("starfield_spike_sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            view_layout,
+            stars_layout,
+            order_layout,
+            storage_buffers,
+            four_point_spikes: spike_texture(DiffractionSpikeShape::FourPoint),
+            six_point_spikes: spike_texture(DiffractionSpikeShape::SixPoint),
+            spike_sampler,
+        }
+    }
+}
+impl StarfieldPipeline {
+    /// Bind a starfield's stars and parameters, along with the spike texture for `spike_shape`.
+    ///
+    /// Returns `None` if the parameters haven't been uploaded yet.
+    fn stars_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        buffer: &StarData,
+        params: &UniformBuffer<StarfieldParams>,
+        scale_buffer: &StarData,
+        spike_shape: DiffractionSpikeShape,
+    ) -> Option<BindGroup> {
+        let spike_texture = match spike_shape {
+            DiffractionSpikeShape::FourPoint => &self.four_point_spikes,
+            DiffractionSpikeShape::SixPoint => &self.six_point_spikes,
+        };
+        Some(render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("starfield_stars_bind_group"),
+            layout: &self.stars_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: params.binding()?,
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: scale_buffer.binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(spike_texture),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::Sampler(&self.spike_sampler),
+                },
+            ],
+        }))
+    }
+}
+impl SpecializedRenderPipeline for StarfieldPipeline {
+    type Key = StarfieldPipelineKey;
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = key.features.shader_defs();
+        if !self.storage_buffers {
+            shader_defs.push("NO_STORAGE_BUFFERS".into());
+        }
+
+        let fragment = if key.features.prepass {
+            FragmentState {
+                shader: key.shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: "prepass_fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: NORMAL_PREPASS_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }
+        } else {
+            FragmentState {
+                shader: key.shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.texture_format,
+                    blend: Some(key.features.blend_state()),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }
+        };
+
+        RenderPipelineDescriptor {
+            label: Some(if key.features.prepass {
+                "starfield_prepass_pipeline".into()
+            } else {
+                "starfield_pipeline".into()
+            }),
+            layout: if key.features.depth_sort {
+                vec![
+                    self.view_layout.clone(),
+                    self.stars_layout.clone(),
+                    self.order_layout.clone(),
+                ]
+            } else {
+                vec![self.view_layout.clone(), self.stars_layout.clone()]
+            },
+            push_constant_ranges: vec![],
+            vertex: VertexState {
+                shader: key.shader,
+                shader_defs,
+                entry_point: "vertex".into(),
+                // Only the positions of a custom mesh are used.
+                buffers: key
+                    .mesh_layout
+                    .and_then(|layout| {
+                        layout
+                            .get_layout(&[Mesh::ATTRIBUTE_POSITION.at_shader_location(0)])
+                            .ok()
+                    })
+                    .into_iter()
+                    .collect(),
+            },
+            primitive: PrimitiveState {
+                topology: if key.features.points {
+                    PrimitiveTopology::PointList
+                } else {
+                    PrimitiveTopology::TriangleList
+                },
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            // The 2D pass has no depth buffer.
+            depth_stencil: (!key.features.plane).then(|| DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState {
+                count: key.samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(fragment),
+        }
+    }
+}
+
+struct StarfieldRenderCommand;
+impl<P: PhaseItem> RenderCommand<P> for StarfieldRenderCommand {
+    type Param = (SRes<GpuStarfields>, SRes<RenderAssets<Mesh>>);
+    type ViewWorldQuery = (
+        Read<ViewUniformOffset>,
+        Read<StarfieldViewUniformOffset>,
+        Read<StarfieldViewBindGroup>,
+        Option<Read<StarfieldDrawOrders>>,
+    );
+    type ItemWorldQuery = ();
+
+    fn render<'w>(
+        item: &P,
+        (view_uniform, starfield_view_uniform, bind_group, draw_orders): <<Self::ViewWorldQuery as WorldQuery>::ReadOnly as WorldQuery>::Item<'w>,
+        _entity: <<Self::ItemWorldQuery as WorldQuery>::ReadOnly as WorldQuery>::Item<'w>,
+        (gpu_starfields, meshes): <Self::Param as SystemParam>::Item<'w, '_>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(gpu_starfield) = gpu_starfields.into_inner().0.get(&item.entity()) else {
+            return RenderCommandResult::Failure;
+        };
+
+        pass.set_bind_group(
+            0,
+            &bind_group.0,
+            &[view_uniform.offset, starfield_view_uniform.offset],
+        );
+        pass.set_bind_group(1, &gpu_starfield.bind_group, &[]);
+        if let Some(order) = draw_orders.and_then(|orders| orders.0.get(&item.entity())) {
+            pass.set_bind_group(2, order, &[]);
+        }
+        // This matches the choice made when specializing the pipeline, since meshes only load
+        // before queueing.
+        if let Some(mesh) = gpu_starfield.mesh(meshes.into_inner()) {
+            let stars = 0..gpu_starfield.draw_count;
+            pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            match &mesh.buffer_info {
+                GpuBufferInfo::Indexed {
+                    buffer,
+                    index_format,
+                    count,
+                } => {
+                    pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                    pass.draw_indexed(0..*count, 0, stars);
+                }
+                GpuBufferInfo::NonIndexed { vertex_count } => pass.draw(0..*vertex_count, stars),
+            }
+            return RenderCommandResult::Success;
+        }
+        let vertices = gpu_starfield.vertices_per_star() * gpu_starfield.draw_count;
+        pass.draw(0..vertices, 0..1);
+        RenderCommandResult::Success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Same as WGSL's `unpack2x16snorm`.
+    fn unpack_snorm2x16(packed: u32) -> Vec2 {
+        let unpack = |bits: u32| (bits as u16 as i16 as f32 / 32767.0).max(-1.0);
+        Vec2::new(unpack(packed), unpack(packed >> 16))
+    }
+
+    /// Direction, magnitude and color index decoded as `load_star` does in the shader.
+    fn decode(star: CompactStar) -> (Vec3, f32, f32) {
+        let octahedral = unpack_snorm2x16(star.direction);
+        let mut direction = octahedral.extend(1.0 - octahedral.x.abs() - octahedral.y.abs());
+        let fold = (-direction.z).max(0.0);
+        direction.x += if direction.x >= 0.0 { -fold } else { fold };
+        direction.y += if direction.y >= 0.0 { -fold } else { fold };
+        let magnitude_color_index = unpack_snorm2x16(star.magnitude_color_index);
+        (
+            direction.normalize(),
+            magnitude_color_index.x * CompactStar::MAX_MAGNITUDE,
+            magnitude_color_index.y * CompactStar::MAX_COLOR_INDEX,
+        )
+    }
+
+    #[test]
+    fn depth_sort_draws_farthest_first() {
+        let ahead = Star::new(0.0, 0.0, 1.0);
+        let aside = Star::new(0.0, 90f32.to_radians(), 1.0);
+        let stars = [ahead, ahead, aside, ahead];
+        let distances = [5.0, 20.0, 100.0, f32::INFINITY];
+        let forward = ahead.direction();
+        let order = back_to_front(&stars, &distances, Mat3::IDENTITY, forward);
+        // Stars at right angles to the view have no depth, however far away they are.
+        assert_eq!(order, [3, 1, 0, 2]);
+
+        // Turning the view around reverses the order of the stars ahead.
+        let order = back_to_front(&stars, &distances, Mat3::IDENTITY, -forward);
+        assert_eq!(order, [2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn daylight_limits_cameras_with_their_own_magnitude_limit() {
+        let noon = Daylight {
+            sun_direction: Vec3::Y,
+            ..default()
+        };
+        let environment_limit = environment_magnitude_limit(None, Some(&noon));
+        // As in the shader, the camera's limit replaces the starfield's but not the sky's.
+        let camera_limit = 20.0f32.min(environment_limit);
+        // Sirius, the brightest star in the night sky.
+        assert!(camera_limit < -1.46, "limit {camera_limit} at noon");
+
+        let night = Daylight::default();
+        let environment_limit = environment_magnitude_limit(None, Some(&night));
+        assert_eq!(20.0f32.min(environment_limit), 20.0);
+    }
+
+    #[test]
+    fn compact_star_directions_round_trip() {
+        let mut max_error = 0.0f32;
+        for i in 0..=180 {
+            for j in 0..360 {
+                let declination = (i as f32 - 90.0).to_radians();
+                let right_ascension = (j as f32 + 0.37).to_radians();
+                let star = Star::new(declination, right_ascension, 0.0);
+                let (direction, _, _) = decode(CompactStar::new(&star));
+                let expected = star.direction();
+                let error = expected
+                    .cross(direction)
+                    .length()
+                    .atan2(expected.dot(direction));
+                max_error = max_error.max(error);
+            }
+        }
+        // About a quarter of an arcminute.
+        assert!(max_error < 1e-4, "max error {max_error} radians");
+    }
+
+    #[test]
+    fn compact_star_magnitudes_round_trip() {
+        for i in -64..=64 {
+            let magnitude = i as f32 * 0.49;
+            let color_index = i as f32 * 0.06;
+            let star = Star::new(0.0, 0.0, magnitude).with_color_index(color_index);
+            let (_, decoded_magnitude, decoded_color_index) = decode(CompactStar::new(&star));
+            assert!((decoded_magnitude - magnitude).abs() <= 0.5 * 32.0 / 32767.0 + 1e-5);
+            assert!((decoded_color_index - color_index).abs() <= 0.5 * 4.0 / 32767.0 + 1e-6);
+        }
+
+        let star = Star::new(0.0, 0.0, 40.0).with_color_index(-5.0);
+        let (_, magnitude, color_index) = decode(CompactStar::new(&star));
+        assert_eq!(magnitude, CompactStar::MAX_MAGNITUDE);
+        assert_eq!(color_index, -CompactStar::MAX_COLOR_INDEX);
+    }
+}
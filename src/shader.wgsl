@@ -1,8 +1,74 @@
 #import bevy_render::view
+#ifdef STAR_HOOKS
+#import bevy_starfield::star_hooks
+#endif
 
 struct Uniforms {
     world_to_ecef: mat3x3<f32>,
-    sidereal_time: f32,
+    celestial_to_body: mat3x3<f32>,
+    modulation_bands: array<vec4<f32>, 4>,
+    modulation_band_count: u32,
+    modulation_brightness: f32,
+    modulation_size: f32,
+    cloud_mapping: u32,
+    cloud_opacity: f32,
+    moon_direction: vec3<f32>,
+    moon_magnitude_reduction: f32,
+    moon_glow_radius: f32,
+    moon_background_reduction: f32,
+    haze_tint: vec3<f32>,
+    haze_height: f32,
+    haze_dimming: f32,
+    horizon_altitudes: array<vec4<f32>, 16>,
+    horizon_sample_count: u32,
+    horizon_fade: f32,
+    extinction_up: vec3<f32>,
+    extinction_coefficient: f32,
+}
+
+struct ViewSettings {
+    view_proj: mat4x4<f32>,
+    magnitude_limit: f32,
+    magnitude_limit_override: f32,
+    intensity: f32,
+    zoom: f32,
+    pixels_to_ndc: vec2<f32>,
+    radians_to_ndc: vec2<f32>,
+}
+
+struct StarfieldParams {
+    orientation: mat3x3<f32>,
+    time: f32,
+    intensity: f32,
+    hdr_luminance: f32,
+    visible_magnitude_limit: f32,
+    environment_magnitude_limit: f32,
+    magnitude_fade_width: f32,
+    pixel_size: f32,
+    angular_size: f32,
+    fov_compensation: f32,
+    size_magnitude_scaling: f32,
+    size_curve: vec4<f32>,
+    brightness_curve: vec2<f32>,
+    max_star_pixels: f32,
+    fade_oversized_stars: f32,
+    velocity: vec3<f32>,
+    glow_intensity: f32,
+    glow_scale: f32,
+    spike_magnitude_threshold: f32,
+    spike_scale: f32,
+    spike_intensity: f32,
+    twinkle_amplitude: f32,
+    twinkle_speed: f32,
+    color_saturation: f32,
+    visibility_center: vec3<f32>,
+    visibility_range: vec4<f32>,
+    flash_indices: array<vec4<u32>, 4>,
+    flash_colors: array<vec4<f32>, 16>,
+    flash_count: u32,
+    plane_tile_size: f32,
+    plane_layer_count: u32,
+    plane_layers: array<vec4<f32>, 4>,
 }
 
 @group(0) @binding(0)
@@ -12,46 +78,558 @@ var<uniform> view: View;
 var<uniform> uniforms: Uniforms;
 
 @group(0) @binding(2)
+var<uniform> view_settings: ViewSettings;
+
+@group(0) @binding(3)
+var cloud_texture: texture_2d<f32>;
+
+@group(0) @binding(4)
+var cloud_sampler: sampler;
+
+@group(1) @binding(1)
+var<uniform> starfield: StarfieldParams;
+
+#ifdef DIFFRACTION_SPIKES
+@group(1) @binding(3)
+var spike_texture: texture_2d<f32>;
+
+@group(1) @binding(4)
+var spike_sampler: sampler;
+#endif
+
+#ifdef NO_STORAGE_BUFFERS
+// WebGL2 has no storage buffers, so stars and scales are packed into textures 2048 texels wide.
+@group(1) @binding(0)
+var star_texture: texture_2d<f32>;
+
+@group(1) @binding(2)
+var scale_texture: texture_2d<f32>;
+
+fn star_texel(star_index: u32) -> vec2<i32> {
+    return vec2<i32>(i32(star_index % 2048u), i32(star_index / 2048u));
+}
+
+fn load_star(star_index: u32) -> vec4<f32> {
+    return textureLoad(star_texture, star_texel(star_index), 0);
+}
+
+fn load_star_scale(star_index: u32) -> f32 {
+    return textureLoad(scale_texture, star_texel(star_index), 0).x;
+}
+#else
+@group(1) @binding(2)
+var<storage,read> star_scales: array<f32>;
+
+#ifdef COMPACT_STARS
+// Octahedral encoded direction, then magnitude and color index scaled by 32 and 4 respectively.
+@group(1) @binding(0)
+var<storage,read> stars: array<vec2<u32>>;
+
+fn load_star(star_index: u32) -> vec4<f32> {
+    let packed = stars[star_index];
+    let octahedral = unpack2x16snorm(packed.x);
+    var direction = vec3(octahedral, 1.0 - abs(octahedral.x) - abs(octahedral.y));
+    let fold = max(-direction.z, 0.0);
+    direction.x += select(fold, -fold, direction.x >= 0.0);
+    direction.y += select(fold, -fold, direction.y >= 0.0);
+    direction = normalize(direction);
+    let magnitude_color_index = unpack2x16snorm(packed.y);
+    return vec4(
+        asin(clamp(direction.z, -1.0, 1.0)),
+        atan2(direction.y, direction.x),
+        magnitude_color_index.x * 32.0,
+        magnitude_color_index.y * 4.0);
+}
+#else
+@group(1) @binding(0)
 var<storage,read> stars: array<vec4<f32>>;
 
+fn load_star(star_index: u32) -> vec4<f32> {
+    return stars[star_index];
+}
+#endif
+
+fn load_star_scale(star_index: u32) -> f32 {
+    return star_scales[star_index];
+}
+#endif
+
+#ifdef DEPTH_SORT
+// Stars drawn back to front, as sorted for this view.
+#ifdef NO_STORAGE_BUFFERS
+@group(2) @binding(0)
+var draw_order_texture: texture_2d<u32>;
+
+fn sorted_star_index(draw_index: u32) -> u32 {
+    return textureLoad(draw_order_texture, star_texel(draw_index), 0).x;
+}
+#else
+@group(2) @binding(0)
+var<storage,read> draw_order: array<u32>;
+
+fn sorted_star_index(draw_index: u32) -> u32 {
+    return draw_order[draw_index];
+}
+#endif
+#endif
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) texcoord: vec2<f32>,
     @location(1) magnitude: f32,
     @location(2) world_position: vec4<f32>,
+    @location(3) brightness: f32,
+    @location(4) color: vec3<f32>,
+    @location(5) @interpolate(flat) glow: f32,
+    @location(6) @interpolate(flat) pixel_size: f32,
+    @location(7) @interpolate(flat) spikes: f32,
+#ifdef NORMAL_PREPASS
+    @location(8) @interpolate(flat) world_normal: vec3<f32>,
+#endif
 };
 
+// Level of the external modulation band that a star follows.
+fn modulation(star_index: u32) -> f32 {
+#ifdef MODULATION
+    if (uniforms.modulation_band_count == 0u) {
+        return 0.0;
+    }
+    let band = star_index % uniforms.modulation_band_count;
+    return uniforms.modulation_bands[band / 4u][band % 4u];
+#else
+    return 0.0;
+#endif
+}
+
+// Fraction of a star's light that makes it through the clouds. Takes the star's world space
+// direction and its clip space position.
+fn cloud_transmittance(world_direction: vec3<f32>, clip_position: vec4<f32>) -> f32 {
+#ifdef CLOUD_COVER
+    var uv: vec2<f32>;
+    if (uniforms.cloud_mapping == 0u) {
+        let pi = 3.14159265359;
+        uv = vec2(
+            atan2(world_direction.x, -world_direction.z) / (2.0 * pi) + 0.5,
+            acos(clamp(world_direction.y, -1.0, 1.0)) / pi);
+    } else {
+        let ndc = clip_position.xy / clip_position.w;
+        uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    }
+    let coverage = textureSampleLevel(cloud_texture, cloud_sampler, uv, 0.0).r;
+    return 1.0 - clamp(coverage * uniforms.cloud_opacity, 0.0, 1.0);
+#else
+    return 1.0;
+#endif
+}
+
+// How many magnitudes brighter a star in the given world space direction must be to remain visible
+// through the glow of the sky around it.
+fn sky_glow(world_direction: vec3<f32>) -> f32 {
+    var glow = 0.0;
+#ifdef MOON_GLOW
+    let moon_angle = acos(clamp(dot(world_direction, uniforms.moon_direction), -1.0, 1.0));
+    glow += uniforms.moon_magnitude_reduction * exp(-moon_angle / uniforms.moon_glow_radius);
+    glow += uniforms.moon_background_reduction;
+#endif
+    return glow;
+}
+
+// Applies relativistic aberration and Doppler shift for an observer moving at `starfield.velocity`.
+// Returns the apparent world space direction of the star.
+fn apply_relativistic_effects(world_direction: vec3<f32>, out: ptr<function, VertexOutput>) -> vec3<f32> {
+#ifdef RELATIVISTIC
+    let beta = length(starfield.velocity);
+    if (beta < 1e-6) {
+        return world_direction;
+    }
+    let axis = starfield.velocity / beta;
+    let cos_theta = dot(world_direction, axis);
+    let cos_aberrated = (cos_theta + beta) / (1.0 + beta * cos_theta);
+    let perpendicular = world_direction - axis * cos_theta;
+    let perpendicular_length = length(perpendicular);
+    var apparent = axis * sign(cos_aberrated);
+    if (perpendicular_length > 1e-6) {
+        apparent = axis * cos_aberrated
+            + perpendicular / perpendicular_length * sqrt(max(1.0 - cos_aberrated * cos_aberrated, 0.0));
+    }
+
+    // Received flux scales with the fourth power of the Doppler factor.
+    let gamma = inverseSqrt(1.0 - beta * beta);
+    let doppler = 1.0 / (gamma * (1.0 - beta * cos_aberrated));
+    (*out).magnitude -= 10.0 * log2(doppler) * 0.30103;
+    let shift = clamp(log2(doppler) * 0.5, -1.0, 1.0);
+    (*out).color *= mix(
+        mix(vec3(1.0), vec3(1.0, 0.45, 0.25), max(-shift, 0.0)),
+        vec3(0.6, 0.75, 1.0),
+        max(shift, 0.0));
+    return apparent;
+#else
+    return world_direction;
+#endif
+}
+
+// Clip space position of a world space direction. Returns a w of zero for directions that fall
+// outside of the fisheye circle.
+fn project(world_direction: vec3<f32>) -> vec4<f32> {
+#ifdef FISHEYE
+    let view_direction = (view.inverse_view * vec4(world_direction, 0.0)).xyz;
+    let angle = acos(clamp(-view_direction.z, -1.0, 1.0));
+    let radial = view_direction.xy / max(length(view_direction.xy), 1e-9);
+    let radians_to_ndc = view_settings.radians_to_ndc;
+    if (angle * max(radians_to_ndc.x, radians_to_ndc.y) > 1.0) {
+        return vec4(0.0);
+    }
+    // Like rectilinear projections below, stars sit on the far plane.
+    return vec4(radial * angle * radians_to_ndc, 0.0, 1.0);
+#else
+    var position = view_settings.view_proj * vec4(world_direction, 1.e-15);
+    // Rectilinear projections don't share the camera's depth range, so place stars on its far
+    // plane directly.
+    position.z = 0.0;
+    return position;
+#endif
+}
+
+// Layer of a star laid out on a plane, picked at random from the index of the star. Returns the
+// layer's parallax in `x` and brightness in `y`, or a brightness of zero for stars in no layer.
+fn plane_layer(star_index: u32) -> vec2<f32> {
+    let state = star_index * 277803737u + 1442695041u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 747796405u;
+    let random = f32((word >> 22u) ^ word) / 4294967296.0;
+    for (var i = 0u; i < starfield.plane_layer_count; i++) {
+        let layer = starfield.plane_layers[i];
+        if (random >= layer.y && random < layer.z) {
+            return layer.xw;
+        }
+    }
+    return vec2(0.0);
+}
+
+// Clip space position of a star laid out on a plane behind a 2D camera. The sky is mapped to a
+// square tile with equal area per star, which repeats in every direction.
+fn project_plane(ascension: f32, declination: f32, parallax: f32) -> vec4<f32> {
+    let pi = 3.14159265359;
+    let tile_size = starfield.plane_tile_size;
+    let uv = vec2(ascension / (2.0 * pi), sin(declination) * 0.5 + 0.5);
+    let camera = view.world_position.xy * parallax;
+    let offset = (fract(uv - camera / tile_size + 0.5) - 0.5) * tile_size;
+    var position = view.view_proj * vec4(view.world_position.xy + offset, 0.0, 1.0);
+    position.z = 0.0;
+    return position;
+}
+
+// Dims and tints stars close to the horizon.
+fn apply_horizon_haze(world_direction: vec3<f32>, out: ptr<function, VertexOutput>) {
+#ifdef HORIZON_HAZE
+    let altitude = asin(clamp(world_direction.y, -1.0, 1.0));
+    let haze = 1.0 - smoothstep(0.0, uniforms.haze_height, altitude);
+    (*out).brightness *= 1.0 - uniforms.haze_dimming * haze;
+    (*out).color = mix((*out).color, uniforms.haze_tint, haze);
+#endif
+}
+
+// Dims and reddens stars by the airmass along their line of sight.
+fn apply_extinction(world_direction: vec3<f32>, out: ptr<function, VertexOutput>) {
+#ifdef ATMOSPHERIC_EXTINCTION
+    let pi = 3.14159265359;
+    let altitude = max(asin(clamp(dot(world_direction, uniforms.extinction_up), -1.0, 1.0)), 0.0);
+    // Kasten and Young's airmass formula, which stays finite down to the horizon.
+    let degrees = altitude * 180.0 / pi;
+    let airmass = 1.0 / (sin(altitude) + 0.50572 * pow(degrees + 6.07995, -1.6364));
+    let extinction = uniforms.extinction_coefficient * airmass;
+    (*out).brightness *= pow(10.0, -0.4 * extinction);
+    // Blue light is scattered more than red, so stars low in the sky turn orange.
+    (*out).color *= pow(vec3(10.0), -0.4 * extinction * vec3(-0.4, 0.0, 0.6));
+#endif
+}
+
+// Fraction of a star's light that isn't blocked by terrain along the skyline.
+fn terrain_visibility(world_direction: vec3<f32>) -> f32 {
+#ifdef HORIZON_PROFILE
+    let count = uniforms.horizon_sample_count;
+    if (count == 0u) {
+        return 1.0;
+    }
+    let pi = 3.14159265359;
+    let azimuth = atan2(world_direction.x, -world_direction.z) / (2.0 * pi);
+    let position = fract(azimuth + 1.0) * f32(count);
+    let i0 = u32(position) % count;
+    let i1 = (i0 + 1u) % count;
+    let skyline = mix(
+        uniforms.horizon_altitudes[i0 / 4u][i0 % 4u],
+        uniforms.horizon_altitudes[i1 / 4u][i1 % 4u],
+        fract(position));
+    let altitude = asin(clamp(world_direction.y, -1.0, 1.0));
+    return smoothstep(skyline - uniforms.horizon_fade, skyline, altitude);
+#else
+    return 1.0;
+#endif
+}
+
+// Fade of the whole starfield based on how far the camera is from the center of its visibility
+// range.
+fn visibility_range_fade() -> f32 {
+#ifdef VISIBILITY_RANGE
+    let distance = length(view.world_position - starfield.visibility_center);
+    let range = starfield.visibility_range;
+    let fade_in = select(
+        clamp((distance - range.x) / max(range.y - range.x, 1e-6), 0.0, 1.0),
+        1.0,
+        distance >= range.y);
+    let fade_out = select(
+        clamp((distance - range.z) / max(range.w - range.z, 1e-6), 0.0, 1.0),
+        0.0,
+        distance < range.z);
+    return smoothstep(0.0, 1.0, fade_in) * (1.0 - smoothstep(0.0, 1.0, fade_out));
+#else
+    return 1.0;
+#endif
+}
+
+// Size of a star relative to the others in its starfield.
+fn star_scale(star_index: u32) -> f32 {
+#ifdef STAR_SCALES
+    return load_star_scale(star_index);
+#else
+    return 1.0;
+#endif
+}
+
+// Brightness multiplier that makes a star flicker over time, with a phase of its own.
+fn twinkle(star_index: u32) -> f32 {
+#ifdef TWINKLE
+    // PCG hash of the index, so that neighboring stars don't flicker in sync.
+    let state = star_index * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    let phase = f32((word >> 22u) ^ word) * (6.2831853 / 4294967295.0);
+    let t = starfield.time * starfield.twinkle_speed * 6.2831853;
+    // Two incommensurate frequencies keep the flicker from looking periodic.
+    let wave = 0.6 * sin(t + phase) + 0.4 * sin(2.7 * t + 3.0 * phase);
+    return 1.0 + starfield.twinkle_amplitude * wave;
+#else
+    return 1.0;
+#endif
+}
+
+// Linear RGB color of a star with the given B-V color index. Matches `star_color` in color.rs.
+fn star_color(color_index: f32, saturation: f32) -> vec3<f32> {
+    let bv = clamp(color_index, -0.4, 2.0);
+    let temperature = max(4600.0 * (1.0 / (0.92 * bv + 1.7) + 1.0 / (0.92 * bv + 0.62)), 500.0);
+    let white = 4600.0 * (1.0 / 1.7 + 1.0 / 0.62);
+    let wavelengths = vec3(610e-9, 550e-9, 465e-9);
+    let c2 = 1.4388e-2;
+    let color = (exp(c2 / (wavelengths * white)) - 1.0) / (exp(c2 / (wavelengths * temperature)) - 1.0);
+    let normalized = color / max(color.r, max(color.g, color.b));
+    return max(mix(vec3(1.0), normalized, saturation), vec3(0.0));
+}
+
+// Brightens and tints the star if it is currently flashing.
+fn apply_flashes(star_index: u32, out: ptr<function, VertexOutput>) {
+#ifdef STAR_FLASHES
+    for (var i = 0u; i < starfield.flash_count; i++) {
+        if (starfield.flash_indices[i / 4u][i % 4u] == star_index) {
+            let flash = starfield.flash_colors[i];
+            (*out).magnitude -= 2.5 * flash.w;
+            (*out).color = mix((*out).color, flash.rgb, flash.w);
+        }
+    }
+#endif
+}
+
+// Blue to red color ramp used by the debug views.
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0);
+    return clamp(
+        vec3(1.5 - abs(4.0 * x - 3.0), 1.5 - abs(4.0 * x - 2.0), 1.5 - abs(4.0 * x - 1.0)),
+        vec3(0.0),
+        vec3(1.0));
+}
+
 @vertex
-fn vertex( @builtin(vertex_index) in_vertex_index: u32,) -> VertexOutput {
+fn vertex(
+    @builtin(vertex_index) in_vertex_index: u32,
+#ifdef STAR_MESH
+    @builtin(instance_index) in_instance_index: u32,
+    @location(0) mesh_position: vec3<f32>,
+#endif
+) -> VertexOutput {
     var out: VertexOutput;
 
-    let star = stars[in_vertex_index / 6u];
+#ifdef STAR_POINTS
+    let sprite_vertices = 1u;
+#else
+#ifdef STAR_TRIANGLES
+    let sprite_vertices = 3u;
+#else
+    let sprite_vertices = 6u;
+#endif
+#endif
+    // Each star gets a glow sprite and a diffraction spike sprite (when enabled) followed by the
+    // sprite of the star itself, so that all come out of a single draw and the star is blended on
+    // top.
+#ifdef GLOW
+    let glow_sprites = 1u;
+#else
+    let glow_sprites = 0u;
+#endif
+#ifdef DIFFRACTION_SPIKES
+    let spike_sprites = 1u;
+#else
+    let spike_sprites = 0u;
+#endif
+    let sprites_per_star = glow_sprites + spike_sprites + 1u;
+#ifdef STAR_MESH
+    // Each instance of the custom mesh is one star, with neither glow nor spikes.
+    var star_index = in_instance_index;
+    out.glow = 0.0;
+    out.spikes = 0.0;
+#else
+    var star_index = in_vertex_index / (sprites_per_star * sprite_vertices);
+    let sprite = (in_vertex_index / sprite_vertices) % sprites_per_star;
+    out.glow = select(0.0, 1.0, sprite < glow_sprites);
+    out.spikes = select(0.0, 1.0, sprite >= glow_sprites && sprite < glow_sprites + spike_sprites);
+    let corner = in_vertex_index % sprite_vertices;
+#endif
+#ifdef DEPTH_SORT
+    star_index = sorted_star_index(star_index);
+#endif
+    let star = load_star(star_index);
     let declination = star.x;
     let ascension = star.y;
     out.magnitude = star.z;
+    out.color = star_color(star.w, starfield.color_saturation);
 
-    let sidereal_time = uniforms.sidereal_time;
+#ifdef STAR_MESH
+    // The mesh spans -1 to 1 where the sprite would cover the star's disc.
+    out.texcoord = mesh_position.xy * 0.5 + 0.5;
+#else
+#ifdef STAR_POINTS
+    // Points have no extent, so the whole star is shaded as the center of its disc.
+    out.texcoord = vec2(0.5, 0.5);
+#else
+#ifdef STAR_TRIANGLES
+    // A single triangle just large enough to contain the star's disc.
+	if(corner == 0u) { out.texcoord = vec2(0.5, 1.5); }
+	if(corner == 1u) { out.texcoord = vec2(-0.3660254, 0.); }
+	if(corner == 2u) { out.texcoord = vec2(1.3660254, 0.); }
+#else
+	if(corner == 0u) { out.texcoord = vec2(0., 0.); }
+	if(corner == 1u) { out.texcoord = vec2(1., 0.); }
+	if(corner == 2u) { out.texcoord = vec2(0., 1.); }
+	if(corner == 3u) { out.texcoord = vec2(1., 1.); }
+	if(corner == 4u) { out.texcoord = vec2(0., 1.); }
+	if(corner == 5u) { out.texcoord = vec2(1., 0.); }
+#endif
+#endif
+#endif
 
-	if(in_vertex_index % 6u == 0u) { out.texcoord = vec2(0., 0.); }
-	if(in_vertex_index % 6u == 1u) { out.texcoord = vec2(1., 0.); }
-	if(in_vertex_index % 6u == 2u) { out.texcoord = vec2(0., 1.); }
-	if(in_vertex_index % 6u == 3u) { out.texcoord = vec2(1., 1.); }
-	if(in_vertex_index % 6u == 4u) { out.texcoord = vec2(0., 1.); }
-	if(in_vertex_index % 6u == 5u) { out.texcoord = vec2(1., 0.); }
-
-    let direction = vec3(
-		-sin(ascension - sidereal_time) * cos(declination),
-		cos(ascension - sidereal_time) * cos(declination),
+    let direction = uniforms.celestial_to_body * vec3(
+		cos(ascension) * cos(declination),
+		sin(ascension) * cos(declination),
 		sin(declination));
 
-    let screen_dimensions = vec2(view.viewport.z, view.viewport.w);
+    let world_direction = apply_relativistic_effects(
+        starfield.orientation * (uniforms.world_to_ecef * direction), &out);
+    apply_flashes(star_index, &out);
+#ifdef NORMAL_PREPASS
+    // Stars are seen head on, from the opposite of the direction they lie in.
+    out.world_normal = -world_direction;
+#endif
+
+    // Collapse stars that are too faint to be visible so they don't produce any fragments. A
+    // camera's own limit replaces the starfield's, but not those set by the sky or quality settings.
+    let camera_magnitude_limit = min(view_settings.magnitude_limit, starfield.environment_magnitude_limit);
+    let magnitude_limit = select(starfield.visible_magnitude_limit, camera_magnitude_limit, view_settings.magnitude_limit_override > 0.5)
+        - sky_glow(world_direction);
+    if (out.magnitude >= magnitude_limit) {
+        out.position = vec4(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+    let fade_width = max(starfield.magnitude_fade_width, 0.0001);
+    out.brightness = starfield.intensity * (1.0 - smoothstep(magnitude_limit - fade_width, magnitude_limit, out.magnitude));
+    let brightness_curve = starfield.brightness_curve;
+    out.brightness *= min(exp(brightness_curve.y * (brightness_curve.x - out.magnitude)), 1.0);
+    out.brightness *= visibility_range_fade();
+    if (out.brightness <= 0.0) {
+        out.position = vec4(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
 
-	out.position = view.view_proj * vec4(uniforms.world_to_ecef * direction, 1.e-15);
-    let position_delta = (out.texcoord-vec2(0.5)) * out.position.w * 4.0 * 2.0 * clamp(exp(1. - 0.35 * out.magnitude), .25, 1.) / vec2(screen_dimensions);
+#ifdef STAR_PLANE
+    let layer = plane_layer(star_index);
+    out.brightness *= layer.y;
+    if (out.brightness <= 0.0) {
+        out.position = vec4(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+    out.position = project_plane(ascension, declination, layer.x);
+#else
+	out.position = project(world_direction);
+#endif
+    if (out.position.w == 0.0) {
+        out.position = vec4(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+    out.brightness *= cloud_transmittance(world_direction, out.position);
+    apply_horizon_haze(world_direction, &out);
+    apply_extinction(world_direction, &out);
+    out.brightness *= terrain_visibility(world_direction);
+    let pulse = modulation(star_index);
+    out.brightness *= 1.0 + uniforms.modulation_brightness * pulse;
+    out.brightness *= twinkle(star_index);
+#ifdef HDR
+    out.brightness *= starfield.hdr_luminance;
+#endif
+    let size_curve = starfield.size_curve;
+    let magnitude_size = clamp(exp(size_curve.y * (size_curve.x - out.magnitude)), size_curve.z, size_curve.w);
+    let size_scale = mix(1.0, magnitude_size, starfield.size_magnitude_scaling)
+        * star_scale(star_index)
+        * (1.0 + uniforms.modulation_size * pulse);
+    let pixel_size = starfield.pixel_size * mix(1.0, view_settings.zoom, starfield.fov_compensation);
+    var star_size = (view_settings.pixels_to_ndc * pixel_size + view_settings.radians_to_ndc * starfield.angular_size) * size_scale;
+#ifdef STAR_HOOKS
+    star_size = star_hook_size(star_size, out.magnitude, star_index);
+#endif
+
+#ifdef MAX_STAR_SIZE
+    // Shrink (and optionally fade) stars that would otherwise cover too much of the screen.
+    let star_pixels = max(star_size.x * view.viewport.z, star_size.y * view.viewport.w) * 0.5;
+    if (star_pixels > starfield.max_star_pixels) {
+        let shrink = starfield.max_star_pixels / star_pixels;
+        star_size *= shrink;
+        out.brightness *= mix(1.0, shrink, starfield.fade_oversized_stars);
+    }
+#endif
+
+#ifdef GLOW
+    if (out.glow > 0.5) {
+        star_size *= starfield.glow_scale;
+        out.brightness *= starfield.glow_intensity;
+    }
+#endif
+#ifdef DIFFRACTION_SPIKES
+    if (out.spikes > 0.5) {
+        if (out.magnitude >= starfield.spike_magnitude_threshold) {
+            out.position = vec4(0.0, 0.0, 0.0, 1.0);
+            return out;
+        }
+        star_size *= starfield.spike_scale;
+        out.brightness *= starfield.spike_intensity;
+    }
+#endif
+
+#ifdef DEBUG_PIXEL_SIZE
+    out.pixel_size = max(star_size.x * view.viewport.z, star_size.y * view.viewport.w) * 0.5;
+#endif
+
+    let position_delta = (out.texcoord-vec2(0.5)) * out.position.w * star_size;
 	out.position.x += position_delta.x;
     out.position.y += position_delta.y;
 
 	out.world_position = out.position;
+#ifdef STAR_HOOKS
+    out.color = star_hook_color(out.color, out.magnitude, star_index);
+#endif
 
     return out;
 }
@@ -65,7 +643,67 @@ fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
     // }
 
 	let v = in.texcoord * 2.0 - 1.0;
+#ifdef STAR_MESH
+    // The mesh already gives the star its shape, so it is shaded evenly instead of as a disc.
+    let x = 0.0;
+#else
 	let x = dot(v, v);
-	let alpha = smoothstep(1., 0., x) * clamp(0., 1., exp(1. - 0.7 * in.magnitude));
-    return vec4(1., 1., 1., alpha);
+#endif
+
+    // Debug views replace the rest of the shading, which WGSL doesn't allow after a return.
+#ifdef DEBUG_OVERDRAW
+    return vec4(0.05, 0.025, 0.01, 1.0);
+#else
+    let coverage = select(0.0, 1.0, x < 1.0);
+#ifdef DEBUG_PIXEL_SIZE
+    return vec4(heatmap(log2(max(in.pixel_size, 1.0)) / 6.0) * coverage, coverage);
+#else
+#ifdef DEBUG_MAGNITUDE_BIN
+    return vec4(heatmap((floor(in.magnitude) + 2.0) / 10.0) * coverage, coverage);
+#else
+    var falloff = smoothstep(1., 0., x);
+#ifdef STAR_HOOKS
+    falloff = star_hook_falloff(in.texcoord, falloff);
+#endif
+#ifdef GLOW
+    if (in.glow > 0.5) {
+        falloff = pow(max(1.0 - x, 0.0), 3.0);
+    }
+#endif
+#ifdef DIFFRACTION_SPIKES
+    if (in.spikes > 0.5) {
+        falloff = textureSampleLevel(spike_texture, spike_sampler, in.texcoord, 0.0).r;
+    }
+#endif
+	let alpha = falloff * in.brightness;
+    let energy = alpha * view_settings.intensity;
+    let opacity = min(energy, 1.0);
+#ifdef HDR
+    // Light that full opacity can't show pushes the star past white instead of being clipped.
+    let color = in.color * max(energy, 1.0);
+#else
+    let color = in.color;
+#endif
+#ifdef PREMULTIPLIED_ALPHA
+    return vec4(color * opacity, opacity);
+#else
+    return vec4(color, opacity);
+#endif
+#endif
+#endif
+#endif
+}
+
+#ifdef NORMAL_PREPASS
+@fragment
+fn prepass_fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+	let v = in.texcoord * 2.0 - 1.0;
+#ifndef STAR_MESH
+    // Only the disc of the star covers what is behind it, not its glow or spikes.
+    if (dot(v, v) >= 1.0 || in.glow > 0.5 || in.spikes > 0.5) {
+        discard;
+    }
+#endif
+    return vec4(normalize(in.world_normal) * 0.5 + vec3(0.5), 1.0);
 }
+#endif
@@ -0,0 +1,350 @@
+//! Inputs from the rest of the game's environment that affect how much of the sky is visible.
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+/// How a [`CloudCover`] texture is mapped onto the sky.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect)]
+pub enum CloudCoverMapping {
+    /// The texture covers the whole sky dome around the observer. The horizontal axis maps to
+    /// azimuth, with the world space -Z direction at the center of the texture and +X three
+    /// quarters of the way across. The vertical axis maps to the angle from zenith (top) to nadir
+    /// (bottom).
+    #[default]
+    Equirectangular,
+    /// The texture covers the screen of every camera.
+    ScreenSpace,
+}
+
+/// Cloud coverage provided by a weather system, used to hide stars behind clouds.
+///
+/// The red channel of `image` holds the coverage, from 0.0 for a clear sky to 1.0 for full
+/// overcast.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct CloudCover {
+    /// Texture holding the cloud coverage.
+    ///
+    /// Defaults to no texture, which leaves the sky clear.
+    pub image: Handle<Image>,
+    /// How `image` is mapped onto the sky.
+    pub mapping: CloudCoverMapping,
+    /// How much light from stars behind fully covered parts of the sky is blocked.
+    ///
+    /// Defaults to 1.0.
+    pub opacity: f32,
+}
+impl Default for CloudCover {
+    fn default() -> Self {
+        Self::new(Handle::default())
+    }
+}
+impl CloudCover {
+    /// Cover the sky according to an equirectangular coverage texture.
+    pub fn new(image: Handle<Image>) -> Self {
+        Self {
+            image,
+            mapping: CloudCoverMapping::default(),
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Glow of the sky around the moon, which hides faint stars nearby and brightens the whole sky.
+///
+/// Insert this resource to have the moon wash out the sky. Both effects scale with `phase`, and
+/// fade away as the moon sets below the world space horizon.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct MoonGlow {
+    /// World space direction towards the moon.
+    pub direction: Vec3,
+    /// How many magnitudes brighter than usual a star right next to the moon must be to remain
+    /// visible.
+    ///
+    /// Defaults to 4.0, roughly the effect of a full moon.
+    pub magnitude_reduction: f32,
+    /// Angular distance in degrees over which the glow falls off by a factor of e.
+    ///
+    /// Defaults to 30.0.
+    pub radius: f32,
+    /// How many magnitudes brighter than usual every star must be to remain visible, from the
+    /// moonlight scattered across the whole sky.
+    ///
+    /// Defaults to 1.5, roughly the effect of a full moon.
+    pub background_reduction: f32,
+    /// Illuminated fraction of the moon's disk, from 0.0 at new moon to 1.0 at full moon. The
+    /// magnitude reductions above are scaled by it.
+    ///
+    /// Defaults to 1.0.
+    pub phase: f32,
+}
+impl Default for MoonGlow {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::Y,
+            magnitude_reduction: 4.0,
+            radius: 30.0,
+            background_reduction: 1.5,
+            phase: 1.0,
+        }
+    }
+}
+
+/// Maximum number of samples in a [`HorizonProfile`].
+pub const MAX_HORIZON_SAMPLES: usize = 64;
+
+/// Altitude of the terrain's skyline all the way around the observer, used to hide stars behind
+/// mountains and other distant terrain.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct HorizonProfile {
+    /// Altitude in degrees of the skyline at evenly spaced azimuths, starting from the world space
+    /// -Z direction and turning towards +X. Values beyond [`MAX_HORIZON_SAMPLES`] are ignored.
+    pub altitudes: Vec<f32>,
+    /// Angle in degrees below the skyline over which stars fade out rather than being cut off.
+    ///
+    /// Defaults to 0.5.
+    pub fade: f32,
+}
+impl Default for HorizonProfile {
+    fn default() -> Self {
+        Self {
+            altitudes: Vec::new(),
+            fade: 0.5,
+        }
+    }
+}
+impl HorizonProfile {
+    /// Compute the skyline seen from `observer` by marching outwards over a heightmap.
+    ///
+    /// `height_at` returns the world space height of the terrain at a given world space XZ
+    /// position. Terrain further than `max_distance` from the observer is ignored.
+    pub fn from_heightmap(
+        observer: Vec3,
+        max_distance: f32,
+        height_at: impl Fn(Vec2) -> f32,
+    ) -> Self {
+        const STEPS: usize = 128;
+        let altitudes = (0..MAX_HORIZON_SAMPLES)
+            .map(|i| {
+                let azimuth = i as f32 / MAX_HORIZON_SAMPLES as f32 * std::f32::consts::TAU;
+                let step = Vec2::new(azimuth.sin(), -azimuth.cos()) * (max_distance / STEPS as f32);
+                (1..=STEPS)
+                    .map(|j| {
+                        let offset = step * j as f32;
+                        let height = height_at(observer.xz() + offset) - observer.y;
+                        height.atan2(offset.length()).to_degrees()
+                    })
+                    .fold(f32::MIN, f32::max)
+            })
+            .collect();
+        Self {
+            altitudes,
+            ..default()
+        }
+    }
+}
+
+/// Brightness and tint gradient in the sky just above the horizon.
+///
+/// Insert this resource to dim and tint stars close to the horizon, so the sky meets the ground
+/// plausibly even without an atmosphere simulation.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct HorizonHaze {
+    /// Height in degrees above the horizon over which the haze fades out.
+    ///
+    /// Defaults to 10.0.
+    pub height: f32,
+    /// Fraction of star light blocked right at the horizon.
+    ///
+    /// Defaults to 0.8.
+    pub dimming: f32,
+    /// Color that stars are tinted towards at the horizon.
+    ///
+    /// Defaults to a warm orange.
+    pub tint: Color,
+}
+impl Default for HorizonHaze {
+    fn default() -> Self {
+        Self {
+            height: 10.0,
+            dimming: 0.8,
+            tint: Color::rgb(1.0, 0.75, 0.5),
+        }
+    }
+}
+
+/// Dimming and reddening of starlight on its long path through the atmosphere near the horizon.
+///
+/// Insert this resource for ground-based scenes. Stars lose light with the airmass along their line
+/// of sight, so they fade and turn orange as they approach the horizon, while those overhead are
+/// barely affected.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct AtmosphericExtinction {
+    /// Thickness of the atmosphere, from 0.0 for space to 1.0 for a thick, hazy atmosphere.
+    ///
+    /// Defaults to 0.5, roughly a clear night at sea level.
+    pub strength: f32,
+    /// Direction of the zenith in world space, which altitudes are measured from.
+    ///
+    /// Defaults to `Vec3::Y`.
+    pub up: Vec3,
+}
+impl Default for AtmosphericExtinction {
+    fn default() -> Self {
+        Self {
+            strength: 0.5,
+            up: Vec3::Y,
+        }
+    }
+}
+
+/// Brightness of the night sky from light pollution, which hides the faintest stars.
+///
+/// Insert this resource to cap how faint a star can be and still show, on top of
+/// [`StarfieldSettings::visible_magnitude_limit`](crate::StarfieldSettings::visible_magnitude_limit).
+/// Stars near the limit fade out over
+/// [`StarfieldSettings::magnitude_fade_width`](crate::StarfieldSettings::magnitude_fade_width), and
+/// changes to the sky brightness are eased in at
+/// [`StarfieldSettings::magnitude_limit_speed`](crate::StarfieldSettings::magnitude_limit_speed).
+/// The cap applies to cameras that override the limit with
+/// [`StarfieldCameraSettings::visible_magnitude_limit`](crate::StarfieldCameraSettings::visible_magnitude_limit)
+/// as well.
+#[derive(Clone, Copy, Debug, PartialEq, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub enum SkyBrightness {
+    /// Class on the Bortle scale, from 1 for an excellent dark site to 9 for an inner city sky.
+    Bortle(u8),
+    /// Brightness of the sky at the zenith in magnitudes per square arcsecond, as measured by a sky
+    /// quality meter. Around 22 at the darkest sites and 17 or lower in city centers.
+    SurfaceBrightness(f32),
+}
+impl Default for SkyBrightness {
+    fn default() -> Self {
+        Self::Bortle(1)
+    }
+}
+impl SkyBrightness {
+    /// Zenith brightness of the sky in magnitudes per square arcsecond.
+    pub fn surface_brightness(&self) -> f32 {
+        match *self {
+            // Middle of the range of sky quality meter readings for each class.
+            Self::Bortle(class) => match class {
+                0 | 1 => 21.99,
+                2 => 21.89,
+                3 => 21.69,
+                4 => 21.09,
+                5 => 20.0,
+                6 => 19.22,
+                7 => 18.66,
+                8 => 18.19,
+                _ => 17.5,
+            },
+            Self::SurfaceBrightness(brightness) => brightness,
+        }
+    }
+
+    /// Apparent magnitude of the faintest stars that show through the sky glow.
+    ///
+    /// About 8.0 under a pristine sky, dropping to around 4.2 in a city center.
+    pub fn limiting_magnitude(&self) -> f32 {
+        8.0 - 0.85 * (21.99 - self.surface_brightness()).max(0.0)
+    }
+}
+
+/// Position of the sun, which hides the stars by day and brings them out through twilight.
+///
+/// Insert this resource and keep `sun_direction` up to date, or add [`StarfieldSun`] to the
+/// [`DirectionalLight`] that lights the scene to have it tracked automatically. Like
+/// [`SkyBrightness`], the sun caps how faint a star can be and still show: the faintest stars
+/// appear once the sun is 6° below the horizon, and the sky is fully dark once it is 18° below.
+/// This holds for cameras with their own magnitude limit too. Only stars are affected; nebulae stay
+/// visible.
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct Daylight {
+    /// Direction towards the sun in world space.
+    ///
+    /// Defaults to `Vec3::NEG_Y`, with the sun far below the horizon.
+    pub sun_direction: Vec3,
+    /// Direction of the zenith in world space, which the sun's altitude is measured from.
+    ///
+    /// Defaults to `Vec3::Y`.
+    pub up: Vec3,
+}
+impl Default for Daylight {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::NEG_Y,
+            up: Vec3::Y,
+        }
+    }
+}
+impl Daylight {
+    /// Altitude of the sun above the horizon in degrees.
+    pub fn sun_altitude(&self) -> f32 {
+        let sine = self
+            .sun_direction
+            .normalize_or_zero()
+            .dot(self.up.normalize_or_zero());
+        sine.clamp(-1.0, 1.0).asin().to_degrees()
+    }
+
+    /// Apparent magnitude of the faintest stars that show at the current sun altitude, or infinity
+    /// once the sun is far enough below the horizon that it doesn't matter.
+    pub fn limiting_magnitude(&self) -> f32 {
+        // Roughly what the naked eye sees at the boundaries of astronomical, nautical and civil
+        // twilight, with even Sirius lost shortly after sunrise.
+        const LIMITS: [(f32, f32); 5] = [
+            (-18.0, 8.0),
+            (-12.0, 5.5),
+            (-6.0, 2.0),
+            (0.0, -1.5),
+            (2.0, -5.0),
+        ];
+
+        let altitude = self.sun_altitude();
+        if altitude <= LIMITS[0].0 {
+            return f32::INFINITY;
+        }
+        for pair in LIMITS.windows(2) {
+            let ((a0, m0), (a1, m1)) = (pair[0], pair[1]);
+            if altitude <= a1 {
+                return m0 + (m1 - m0) * (altitude - a0) / (a1 - a0);
+            }
+        }
+        LIMITS[LIMITS.len() - 1].1
+    }
+}
+
+/// Marks the [`DirectionalLight`] whose direction sets [`Daylight::sun_direction`].
+///
+/// The [`Daylight`] resource is inserted if it doesn't exist yet.
+#[derive(Clone, Copy, Debug, Default, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct StarfieldSun;
+
+pub(crate) fn follow_starfield_sun(
+    mut commands: Commands,
+    daylight: Option<ResMut<Daylight>>,
+    suns: Query<&GlobalTransform, (With<StarfieldSun>, With<DirectionalLight>)>,
+) {
+    let Some(transform) = suns.iter().next() else {
+        return;
+    };
+    // Directional lights shine along their forward direction, so the sun is behind them.
+    let sun_direction = transform.back();
+    match daylight {
+        Some(mut daylight) => {
+            if daylight.sun_direction != sun_direction {
+                daylight.sun_direction = sun_direction;
+            }
+        }
+        None => commands.insert_resource(Daylight {
+            sun_direction,
+            ..default()
+        }),
+    }
+}
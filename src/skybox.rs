@@ -0,0 +1,168 @@
+//! Baking the starfield once instead of drawing it every frame, for low-end targets.
+
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_component::ExtractComponent,
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        },
+        view::{NoFrustumCulling, RenderLayers},
+    },
+};
+
+use crate::{
+    bake::bake_cubemap_layers, BakeOptions, GameUnitsToCelestial, Starfield, StarfieldSettings,
+};
+
+/// Add to a camera to bake every starfield into a cubemap and stop drawing them live for that
+/// camera.
+///
+/// The cubemap is baked when the component is added or changed, and stored in a
+/// [`BakedStarfieldSkybox`] on the camera. Bevy 0.10 has no built-in skybox, so a cube showing the
+/// image is spawned along with it, on the camera's [`RenderLayers`]. Other cameras sharing those
+/// layers draw the cube too. Removing the component or despawning the camera despawns the cube.
+/// The baked sky doesn't turn with the time of day or pick up later changes to the starfields.
+#[derive(Clone, Component, ExtractComponent, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct StarfieldSkybox {
+    /// Resolution of the baked cubemap, as in [`BakeOptions::resolution`].
+    ///
+    /// Defaults to 4096.
+    pub resolution: u32,
+}
+impl Default for StarfieldSkybox {
+    fn default() -> Self {
+        Self { resolution: 4096 }
+    }
+}
+
+/// Cubemap baked for a [`StarfieldSkybox`], inserted on the same camera.
+#[derive(Clone, Component)]
+pub struct BakedStarfieldSkybox(pub Handle<Image>);
+
+pub(crate) const SKYBOX_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 2871302766429043618);
+
+/// Material drawing a baked cubemap on the far plane.
+#[derive(Clone, AsBindGroup, TypeUuid)]
+#[uuid = "6f0f5c8e-93d2-4b8a-8c52-4be0cf3e1a27"]
+pub(crate) struct SkyboxMaterial {
+    #[texture(0, dimension = "cube")]
+    #[sampler(1)]
+    sky: Handle<Image>,
+}
+
+impl Material for SkyboxMaterial {
+    fn vertex_shader() -> ShaderRef {
+        SKYBOX_SHADER_HANDLE.typed().into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SKYBOX_SHADER_HANDLE.typed().into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // The camera is always inside the cube.
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// Entity drawing the cube for the [`StarfieldSkybox`] of the camera it is on.
+#[derive(Component)]
+pub(crate) struct SkyboxCube(Entity);
+
+/// Camera whose [`StarfieldSkybox`] the cube it is on draws.
+#[derive(Component)]
+pub(crate) struct SkyboxCamera(Entity);
+
+pub(crate) fn bake_starfield_skyboxes(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SkyboxMaterial>>,
+    cameras: Query<
+        (
+            Entity,
+            &StarfieldSkybox,
+            Option<&BakedStarfieldSkybox>,
+            Option<&SkyboxCube>,
+            Option<&RenderLayers>,
+        ),
+        Changed<StarfieldSkybox>,
+    >,
+    starfields: Query<(&Starfield, &StarfieldSettings)>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+) {
+    for (entity, skybox, baked, cube, layers) in cameras.iter() {
+        let starfield_layers: Vec<_> = starfields.iter().collect();
+        let image = bake_cubemap_layers(
+            &starfield_layers,
+            &BakeOptions {
+                resolution: skybox.resolution,
+                celestial_to_world: game_units_to_celestial
+                    .celestial_to_world(time.elapsed_seconds_f64()),
+                ..default()
+            },
+        );
+        let handle = match baked {
+            Some(BakedStarfieldSkybox(handle)) => images.set(handle.clone(), image),
+            None => images.add(image),
+        };
+        commands
+            .entity(entity)
+            .insert(BakedStarfieldSkybox(handle.clone()));
+
+        let material = materials.add(SkyboxMaterial { sky: handle });
+        match cube {
+            Some(SkyboxCube(cube)) => {
+                commands.entity(*cube).insert(material);
+            }
+            None => {
+                // The shader ignores the transform, so the cube's bounds say nothing about where
+                // it is drawn.
+                let cube = commands
+                    .spawn((
+                        MaterialMeshBundle {
+                            mesh: meshes.add(shape::Cube { size: 2.0 }.into()),
+                            material,
+                            ..default()
+                        },
+                        layers.copied().unwrap_or_default(),
+                        NoFrustumCulling,
+                        SkyboxCamera(entity),
+                    ))
+                    .id();
+                commands.entity(entity).insert(SkyboxCube(cube));
+            }
+        }
+    }
+}
+
+/// Despawn the cubes, and drop the baked images, of cameras that were despawned or no longer have
+/// a [`StarfieldSkybox`].
+pub(crate) fn remove_skybox_cubes(
+    mut commands: Commands,
+    cubes: Query<(Entity, &SkyboxCamera)>,
+    cameras: Query<(), With<StarfieldSkybox>>,
+) {
+    for (cube, SkyboxCamera(camera)) in cubes.iter() {
+        if cameras.contains(*camera) {
+            continue;
+        }
+        commands.entity(cube).despawn();
+        if let Some(mut camera) = commands.get_entity(*camera) {
+            camera.remove::<(SkyboxCube, BakedStarfieldSkybox)>();
+        }
+    }
+}
@@ -0,0 +1,35 @@
+#import bevy_pbr::mesh_view_bindings
+
+@group(1) @binding(0)
+var sky_texture: texture_cube<f32>;
+
+@group(1) @binding(1)
+var sky_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    // Corners of the cube are world space directions, so like the stars it was baked from it sits on
+    // the far plane around the camera wherever the camera moves.
+    out.clip_position = view.view_proj * vec4(vertex.position, 0.0);
+    out.clip_position.z = 0.0;
+    out.direction = vertex.position;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(in.direction);
+    // Cubemaps are sampled with a left-handed coordinate system.
+    let sky = textureSample(sky_texture, sky_sampler, vec3(direction.xy, -direction.z));
+    return vec4(sky.rgb, 1.0);
+}
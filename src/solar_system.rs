@@ -0,0 +1,312 @@
+//! The Moon and naked-eye planets, positioned from low precision orbital elements.
+//!
+//! Positions follow Paul Schlyter's [How to compute planetary
+//! positions](https://stjarnhimlen.se/comp/ppcomp.html) and are good to a fraction of a degree
+//! for the planets and a degree or two for the Moon, within a few centuries of the present.
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    direction_to_radec, GameUnitsToCelestial, MoonGlow, Star, StarId, Starfield, StarfieldBundle,
+    StarfieldSettings, StarfieldSource,
+};
+
+/// Seed for the [`StarId`]s of solar system bodies, so that they don't collide with catalog stars.
+const SOLAR_SYSTEM_SEED: u64 = 0x501a_5157;
+
+/// Earth radii per astronomical unit, for the distance to the Moon.
+const EARTH_RADII_PER_AU: f64 = 23454.8;
+
+/// A body of the solar system that is visible to the naked eye.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SolarSystemBody {
+    /// The Moon.
+    Moon,
+    /// Mercury.
+    Mercury,
+    /// Venus.
+    Venus,
+    /// Mars.
+    Mars,
+    /// Jupiter.
+    Jupiter,
+    /// Saturn.
+    Saturn,
+}
+impl SolarSystemBody {
+    /// Every body, in the order they appear in the [`SolarSystem`] starfield.
+    pub const ALL: [Self; 6] = [
+        Self::Moon,
+        Self::Mercury,
+        Self::Venus,
+        Self::Mars,
+        Self::Jupiter,
+        Self::Saturn,
+    ];
+
+    /// ID of the body within the [`SolarSystem`] starfield.
+    pub fn star_id(self) -> StarId {
+        StarId::generated(SOLAR_SYSTEM_SEED, self as u32)
+    }
+
+    /// The body as it appears from the Earth on `julian_date`, with its apparent magnitude and
+    /// approximate color.
+    ///
+    /// The magnitude of Saturn leaves out the contribution of its rings.
+    pub fn star(self, julian_date: f64) -> Star {
+        let d = julian_date - 2451543.5;
+        let (geocentric, heliocentric) = self.positions(d);
+        let r = heliocentric.length();
+        let distance = geocentric.length();
+        let phase_angle = heliocentric.angle_between(geocentric).to_degrees();
+        let magnitude = 5.0 * (r * distance).log10()
+            + match self {
+                Self::Moon => 0.23 + 0.026 * phase_angle + 4.0e-9 * phase_angle.powi(4),
+                Self::Mercury => -0.36 + 0.027 * phase_angle + 2.2e-13 * phase_angle.powi(6),
+                Self::Venus => -4.34 + 0.013 * phase_angle + 4.2e-7 * phase_angle.powi(3),
+                Self::Mars => -1.51 + 0.016 * phase_angle,
+                Self::Jupiter => -9.25 + 0.014 * phase_angle,
+                Self::Saturn => -9.0 + 0.044 * phase_angle,
+            };
+        let color_index = match self {
+            Self::Moon => 0.92,
+            Self::Mercury => 0.93,
+            Self::Venus => 0.82,
+            Self::Mars => 1.36,
+            Self::Jupiter => 0.83,
+            Self::Saturn => 1.04,
+        };
+
+        // Rotate from ecliptic to equatorial coordinates.
+        let obliquity = (23.4393 - 3.563e-7 * d).to_radians();
+        let (sin, cos) = obliquity.sin_cos();
+        let equatorial = DVec3::new(
+            geocentric.x,
+            geocentric.y * cos - geocentric.z * sin,
+            geocentric.y * sin + geocentric.z * cos,
+        );
+        let (right_ascension, declination) = direction_to_radec(equatorial.as_vec3());
+        Star::new(declination, right_ascension, magnitude as f32).with_color_index(color_index)
+    }
+
+    /// Fraction of the body's disk lit by the Sun as seen from the Earth on `julian_date`, from
+    /// 0.0 at new moon to 1.0 at full moon.
+    pub fn illuminated_fraction(self, julian_date: f64) -> f32 {
+        let (geocentric, heliocentric) = self.positions(julian_date - 2451543.5);
+        let phase_angle = heliocentric.angle_between(geocentric);
+        ((1.0 + phase_angle.cos()) * 0.5) as f32
+    }
+
+    /// Position of the body relative to the Earth and to the Sun in ecliptic coordinates, in
+    /// astronomical units, `d` days after 2000 January 0.0.
+    fn positions(self, d: f64) -> (DVec3, DVec3) {
+        // Position of the Sun relative to the Earth.
+        let sun = OrbitalElements::sun(d).position();
+        match self {
+            Self::Moon => {
+                let moon = OrbitalElements::moon(d).position() / EARTH_RADII_PER_AU;
+                (moon, moon - sun)
+            }
+            planet => {
+                let heliocentric = OrbitalElements::planet(planet, d).position();
+                (heliocentric + sun, heliocentric)
+            }
+        }
+    }
+}
+
+/// Keplerian orbit of a body, with angles in degrees.
+struct OrbitalElements {
+    /// Longitude of the ascending node.
+    n: f64,
+    /// Inclination to the ecliptic.
+    i: f64,
+    /// Argument of perihelion.
+    w: f64,
+    /// Semi-major axis.
+    a: f64,
+    /// Eccentricity.
+    e: f64,
+    /// Mean anomaly.
+    m: f64,
+}
+impl OrbitalElements {
+    /// The Sun's apparent orbit around the Earth, `d` days after 2000 January 0.0.
+    fn sun(d: f64) -> Self {
+        Self {
+            n: 0.0,
+            i: 0.0,
+            w: 282.9404 + 4.70935e-5 * d,
+            a: 1.0,
+            e: 0.016709 - 1.151e-9 * d,
+            m: 356.0470 + 0.9856002585 * d,
+        }
+    }
+
+    /// The Moon's orbit around the Earth, in Earth radii.
+    fn moon(d: f64) -> Self {
+        Self {
+            n: 125.1228 - 0.0529538083 * d,
+            i: 5.1454,
+            w: 318.0634 + 0.1643573223 * d,
+            a: 60.2666,
+            e: 0.054900,
+            m: 115.3654 + 13.0649929509 * d,
+        }
+    }
+
+    /// A planet's orbit around the Sun, in astronomical units.
+    fn planet(planet: SolarSystemBody, d: f64) -> Self {
+        let [n, i, w, a, e, m] = match planet {
+            SolarSystemBody::Moon => unreachable!("the Moon orbits the Earth"),
+            SolarSystemBody::Mercury => [
+                48.3313 + 3.24587e-5 * d,
+                7.0047 + 5.00e-8 * d,
+                29.1241 + 1.01444e-5 * d,
+                0.387098,
+                0.205635 + 5.59e-10 * d,
+                168.6562 + 4.0923344368 * d,
+            ],
+            SolarSystemBody::Venus => [
+                76.6799 + 2.46590e-5 * d,
+                3.3946 + 2.75e-8 * d,
+                54.8910 + 1.38374e-5 * d,
+                0.723330,
+                0.006773 - 1.302e-9 * d,
+                48.0052 + 1.6021302244 * d,
+            ],
+            SolarSystemBody::Mars => [
+                49.5574 + 2.11081e-5 * d,
+                1.8497 - 1.78e-8 * d,
+                286.5016 + 2.92961e-5 * d,
+                1.523688,
+                0.093405 + 2.516e-9 * d,
+                18.6021 + 0.5240207766 * d,
+            ],
+            SolarSystemBody::Jupiter => [
+                100.4542 + 2.76854e-5 * d,
+                1.3030 - 1.557e-7 * d,
+                273.8777 + 1.64505e-5 * d,
+                5.20256,
+                0.048498 + 4.469e-9 * d,
+                19.8950 + 0.0830853001 * d,
+            ],
+            SolarSystemBody::Saturn => [
+                113.6634 + 2.38980e-5 * d,
+                2.4886 - 1.081e-7 * d,
+                339.3939 + 2.97661e-5 * d,
+                9.55475,
+                0.055546 - 9.499e-9 * d,
+                316.9670 + 0.0334442282 * d,
+            ],
+        };
+        Self { n, i, w, a, e, m }
+    }
+
+    /// Position in ecliptic coordinates relative to the body being orbited.
+    fn position(&self) -> DVec3 {
+        let m = self.m.to_radians().rem_euclid(std::f64::consts::TAU);
+        let mut eccentric_anomaly = m + self.e * m.sin() * (1.0 + self.e * m.cos());
+        for _ in 0..5 {
+            eccentric_anomaly -= (eccentric_anomaly - self.e * eccentric_anomaly.sin() - m)
+                / (1.0 - self.e * eccentric_anomaly.cos());
+        }
+        let x = self.a * (eccentric_anomaly.cos() - self.e);
+        let y = self.a * (1.0 - self.e * self.e).sqrt() * eccentric_anomaly.sin();
+        let (r, v) = (x.hypot(y), y.atan2(x));
+
+        let (n, i) = (self.n.to_radians(), self.i.to_radians());
+        let u = v + self.w.to_radians();
+        r * DVec3::new(
+            n.cos() * u.cos() - n.sin() * u.sin() * i.cos(),
+            n.sin() * u.cos() + n.cos() * u.sin() * i.cos(),
+            u.sin() * i.sin(),
+        )
+    }
+}
+
+/// Marker for the starfield that holds the Moon and planets, in the order of
+/// [`SolarSystemBody::ALL`].
+#[derive(Clone, Copy, Component, Default, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct SolarSystem;
+
+/// Draw the Moon and the naked-eye planets where they appear from the Earth on the date given by
+/// [`GameUnitsToCelestial`].
+///
+/// The bodies are spawned as their own starfield, marked with [`SolarSystem`], so they are drawn
+/// in the same pass as the stars and can be restyled through its [`StarfieldSettings`]. The Moon is
+/// drawn three times as large as the planets. If a [`MoonGlow`] resource is present, its direction
+/// is kept pointing at the Moon and its phase matched to the Moon's.
+pub struct SolarSystemPlugin;
+impl Plugin for SolarSystemPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SolarSystem>()
+            .add_startup_system(spawn_solar_system)
+            .add_system(update_solar_system);
+    }
+}
+
+fn spawn_solar_system(
+    mut commands: Commands,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+) {
+    let julian_date = game_units_to_celestial.julian_date(time.elapsed_seconds_f64());
+    let stars = SolarSystemBody::ALL
+        .map(|body| body.star(julian_date))
+        .to_vec();
+    let ids = SolarSystemBody::ALL.map(SolarSystemBody::star_id).to_vec();
+    let scales = SolarSystemBody::ALL
+        .map(|body| {
+            if body == SolarSystemBody::Moon {
+                3.0
+            } else {
+                1.0
+            }
+        })
+        .to_vec();
+    let source = StarfieldSource::Stars {
+        stars: stars.clone(),
+        ids: Some(ids.clone()),
+    };
+    commands.spawn((
+        StarfieldBundle {
+            starfield: Starfield::with_ids(stars, ids).with_scales(scales),
+            source,
+            settings: StarfieldSettings::default(),
+            name: Name::new("Solar System"),
+        },
+        SolarSystem,
+    ));
+}
+
+fn update_solar_system(
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+    moon_glow: Option<ResMut<MoonGlow>>,
+    mut solar_systems: Query<(&mut Starfield, &StarfieldSettings), With<SolarSystem>>,
+) {
+    let elapsed_seconds = time.elapsed_seconds_f64();
+    let julian_date = game_units_to_celestial.julian_date(elapsed_seconds);
+    let stars = SolarSystemBody::ALL.map(|body| body.star(julian_date));
+
+    for (mut starfield, _) in solar_systems.iter_mut() {
+        if starfield.stars().len() == stars.len() && starfield.stars() != stars {
+            starfield.replace_stars(0, &stars);
+        }
+    }
+
+    if let (Some(mut moon_glow), Some((_, settings))) = (moon_glow, solar_systems.iter().next()) {
+        let direction = Mat3::from_quat(settings.orientation)
+            * game_units_to_celestial.celestial_to_world(elapsed_seconds)
+            * stars[0].direction();
+        if moon_glow.direction != direction {
+            moon_glow.direction = direction;
+        }
+        let phase = SolarSystemBody::Moon.illuminated_fraction(julian_date);
+        if moon_glow.phase != phase {
+            moon_glow.phase = phase;
+        }
+    }
+}
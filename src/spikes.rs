@@ -0,0 +1,103 @@
+//! Diffraction spikes around the brightest stars.
+
+use bevy::prelude::*;
+
+/// Resolution of the built-in point spread function textures.
+pub(crate) const SPIKE_TEXTURE_SIZE: u32 = 128;
+
+/// Pattern of spikes produced by the supports of a telescope's secondary mirror.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiffractionSpikeShape {
+    /// A cross of four spikes, as from a Newtonian telescope with a four-vane spider.
+    #[default]
+    FourPoint,
+    /// Six spikes, as from a telescope with a hexagonal segmented mirror.
+    SixPoint,
+}
+
+/// Spikes and a soft halo drawn over stars brighter than a threshold, from a point spread function
+/// texture.
+///
+/// Each starfield with spikes draws one extra sprite per star, so this costs about as much as
+/// [`StarfieldSettings::glow_intensity`](crate::StarfieldSettings::glow_intensity). Not drawn in
+/// [`StarRenderMode::Point`](crate::StarRenderMode::Point).
+#[derive(Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiffractionSpikes {
+    /// Pattern of the spikes.
+    ///
+    /// Defaults to [`DiffractionSpikeShape::FourPoint`].
+    pub shape: DiffractionSpikeShape,
+    /// Only stars brighter (lower) than this apparent magnitude get spikes.
+    ///
+    /// Defaults to 1.5, which picks out a few dozen of the brightest stars in the sky.
+    pub magnitude_threshold: f32,
+    /// Length of the spikes from tip to tip as a multiple of the star's diameter.
+    ///
+    /// Defaults to 12.0.
+    pub scale: f32,
+    /// Brightness of the spikes relative to the star itself.
+    ///
+    /// Defaults to 0.6.
+    pub intensity: f32,
+}
+impl Default for DiffractionSpikes {
+    fn default() -> Self {
+        Self {
+            shape: DiffractionSpikeShape::FourPoint,
+            magnitude_threshold: 1.5,
+            scale: 12.0,
+            intensity: 0.6,
+        }
+    }
+}
+impl DiffractionSpikes {
+    pub(crate) fn is_valid(&self) -> bool {
+        self.magnitude_threshold.is_finite()
+            && self.scale > 0.0
+            && self.scale.is_finite()
+            && self.intensity >= 0.0
+            && self.intensity.is_finite()
+    }
+}
+
+/// Single channel point spread function for `shape`, with one byte per texel.
+///
+/// The spikes are thin gaussian lines that taper towards the edge of the texture, on top of a
+/// small halo that keeps the center from looking hollow when the spikes are faint.
+pub(crate) fn spike_texture_data(shape: DiffractionSpikeShape) -> Vec<u8> {
+    let lines = match shape {
+        DiffractionSpikeShape::FourPoint => 2,
+        DiffractionSpikeShape::SixPoint => 3,
+    };
+    let directions: Vec<Vec2> = (0..lines)
+        .map(|i| {
+            let angle = std::f32::consts::PI * i as f32 / lines as f32;
+            Vec2::new(angle.cos(), angle.sin())
+        })
+        .collect();
+
+    let size = SPIKE_TEXTURE_SIZE;
+    (0..size * size)
+        .map(|i| {
+            let texel = Vec2::new((i % size) as f32, (i / size) as f32) + 0.5;
+            let v = texel / size as f32 * 2.0 - 1.0;
+            let r = v.length();
+            if r >= 1.0 {
+                return 0;
+            }
+            let halo = 0.5 * (-r * r * 40.0).exp();
+            let spikes: f32 = directions
+                .iter()
+                .map(|d| {
+                    let along = v.dot(*d).abs();
+                    let across = v.perp_dot(*d).abs();
+                    let width = 0.01 + 0.01 * along;
+                    (-(across / width).powi(2)).exp() * (1.0 - along).powi(2)
+                })
+                .sum();
+            ((halo + spikes).min(1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
@@ -0,0 +1,93 @@
+//! Estimating how much light the night sky casts on the scene.
+
+use bevy::prelude::*;
+
+use crate::{GameUnitsToCelestial, MoonGlow, Starfield, StarfieldSettings};
+
+/// Illuminance in lux on a surface facing a magnitude 0 star.
+const LUX_PER_MAGNITUDE_ZERO: f32 = 2.54e-6;
+
+/// Apparent magnitude of the full moon.
+const FULL_MOON_MAGNITUDE: f32 = -12.7;
+
+/// Approximate ambient illumination from the visible sky.
+///
+/// While this resource is present, it is updated every frame from the stars above the horizon and
+/// the moon (if a [`MoonGlow`] is present), and optionally copied into [`AmbientLight`].
+#[derive(Clone, Debug, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct AmbientStarlight {
+    /// Whether to overwrite [`AmbientLight`] with the computed color and brightness.
+    ///
+    /// Defaults to true.
+    pub drive_ambient_light: bool,
+    /// [`AmbientLight::brightness`] per lux of illuminance.
+    ///
+    /// Defaults to 0.2, which makes full moonlight about as bright as Bevy's default ambient
+    /// light. Starlight alone is several thousand times fainter.
+    pub exposure: f32,
+    /// Illuminance in lux on an upward facing surface. Computed.
+    pub illuminance: f32,
+    /// Color of the illumination. Computed.
+    pub color: Color,
+}
+impl Default for AmbientStarlight {
+    fn default() -> Self {
+        Self {
+            drive_ambient_light: true,
+            exposure: 0.2,
+            illuminance: 0.0,
+            color: Color::BLACK,
+        }
+    }
+}
+
+pub(crate) fn update_ambient_starlight(
+    starlight: Option<ResMut<AmbientStarlight>>,
+    ambient_light: Option<ResMut<AmbientLight>>,
+    starfields: Query<(&Starfield, &StarfieldSettings)>,
+    moon_glow: Option<Res<MoonGlow>>,
+    game_units_to_celestial: Res<GameUnitsToCelestial>,
+    time: Res<Time>,
+) {
+    let Some(mut starlight) = starlight else {
+        return;
+    };
+
+    let celestial_to_world = game_units_to_celestial.celestial_to_world(time.elapsed_seconds_f64());
+    let mut star_flux = 0.0;
+    for (starfield, settings) in starfields.iter() {
+        let rotation = Mat3::from_quat(settings.orientation) * celestial_to_world;
+        let up = rotation.transpose() * Vec3::Y;
+        let flux: f32 = starfield
+            .stars()
+            .iter()
+            .filter(|star| star.magnitude < settings.visible_magnitude_limit)
+            .map(|star| {
+                let elevation = up.dot(star.direction());
+                10f32.powf(-0.4 * star.magnitude) * elevation.max(0.0)
+            })
+            .sum();
+        star_flux += flux * settings.intensity;
+    }
+
+    let moon_flux = moon_glow.map_or(0.0, |moon_glow| {
+        10f32.powf(-0.4 * FULL_MOON_MAGNITUDE) * moon_glow.direction.normalize_or_zero().y.max(0.0)
+    });
+
+    let total_flux = star_flux + moon_flux;
+    starlight.illuminance = total_flux * LUX_PER_MAGNITUDE_ZERO;
+    starlight.color = if total_flux > 0.0 {
+        let star_color = Vec3::new(0.75, 0.8, 1.0);
+        let moon_color = Vec3::new(1.0, 0.96, 0.9);
+        let color = (star_color * star_flux + moon_color * moon_flux) / total_flux;
+        Color::rgb(color.x, color.y, color.z)
+    } else {
+        Color::BLACK
+    };
+
+    if let (true, Some(mut ambient_light)) = (starlight.drive_ambient_light, ambient_light) {
+        ambient_light.color = starlight.color;
+        ambient_light.brightness = starlight.illuminance * starlight.exposure;
+    }
+}
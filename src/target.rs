@@ -0,0 +1,134 @@
+//! Rendering a starfield into a texture of its own.
+
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        extract_component::ExtractComponent,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    },
+    utils::HashMap,
+};
+
+/// Add to a starfield entity to render it into an image instead of the main view, for use as a
+/// material texture on spaceship windows, telescope eyepieces or portals into a different sky.
+///
+/// The plugin manages a camera that draws only this starfield into `image`. Other cameras no
+/// longer show the starfield.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct StarfieldRenderTarget {
+    /// Image the starfield is rendered into.
+    pub image: Handle<Image>,
+    /// Position and direction of the camera viewing the starfield.
+    pub view: Transform,
+}
+impl Default for StarfieldRenderTarget {
+    fn default() -> Self {
+        Self {
+            image: Handle::default(),
+            view: Transform::IDENTITY,
+        }
+    }
+}
+impl StarfieldRenderTarget {
+    /// Create a target rendering into a new `width` by `height` image.
+    pub fn new(images: &mut Assets<Image>, width: u32, height: u32) -> Self {
+        Self {
+            image: images.add(render_target_image(width, height)),
+            view: Transform::IDENTITY,
+        }
+    }
+}
+
+/// Marks a camera that only renders the given starfield.
+#[derive(Clone, Component, ExtractComponent)]
+pub(crate) struct StarfieldTargetCamera {
+    pub(crate) starfield: Entity,
+}
+
+/// Render world marker for starfields that are only drawn by their [`StarfieldTargetCamera`].
+#[derive(Component)]
+pub(crate) struct ExclusiveStarfield;
+
+/// The camera spawned for a [`StarfieldRenderTarget`].
+#[derive(Component)]
+pub(crate) struct StarfieldTargetCameraEntity(Entity);
+
+/// Create an image that a camera can render into.
+pub(crate) fn render_target_image(width: u32, height: u32) -> Image {
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Bgra8UnormSrgb,
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+    image
+}
+
+pub(crate) fn update_render_targets(
+    mut commands: Commands,
+    targets: Query<(
+        Entity,
+        Ref<StarfieldRenderTarget>,
+        Option<&StarfieldTargetCameraEntity>,
+    )>,
+    mut cameras: Query<(&mut Camera, &mut Transform)>,
+    mut removed: RemovedComponents<StarfieldRenderTarget>,
+    mut spawned: Local<HashMap<Entity, Entity>>,
+) {
+    for entity in removed.iter() {
+        if let Some(camera) = spawned.remove(&entity) {
+            if let Some(camera) = commands.get_entity(camera) {
+                camera.despawn_recursive();
+            }
+        }
+        if let Some(mut entity_commands) = commands.get_entity(entity) {
+            entity_commands.remove::<StarfieldTargetCameraEntity>();
+        }
+    }
+
+    for (entity, target, camera_entity) in targets.iter() {
+        match camera_entity.and_then(|c| cameras.get_mut(c.0).ok()) {
+            Some((mut camera, mut transform)) => {
+                if target.is_changed() {
+                    camera.target = RenderTarget::Image(target.image.clone());
+                    *transform = target.view;
+                }
+            }
+            None => {
+                let camera = commands
+                    .spawn((
+                        Camera3dBundle {
+                            camera: Camera {
+                                target: RenderTarget::Image(target.image.clone()),
+                                order: -1,
+                                ..default()
+                            },
+                            camera_3d: Camera3d {
+                                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                                ..default()
+                            },
+                            transform: target.view,
+                            ..default()
+                        },
+                        StarfieldTargetCamera { starfield: entity },
+                        Name::new("Starfield Target Camera"),
+                    ))
+                    .id();
+                spawned.insert(entity, camera);
+                commands
+                    .entity(entity)
+                    .insert(StarfieldTargetCameraEntity(camera));
+            }
+        }
+    }
+}
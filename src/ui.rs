@@ -0,0 +1,135 @@
+//! Showing the starfield behind Bevy UI without a 3D scene.
+
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{camera::RenderTarget, render_resource::Extent3d},
+    utils::HashMap,
+};
+
+use crate::target::render_target_image;
+
+/// Add to a UI image node to fill it with the starfield, for main menus and loading screens.
+///
+/// The plugin renders the sky into a texture sized to match the node using a camera of its own,
+/// and replaces the node's [`UiImage`] with it. That camera also draws any other 3D content in the
+/// world, so this is intended for screens that don't have a 3D scene.
+#[derive(Clone, Component, Reflect, FromReflect)]
+#[reflect(Component)]
+pub struct StarfieldUiBackground {
+    /// Direction the sky is viewed from.
+    ///
+    /// Defaults to looking at the horizon towards -Z.
+    pub view: Transform,
+}
+impl Default for StarfieldUiBackground {
+    fn default() -> Self {
+        Self {
+            view: Transform::IDENTITY,
+        }
+    }
+}
+
+/// Camera and texture rendering the sky for a [`StarfieldUiBackground`].
+#[derive(Component)]
+pub(crate) struct StarfieldUiCamera {
+    camera: Entity,
+    image: Handle<Image>,
+}
+
+pub(crate) fn update_ui_backgrounds(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut backgrounds: Query<(
+        Entity,
+        &StarfieldUiBackground,
+        &Node,
+        &mut UiImage,
+        Option<&StarfieldUiCamera>,
+    )>,
+    mut transforms: Query<&mut Transform>,
+    mut removed: RemovedComponents<StarfieldUiBackground>,
+) {
+    for entity in removed.iter() {
+        if let Some(mut entity_commands) = commands.get_entity(entity) {
+            entity_commands.remove::<StarfieldUiCamera>();
+        }
+    }
+
+    for (entity, background, node, mut ui_image, ui_camera) in backgrounds.iter_mut() {
+        let size = node.size().as_uvec2();
+        if size.x == 0 || size.y == 0 {
+            continue;
+        }
+
+        let Some(ui_camera) = ui_camera else {
+            let image = images.add(render_target_image(size.x, size.y));
+            let camera = commands
+                .spawn((
+                    Camera3dBundle {
+                        camera: Camera {
+                            target: RenderTarget::Image(image.clone()),
+                            order: -1,
+                            ..default()
+                        },
+                        camera_3d: Camera3d {
+                            clear_color: ClearColorConfig::Custom(Color::BLACK),
+                            ..default()
+                        },
+                        transform: background.view,
+                        ..default()
+                    },
+                    Name::new("Starfield UI Camera"),
+                ))
+                .id();
+            ui_image.texture = image.clone();
+            commands
+                .entity(entity)
+                .insert(StarfieldUiCamera { camera, image });
+            continue;
+        };
+
+        if ui_image.texture != ui_camera.image {
+            ui_image.texture = ui_camera.image.clone();
+        }
+        if let Ok(mut transform) = transforms.get_mut(ui_camera.camera) {
+            if *transform != background.view {
+                *transform = background.view;
+            }
+        }
+        // Only borrow the image mutably when it needs resizing, since doing so causes it to be
+        // uploaded to the GPU again.
+        let resize = images
+            .get(&ui_camera.image)
+            .is_some_and(|image| image.size().as_uvec2() != size);
+        if resize {
+            if let Some(image) = images.get_mut(&ui_camera.image) {
+                image.resize(Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                });
+            }
+        }
+    }
+}
+
+/// Despawn the camera of a background whose node was despawned or that had its
+/// [`StarfieldUiBackground`] removed.
+pub(crate) fn cleanup_ui_cameras(
+    mut commands: Commands,
+    added: Query<(Entity, &StarfieldUiCamera), Added<StarfieldUiCamera>>,
+    mut removed: RemovedComponents<StarfieldUiCamera>,
+    mut cameras: Local<HashMap<Entity, Entity>>,
+) {
+    for (entity, ui_camera) in added.iter() {
+        cameras.insert(entity, ui_camera.camera);
+    }
+    for entity in removed.iter() {
+        if let Some(camera) = cameras.remove(&entity) {
+            if let Some(camera) = commands.get_entity(camera) {
+                camera.despawn_recursive();
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Reconstructing the sky as seen from somewhere other than the solar system.
+
+use bevy::math::DVec3;
+
+use crate::Star;
+
+/// Absolute visual magnitude of the Sun.
+const SUN_ABSOLUTE_MAGNITUDE: f64 = 4.83;
+
+/// B-V color index of the Sun.
+const SUN_COLOR_INDEX: f32 = 0.65;
+
+/// A star along with its distance from the Sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CatalogStar {
+    /// Direction and apparent magnitude of the star as seen from the Sun.
+    pub star: Star,
+    /// Distance from the Sun in parsecs, usually derived from the star's parallax.
+    pub distance: f64,
+}
+
+/// Recompute the sky as it would appear from `position`.
+///
+/// `position` is given in parsecs relative to the Sun, with +Z towards the north celestial pole
+/// and +X towards the vernal equinox. Star directions are shifted to account for the new vantage
+/// point and magnitudes are adjusted by the change in distance, while colors are unchanged. The
+/// Sun itself is added as a magnitude 4.83 star at 10 parsecs with a color index of 0.65, and
+/// stars without a usable distance are dropped.
+///
+/// The bundled [`bright_star_catalog`](crate::bright_star_catalog) does not include distances, so
+/// this requires a catalog with parallaxes such as HYG or Gaia.
+pub fn sky_from_vantage_point(
+    stars: impl IntoIterator<Item = CatalogStar>,
+    position: DVec3,
+) -> Vec<Star> {
+    let mut output: Vec<Star> = stars
+        .into_iter()
+        .filter(|s| s.distance.is_finite() && s.distance > 0.0)
+        .filter_map(|s| {
+            let (dec, ra) = (s.star.declination as f64, s.star.right_ascension as f64);
+            let direction = DVec3::new(dec.cos() * ra.cos(), dec.cos() * ra.sin(), dec.sin());
+            relocate(
+                direction * s.distance - position,
+                s.star.magnitude as f64 - 5.0 * s.distance.log10(),
+                s.star.color_index,
+            )
+        })
+        .collect();
+    output.extend(relocate(
+        -position,
+        SUN_ABSOLUTE_MAGNITUDE - 5.0,
+        SUN_COLOR_INDEX,
+    ));
+    output
+}
+
+/// Build a star from its offset to the observer (in parsecs), its magnitude at 1 parsec and its
+/// color index.
+fn relocate(offset: DVec3, magnitude_at_one_parsec: f64, color_index: f32) -> Option<Star> {
+    let distance = offset.length();
+    if distance <= 0.0 || !distance.is_finite() {
+        return None;
+    }
+    let direction = offset / distance;
+    Some(
+        Star::new(
+            direction.z.clamp(-1.0, 1.0).asin() as f32,
+            direction
+                .y
+                .atan2(direction.x)
+                .rem_euclid(std::f64::consts::TAU) as f32,
+            (magnitude_at_one_parsec + 5.0 * distance.log10()) as f32,
+        )
+        .with_color_index(color_index),
+    )
+}